@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use scoped_threadpool::Pool;
+
+use crate::image::Image;
+use crate::image_reader::{detect_format, read_image_from_file, InputFormat, ReaderSettings};
+use crate::ppm_parser::read_netpbm_images_from_file;
+
+/// The result of encoding one image of a batch.
+///
+/// # Attributes
+///
+/// * `name`: The name of the encoded file in the output directory.
+/// * `result`: The size of the encoded file in bytes, or why the image couldn't be read or encoded.
+#[derive(Debug)]
+pub struct BatchEntry {
+    pub name: String,
+    pub result: Result<usize, String>,
+}
+
+/// The results of encoding a batch, see `encode_batch`.
+///
+/// # Attributes
+///
+/// * `entries`: The result of each image, in the order of the input files.
+/// * `skipped`: The files of the input directory which aren't images of a supported format.
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub entries: Vec<BatchEntry>,
+    pub skipped: Vec<String>,
+}
+
+impl BatchSummary {
+    /// The amount of images that couldn't be read or encoded.
+    pub fn failed_count(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.result.is_err()).count()
+    }
+
+    /// The size of all encoded files together in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.entries.iter().filter_map(|entry| entry.result.as_ref().ok()).sum()
+    }
+
+    /// Format the summary as a table of the encoded files and their sizes, followed by the totals.
+    pub fn table(&self) -> String {
+        let mut table = format!("{:<32}{:>12}\n", "File", "Bytes");
+        for entry in &self.entries {
+            table += &match &entry.result {
+                Ok(bytes) => format!("{:<32}{:>12}\n", entry.name, bytes),
+                Err(error) => format!("{:<32}  failed: {}\n", entry.name, error),
+            };
+        }
+        table += &format!(
+            "Encoded {} of {} images to {} bytes, {} failed.\n",
+            self.entries.len() - self.failed_count(),
+            self.entries.len(),
+            self.total_bytes(),
+            self.failed_count()
+        );
+        if !self.skipped.is_empty() {
+            table += &format!("Skipped {} files of unknown format: {}.\n", self.skipped.len(), self.skipped.join(", "));
+        }
+        table
+    }
+}
+
+/// What a thread of the batch encodes.
+enum BatchJob {
+    /// A file, read by the thread encoding it. Netpbm files may hold several images, which are encoded one after another.
+    /// Their images are counted beforehand, so the names of all images are known before encoding (see `output_names`).
+    File(PathBuf),
+    /// An image of a multi-image Netpbm file given as the input, which was read beforehand.
+    Image(Result<Image, String>, InputFormat),
+}
+
+/// Encode every image of a directory or of a Netpbm file holding several images,
+/// distributing the files (or the images of a single file) across a thread pool.
+/// The images of a directory are named after their files, e.g. `image.jpg` for `image.png`,
+/// the images of a multi-image file are numbered, e.g. `frames_00.jpg` to `frames_11.jpg`.
+/// Images which would get the same name keep the extension of their file, e.g. `frames_ppm_0.jpg` and `frames_0_png.jpg`
+/// for `frames.ppm` holding several images and `frames_0.png`.
+/// Files of a directory which aren't of a supported format are skipped, subdirectories are ignored.
+/// Images which can't be read or encoded are reported as failed instead of ending the batch.
+///
+/// # Arguments
+/// * `input`: The path to the directory or file.
+/// * `output_directory`: The directory to write the encoded files to, created if needed.
+/// * `settings`: The settings of the readers for video frames.
+/// * `thread_count`: The amount of threads to use. Images are encoded in parallel as long as there are enough of
+///   them, the remaining threads are shared out among them to encode each image in parallel as well.
+/// * `encode`: The function encoding an image of the given format to the given path with a thread pool,
+///   returning the size of the encoded file.
+///
+/// # Examples
+/// ```
+/// let summary = encode_batch("frames.ppm", "frames", &ReaderSettings::default(), 8, encode_image)?;
+/// print!("{}", summary.table());
+/// ```
+///
+/// # Errors
+/// * If the input can't be read or has an unknown format, or the output directory can't be created.
+pub fn encode_batch<F>(input: &str, output_directory: &str, settings: &ReaderSettings, thread_count: usize, encode: F) -> Result<BatchSummary, String>
+where
+    F: Fn(Image, InputFormat, &str, &mut Pool) -> usize + Sync,
+{
+    let metadata = fs::metadata(input).map_err(|error| format!("Error reading {}: {}!", input, error))?;
+    fs::create_dir_all(output_directory).map_err(|error| format!("Error creating {}: {}!", output_directory, error))?;
+
+    let mut summary = BatchSummary::default();
+    let jobs: Vec<(Vec<String>, BatchJob)> = if metadata.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(input)
+            .map_err(|error| format!("Error reading {}: {}!", input, error))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+        let mut formats = vec![];
+        let mut readable = vec![];
+        for path in files {
+            match detect_format(&path.to_string_lossy()) {
+                Ok(format) => {
+                    formats.push(format);
+                    readable.push(path);
+                }
+                Err(_) => summary.skipped.push(file_name(&path)),
+            }
+        }
+        let counts = count_images(&readable, &formats, thread_count);
+        output_names(&readable, &counts).into_iter().zip(readable.into_iter().map(BatchJob::File)).collect()
+    } else {
+        let format = detect_format(input)?;
+        if is_netpbm(format) {
+            let images = read_netpbm_images_from_file(input);
+            let stem = Path::new(input).file_stem().map_or(input.to_string(), |stem| stem.to_string_lossy().to_string());
+            let names = numbered_names(&stem, images.len());
+            names
+                .into_iter()
+                .zip(images.into_iter().map(|image| BatchJob::Image(image, format)))
+                .map(|(name, job)| (vec![name], job))
+                .collect()
+        } else {
+            let path = PathBuf::from(input);
+            vec![(output_names(std::slice::from_ref(&path), &[1]).remove(0), BatchJob::File(path))]
+        }
+    };
+
+    let batch_threads = thread_count.min(jobs.len()).max(1);
+    let image_threads = (thread_count / batch_threads).max(1);
+    let mut results: Vec<Vec<BatchEntry>> = (0..jobs.len()).map(|_| vec![]).collect();
+    let mut pool = Pool::new(batch_threads as u32);
+    pool.scoped(|scope| {
+        for ((names, job), entries) in jobs.into_iter().zip(results.iter_mut()) {
+            let encode = &encode;
+            scope.execute(move || {
+                *entries = encode_job(names, job, Path::new(output_directory), settings, image_threads, encode);
+            });
+        }
+    });
+
+    summary.entries = results.into_iter().flatten().collect();
+    Ok(summary)
+}
+
+/// Read the images of a job if needed and encode them one after another, named by `names` without the extension.
+fn encode_job<F>(names: Vec<String>, job: BatchJob, output_directory: &Path, settings: &ReaderSettings, thread_count: usize, encode: &F) -> Vec<BatchEntry>
+where
+    F: Fn(Image, InputFormat, &str, &mut Pool) -> usize,
+{
+    let (images, format) = match job {
+        BatchJob::Image(image, format) => (names.into_iter().zip(vec![image]).collect::<Vec<_>>(), format),
+        BatchJob::File(path) => {
+            let filename = path.to_string_lossy().to_string();
+            match read_file(&filename, settings) {
+                Ok((images, format)) => (names.into_iter().zip(images).collect(), format),
+                Err(error) => {
+                    return vec![BatchEntry {
+                        name: format!("{}.jpg", names[0]),
+                        result: Err(error),
+                    }]
+                }
+            }
+        }
+    };
+    images
+        .into_iter()
+        .map(|(name, image)| {
+            let name = format!("{}.jpg", name);
+            let path = output_directory.join(&name).to_string_lossy().to_string();
+            // the encoder panics on errors, so catch them to go on with the other images,
+            // with a new pool for every image, as a panic inside leaves a pool unusable
+            let result = image.and_then(|image| catch_panic(|| encode(image, format, &path, &mut Pool::new(thread_count as u32))));
+            BatchEntry { name, result }
+        })
+        .collect()
+}
+
+/// Read all images of a file, which is only more than one for multi-image Netpbm files.
+fn read_file(filename: &str, settings: &ReaderSettings) -> Result<(Vec<Result<Image, String>>, InputFormat), String> {
+    let format = detect_format(filename)?;
+    if is_netpbm(format) {
+        Ok((read_netpbm_images_from_file(filename), format))
+    } else {
+        let (image, format) = read_image_from_file(filename, settings)?;
+        Ok((vec![Ok(image)], format))
+    }
+}
+
+/// Run the function, turning a panic into an error with its message.
+fn catch_panic<T>(function: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(function)).map_err(|payload| match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("Unknown error!".to_string(), |message| message.to_string()),
+    })
+}
+
+/// Whether files of the format may hold several images.
+fn is_netpbm(format: InputFormat) -> bool {
+    matches!(format, InputFormat::Pnm | InputFormat::PlainPpm | InputFormat::Pam)
+}
+
+/// Count the images of each file in parallel, which is only more than one for multi-image Netpbm files.
+/// The images are read and dropped again, so they aren't all held in memory until they are encoded.
+fn count_images(files: &[PathBuf], formats: &[InputFormat], thread_count: usize) -> Vec<usize> {
+    let mut counts = vec![1; files.len()];
+    let mut pool = Pool::new(thread_count.max(1) as u32);
+    pool.scoped(|scope| {
+        for ((path, format), count) in files.iter().zip(formats).zip(counts.iter_mut()) {
+            if is_netpbm(*format) {
+                scope.execute(move || *count = read_netpbm_images_from_file(&path.to_string_lossy()).len().max(1));
+            }
+        }
+    });
+    counts
+}
+
+/// The names of the encoded images of the input files, without the extension. A file holding one image is named
+/// after the file without its extension, the images of a file holding several are numbered (see `numbered_names`).
+/// Files with names that would be the same as those of another file keep their extension, e.g. `image_png` and
+/// `image_ppm`, or `frames_ppm_0` and `frames_0_png` for `frames.ppm` holding several images and `frames_0.png`,
+/// so they don't overwrite each other.
+///
+/// # Arguments
+/// * `files`: The paths to the files.
+/// * `counts`: The amount of images of each file.
+fn output_names(files: &[PathBuf], counts: &[usize]) -> Vec<Vec<String>> {
+    let names_of = |stem: &str, count: usize| if count > 1 { numbered_names(stem, count) } else { vec![stem.to_string()] };
+    let names: Vec<Vec<String>> = files
+        .iter()
+        .zip(counts)
+        .map(|(path, &count)| names_of(&path.file_stem().map_or(file_name(path), |stem| stem.to_string_lossy().to_string()), count))
+        .collect();
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    for name in names.iter().flatten() {
+        *occurrences.entry(name).or_default() += 1;
+    }
+    names
+        .iter()
+        .zip(files.iter().zip(counts))
+        .map(|(file_names, (path, &count))| {
+            if file_names.iter().any(|name| occurrences[name.as_str()] > 1) {
+                names_of(&file_name(path).replace('.', "_"), count)
+            } else {
+                file_names.clone()
+            }
+        })
+        .collect()
+}
+
+/// Number the names of the images of a file, padded with zeros so they sort in order.
+fn numbered_names(stem: &str, count: usize) -> Vec<String> {
+    let width = count.saturating_sub(1).to_string().len();
+    (0..count).map(|index| format!("{}_{:0width$}", stem, index, width = width)).collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or(path.to_string_lossy().to_string(), |name| name.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::image_reader::{InputFormat, ReaderSettings};
+
+    use super::{encode_batch, numbered_names, output_names};
+
+    /// A temporary directory with copies of the test files, removed again when dropped.
+    struct TestDirectory(PathBuf);
+
+    impl TestDirectory {
+        fn new(name: &str, files: &[&str]) -> TestDirectory {
+            let path = std::env::temp_dir().join(format!("jpeg_encoder_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            for file in files {
+                fs::copy(format!("test/{}", file), path.join(file)).unwrap();
+            }
+            TestDirectory(path)
+        }
+
+        fn path(&self) -> String {
+            self.0.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_encode_batch_directory() {
+        let input = TestDirectory::new("batch_input", &["valid_test_8x8.ppm", "valid_test_binary.ppm", "valid_test_24bit_v4.bmp", "zlib_dynamic_block.bin", "valid_test_multi.ppm"]);
+        let output = TestDirectory::new("batch_output", &[]);
+        let summary = encode_batch(&input.path(), &output.path(), &ReaderSettings::default(), 4, |image, format, path, _| {
+            assert!(path.starts_with(&output.path()));
+            if format == InputFormat::Bmp {
+                panic!("BMP fails");
+            }
+            image.width() as usize * image.height() as usize
+        })
+        .unwrap();
+
+        let names: Vec<&str> = summary.entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["valid_test_24bit_v4.jpg", "valid_test_8x8.jpg", "valid_test_binary.jpg", "valid_test_multi_0.jpg", "valid_test_multi_1.jpg", "valid_test_multi_2.jpg"], names);
+        assert_eq!(Err("BMP fails".to_string()), summary.entries[0].result);
+        assert_eq!(Ok(64), summary.entries[1].result);
+        assert_eq!(1, summary.failed_count());
+        assert_eq!(64 + 16 + 16 + 16 + 64, summary.total_bytes());
+        assert_eq!(vec!["zlib_dynamic_block.bin"], summary.skipped);
+        assert!(summary.table().contains("Encoded 5 of 6 images to 176 bytes, 1 failed.\n"));
+    }
+
+    #[test]
+    fn test_encode_batch_unreadable_file() {
+        let input = TestDirectory::new("batch_broken_input", &["valid_test_8x8.ppm"]);
+        fs::write(input.0.join("broken.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let output = TestDirectory::new("batch_broken_output", &[]);
+        let summary = encode_batch(&input.path(), &output.path(), &ReaderSettings::default(), 2, |_, _, _, _| 1).unwrap();
+
+        assert_eq!("broken.jpg", summary.entries[0].name);
+        assert_eq!(Err("PNG file ends unexpectedly!".to_string()), summary.entries[0].result);
+        assert_eq!(Ok(1), summary.entries[1].result);
+    }
+
+    #[test]
+    fn test_encode_batch_numbered_names_of_other_files() {
+        let input = TestDirectory::new("batch_collision_input", &[]);
+        fs::copy("test/valid_test_multi.ppm", input.0.join("frames.ppm")).unwrap();
+        fs::copy("test/valid_test_rgb_8bit_filters.png", input.0.join("frames_0.png")).unwrap();
+        let output = TestDirectory::new("batch_collision_output", &[]);
+        let summary = encode_batch(&input.path(), &output.path(), &ReaderSettings::default(), 4, |image, _, path, _| {
+            fs::write(path, []).unwrap();
+            image.width() as usize
+        })
+        .unwrap();
+
+        let results: Vec<_> = summary.entries.iter().map(|entry| (entry.name.as_str(), entry.result.clone())).collect();
+        assert_eq!(vec![("frames_ppm_0.jpg", Ok(4)), ("frames_ppm_1.jpg", Ok(4)), ("frames_ppm_2.jpg", Ok(8)), ("frames_0_png.jpg", Ok(13))], results);
+        assert_eq!(4, fs::read_dir(&output.0).unwrap().count());
+    }
+
+    #[test]
+    fn test_encode_batch_multi_image_file() {
+        let output = TestDirectory::new("batch_multi_output", &[]);
+        let summary = encode_batch("test/valid_test_multi.ppm", &output.path(), &ReaderSettings::default(), 2, |image, _, _, _| image.width() as usize).unwrap();
+        let results: Vec<_> = summary.entries.iter().map(|entry| (entry.name.as_str(), entry.result.clone())).collect();
+        assert_eq!(vec![("valid_test_multi_0.jpg", Ok(4)), ("valid_test_multi_1.jpg", Ok(4)), ("valid_test_multi_2.jpg", Ok(8))], results);
+        assert!(encode_batch("test/missing", &output.path(), &ReaderSettings::default(), 2, |_, _, _, _| 0).is_err());
+    }
+
+    #[test]
+    fn test_output_names() {
+        let files = ["a/image.png", "a/image.ppm", "a/photo.jpg", "a/frames"].map(PathBuf::from);
+        let names: Vec<Vec<String>> = ["image_png", "image_ppm", "photo", "frames"].iter().map(|name| vec![name.to_string()]).collect();
+        assert_eq!(names, output_names(&files, &[1; 4]));
+
+        let files = ["a/frames.ppm", "a/frames_0.png", "a/frames_2.bmp", "a/other.ppm"].map(PathBuf::from);
+        let names = output_names(&files, &[3, 1, 1, 2]);
+        assert_eq!(vec!["frames_ppm_0", "frames_ppm_1", "frames_ppm_2"], names[0]);
+        assert_eq!(vec!["frames_0_png"], names[1]);
+        assert_eq!(vec!["frames_2_bmp"], names[2]);
+        assert_eq!(vec!["other_0", "other_1"], names[3]);
+    }
+
+    #[test]
+    fn test_numbered_names() {
+        assert_eq!(vec!["frames_0", "frames_1"], numbered_names("frames", 2));
+        assert_eq!("frames_09", numbered_names("frames", 11)[9]);
+        assert_eq!(vec!["frames_0"], numbered_names("frames", 1));
+    }
+}
@@ -1,4 +1,5 @@
 use std::fs;
+use std::io::Write;
 
 use crate::{appendable_to_bit_stream::AppendableToBitStream, utils::get_n_bits_at_offset};
 
@@ -243,6 +244,26 @@ impl BitStream {
         fs::write(filename, &self.data).expect("Error when writing to file.")
     }
 
+    /// Flush the bit stream to a writer, e.g. stdout.
+    ///
+    /// # Arguments
+    ///
+    /// * writer: The writer to write to.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut stream = BitStream.open();
+    /// stream.append_byte(0xff);
+    /// stream.flush_to_writer(&mut std::io::stdout().lock());
+    /// ```
+    pub fn flush_to_writer<W: Write>(&mut self, writer: &mut W) {
+        writer
+            .write_all(&self.data)
+            .and_then(|_| writer.flush())
+            .expect("Error when writing to stream.")
+    }
+
     fn clear_first_bits(&mut self) {
         let last_byte_index = self.data.len() - 1;
         let last_byte = self.data[last_byte_index];
@@ -576,6 +597,19 @@ mod tests {
         fs::remove_file(filename)
     }
 
+    #[test]
+    fn test_flush_to_writer() {
+        let mut stream = BitStream {
+            data: vec![0b10101010, 0b01010101],
+            bits_in_last_byte: 0,
+            bits_read_from_first_byte: 0,
+            byte_stuffing: false,
+        };
+        let mut written: Vec<u8> = vec![];
+        stream.flush_to_writer(&mut written);
+        assert_eq!(vec![0b10101010, 0b01010101], written);
+    }
+
     #[test]
     fn test_append_bits() {
         let mut stream = BitStream::open();
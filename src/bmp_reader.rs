@@ -0,0 +1,274 @@
+use std::fs;
+
+use crate::image::{create_image, Image};
+
+/// The compression of uncompressed pixels with a fixed layout.
+const BI_RGB: u32 = 0;
+/// The compression of uncompressed pixels whose channels are described by bit masks.
+const BI_BITFIELDS: u32 = 3;
+
+/// The sizes of the BITMAPINFOHEADER and its successors V2 to V5 (the undocumented V2 and V3 only add the masks).
+const SUPPORTED_HEADER_SIZES: [usize; 5] = [40, 52, 56, 108, 124];
+
+/// A channel of 16 or 32 bit pixels, as described by a bit mask.
+///
+/// # Attributes
+///
+/// * `mask`: The bits of the pixel holding the channel.
+/// * `shift`: The position of the mask's lowest bit.
+/// * `max_value`: The largest value the channel can hold, which is scaled to 255.
+#[derive(Clone, Copy, Debug)]
+struct BitField {
+    mask: u32,
+    shift: u32,
+    max_value: u32,
+}
+
+impl BitField {
+    /// Create the channel of the mask.
+    ///
+    /// # Errors
+    /// * If the mask is 0.
+    fn new(mask: u32) -> Result<BitField, String> {
+        if mask == 0 {
+            return Err("Invalid BMP bit mask 0!".to_string());
+        }
+        let shift = mask.trailing_zeros();
+        Ok(BitField {
+            mask,
+            shift,
+            max_value: mask >> shift,
+        })
+    }
+
+    /// Extract the channel from a pixel and scale it to 0..=255.
+    /// The product is calculated in u64, as masks may be wider than 24 bits.
+    fn extract(&self, pixel: u32) -> i16 {
+        (((pixel & self.mask) >> self.shift) as u64 * u8::MAX as u64 / self.max_value as u64) as i16
+    }
+}
+
+/// Read a BMP file and decode it into an RGB image.
+///
+/// # Arguments
+/// * `filename`: The path to the file to read.
+///
+/// # Examples
+/// ```
+/// let image = read_bmp_from_file("image.bmp");
+/// ```
+///
+/// # Panics
+/// * If the file can't be read.
+/// * If the file can't be decoded, see `decode_bmp`.
+pub fn read_bmp_from_file(filename: &str) -> Image {
+    let data = fs::read(filename).expect("Error reading BMP file!");
+    match decode_bmp(&data) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Decode a BMP file with a BITMAPINFOHEADER, BITMAPV4HEADER or BITMAPV5HEADER into an RGB image.
+/// Supported are 8 bit paletted pixels, 24 bit pixels and 16 or 32 bit pixels stored as BI_RGB
+/// (5 bits per channel or 8 bits per channel, respectively) or BI_BITFIELDS, in bottom-up or top-down order.
+/// An alpha channel of 32 bit pixels is ignored, as BMP writers rarely fill it in.
+///
+/// # Arguments
+/// * `data`: The content of the BMP file.
+///
+/// # Errors
+/// * If the data isn't a BMP file or ends before all pixels are read.
+/// * If the header is of another type (e.g. the OS/2 BITMAPCOREHEADER) or the image is larger than
+///   65535 pixels in either direction.
+/// * If the pixels are compressed (e.g. BI_RLE8, BI_JPEG) or have an unsupported amount of bits.
+/// * If a bit mask is 0 or a palette index is out of range.
+pub fn decode_bmp(data: &[u8]) -> Result<Image, String> {
+    if data.len() < 18 || !data.starts_with(b"BM") {
+        return Err("Not a BMP file!".to_string());
+    }
+    let pixel_offset = read_u32(data, 10) as usize;
+    let header_size = read_u32(data, 14) as usize;
+    if !SUPPORTED_HEADER_SIZES.contains(&header_size) {
+        return Err(format!("Unsupported BMP header of {} bytes!", header_size));
+    }
+    if data.len() < 14 + header_size {
+        return Err("BMP file ends unexpectedly!".to_string());
+    }
+    let width = read_u32(data, 18) as i32;
+    let height = read_u32(data, 22) as i32;
+    let bits_per_pixel = read_u16(data, 28);
+    let compression = read_u32(data, 30);
+    let colors_used = read_u32(data, 46) as usize;
+
+    // a negative height marks rows stored from top to bottom
+    let top_down = height < 0;
+    let (width, height) = (width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("Unsupported BMP size {}x{}!", width, height));
+    }
+
+    let bit_fields = match (compression, bits_per_pixel) {
+        (BI_RGB, 8 | 24) => None,
+        (BI_RGB, 16) => Some(bit_fields([0x7c00, 0x03e0, 0x001f])?),
+        (BI_RGB, 32) => Some(bit_fields([0x00ff_0000, 0x0000_ff00, 0x0000_00ff])?),
+        (BI_BITFIELDS, 16 | 32) => {
+            // V2 and later headers hold the masks, the BITMAPINFOHEADER is followed by them
+            let masks = data.get(54..66).ok_or("BMP file ends unexpectedly!")?;
+            Some(bit_fields([0, 4, 8].map(|offset| read_u32(masks, offset)))?)
+        }
+        _ => {
+            return Err(format!(
+                "Unsupported BMP format: compression {} with {} bits per pixel!",
+                compression, bits_per_pixel
+            ))
+        }
+    };
+
+    let palette: Vec<(i16, i16, i16)> = if bits_per_pixel == 8 {
+        let palette_offset = 14 + header_size + if compression == BI_BITFIELDS && header_size == 40 { 12 } else { 0 };
+        let count = if colors_used == 0 { 256 } else { colors_used.min(256) };
+        (0..count)
+            .map_while(|index| data.get(palette_offset + 4 * index..palette_offset + 4 * index + 3))
+            .map(|entry| (entry[2] as i16, entry[1] as i16, entry[0] as i16))
+            .collect()
+    } else {
+        vec![]
+    };
+
+    // rows are padded to multiples of 4 bytes
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let row_length = (width * bytes_per_pixel).div_ceil(4) * 4;
+    if data.len() < pixel_offset + row_length * height {
+        return Err("BMP file ends unexpectedly!".to_string());
+    }
+
+    let mut red = vec![vec![0i16; width]; height];
+    let mut green = vec![vec![0i16; width]; height];
+    let mut blue = vec![vec![0i16; width]; height];
+    for y in 0..height {
+        let stored_row = if top_down { y } else { height - 1 - y };
+        let row = &data[pixel_offset + stored_row * row_length..][..row_length];
+        for x in 0..width {
+            let pixel = &row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel];
+            let (r, g, b) = match (bits_per_pixel, &bit_fields) {
+                (8, _) => *palette.get(pixel[0] as usize).ok_or("BMP palette index out of range!")?,
+                (24, _) => (pixel[2] as i16, pixel[1] as i16, pixel[0] as i16),
+                (_, Some([r, g, b])) => {
+                    let value = match bits_per_pixel {
+                        16 => read_u16(pixel, 0) as u32,
+                        _ => read_u32(pixel, 0),
+                    };
+                    (r.extract(value), g.extract(value), b.extract(value))
+                }
+                _ => unreachable!(),
+            };
+            red[y][x] = r;
+            green[y][x] = g;
+            blue[y][x] = b;
+        }
+    }
+    Ok(create_image(height as u16, width as u16, red, green, blue))
+}
+
+/// Create the red, green and blue channel of the masks, see `BitField::new`.
+fn bit_fields(masks: [u32; 3]) -> Result<[BitField; 3], String> {
+    Ok([BitField::new(masks[0])?, BitField::new(masks[1])?, BitField::new(masks[2])?])
+}
+
+/// Read a little endian u16 at the position.
+fn read_u16(data: &[u8], position: usize) -> u16 {
+    u16::from_le_bytes([data[position], data[position + 1]])
+}
+
+/// Read a little endian u32 at the position.
+fn read_u32(data: &[u8], position: usize) -> u32 {
+    u32::from_le_bytes([data[position], data[position + 1], data[position + 2], data[position + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_bmp, read_bmp_from_file, BitField};
+
+    fn rgb(x: usize, y: usize) -> (i16, i16, i16) {
+        (((x * 37 + y * 11) % 256) as i16, ((x * 5 + y * 53) % 256) as i16, ((x * x + 3 * y) % 256) as i16)
+    }
+
+    fn assert_pixels(filename: &str, width: u16, height: u16, expected: impl Fn(usize, usize) -> (i16, i16, i16)) {
+        let image = read_bmp_from_file(filename);
+        assert_eq!((width, height), (image.width(), image.height()));
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(expected(x as usize, y as usize), image.pixel_at(x, y), "pixel {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_8_bit_palette() {
+        assert_pixels("test/valid_test_8bit_palette.bmp", 5, 3, |x, y| {
+            let index = (x + 2 * y) % 16;
+            (((index * 16) % 256) as i16, (255 - index * 16) as i16, ((index * 40) % 256) as i16)
+        });
+    }
+
+    #[test]
+    fn test_decode_24_bit_v4_header() {
+        assert_pixels("test/valid_test_24bit_v4.bmp", 7, 4, rgb);
+    }
+
+    #[test]
+    fn test_decode_32_bit_top_down() {
+        assert_pixels("test/valid_test_32bit_top_down.bmp", 3, 2, rgb);
+    }
+
+    #[test]
+    fn test_decode_32_bit_bitfields_v5_header() {
+        // the masks store the channels in RGBA instead of BGRA order
+        assert_pixels("test/valid_test_32bit_bitfields_v5.bmp", 4, 3, rgb);
+    }
+
+    #[test]
+    fn test_decode_16_bit_bitfields() {
+        // 5 bits red, 6 bits green, 5 bits blue
+        assert_pixels("test/valid_test_16bit_bitfields.bmp", 3, 2, |x, y| {
+            let (r, g, b) = rgb(x, y);
+            ((r >> 3) * 255 / 31, (g >> 2) * 255 / 63, (b >> 3) * 255 / 31)
+        });
+    }
+
+    #[test]
+    fn test_bit_field_wide_mask() {
+        let field = BitField::new(0xffff_ffff).unwrap();
+        assert_eq!(255, field.extract(0xffff_ffff));
+        assert_eq!(127, field.extract(0x8000_0000));
+        assert_eq!(0, field.extract(0));
+    }
+
+    #[test]
+    fn test_decode_rle_compressed() {
+        let mut data = std::fs::read("test/valid_test_8bit_palette.bmp").unwrap();
+        data[30] = 1;
+        assert_eq!("Unsupported BMP format: compression 1 with 8 bits per pixel!", decode_bmp(&data).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_core_header() {
+        let mut data = std::fs::read("test/valid_test_24bit_v4.bmp").unwrap();
+        data[14] = 12;
+        assert_eq!("Unsupported BMP header of 12 bytes!", decode_bmp(&data).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let data = std::fs::read("test/valid_test_24bit_v4.bmp").unwrap();
+        assert_eq!("BMP file ends unexpectedly!", decode_bmp(&data[..data.len() - 1]).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_zero_bit_mask() {
+        let mut data = std::fs::read("test/valid_test_16bit_bitfields.bmp").unwrap();
+        data[54..58].fill(0);
+        assert_eq!("Invalid BMP bit mask 0!", decode_bmp(&data).unwrap_err());
+    }
+}
@@ -0,0 +1,611 @@
+use crate::exif::{ByteOrder, Exif};
+use crate::image::{AlphaPolicy, ColorMatrix, ColorSpace};
+use crate::jpg_writer::Density;
+use crate::quality_control::QualityTarget;
+use crate::rate_control::RateTarget;
+use crate::resize::ResizeFilter;
+use crate::thumbnail::{ThumbnailFormat, DEFAULT_THUMBNAIL_SIZE};
+use crate::yuv_reader::{ColorRange, FrameSize};
+
+/// The path meaning stdin as input and stdout as output.
+pub const STDIO_PATH: &str = "-";
+
+/// The path of the encoded file if none is given.
+pub const DEFAULT_OUTPUT: &str = "output.jpg";
+
+/// The directory to write the encoded files of `batch` to if none is given.
+pub const DEFAULT_BATCH_OUTPUT: &str = "output";
+
+/// What the program should do with the input image.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Encode the image and write it to a file.
+    Encode,
+    /// Encode the image, decode the result again and print quality metrics comparing it with the input.
+    Compare,
+    /// Encode every image of a directory or a multi-image Netpbm file and write them to a directory.
+    Batch,
+}
+
+/// The parsed command line arguments.
+///
+/// # Attributes
+///
+/// * `command`: What to do with the input image.
+/// * `input`: Path to the image file to encode, `-` for stdin, or the directory or file of images for `batch`.
+/// * `output`: Path to write the encoded file to, `-` for stdout, or the directory to write to for `batch`.
+/// * `decoded_output`: Path to write the decoded image to with `compare`, if any.
+/// * `rate_target`: The size the encoded file should fit into, if any.
+/// * `quality_target`: The quality the encoded file should at least have, if any.
+/// * `rdo_lambda`: The lambda for rate-distortion optimized quantization, if enabled.
+/// * `masking_strength`: The strength of activity masking, if enabled.
+/// * `color_matrix`: The matrix to convert from RGB to YCbCr with.
+/// * `color_space`: The colour space to encode in.
+/// * `alpha`: What to do with the alpha channel of RGBA images.
+/// * `icc_profile`: Path to an ICC profile to embed, if any.
+/// * `exif`: The Exif metadata to embed, if any `--exif-*` option is given.
+/// * `xmp`: Path to an XMP packet to embed, if any.
+/// * `comments`: The comments to write instead of the default one, if any.
+/// * `density`: The pixel density to store in the JFIF header.
+/// * `thumbnail`: The format of the thumbnail to embed in the JFIF header, if any.
+/// * `thumbnail_size`: The maximum length of the thumbnail's longer side.
+/// * `frame`: The index of the frame to read from Y4M and raw YUV files.
+/// * `yuv_size`: The width and height of the frames of raw YUV files, if given.
+/// * `yuv_range`: The range of the samples of raw YUV files.
+/// * `resize_widths`: The widths to resize the image to before encoding, one encoded file each. Empty to keep the size.
+/// * `resize_filter`: The filter to resize with.
+pub struct Arguments {
+    pub command: Command,
+    pub input: String,
+    pub output: String,
+    pub decoded_output: Option<String>,
+    pub rate_target: Option<RateTarget>,
+    pub quality_target: Option<QualityTarget>,
+    pub rdo_lambda: Option<f32>,
+    pub masking_strength: Option<f32>,
+    pub color_matrix: ColorMatrix,
+    pub color_space: ColorSpace,
+    pub alpha: AlphaPolicy,
+    pub icc_profile: Option<String>,
+    pub exif: Option<Exif>,
+    pub xmp: Option<String>,
+    pub comments: Option<Vec<String>>,
+    pub density: Density,
+    pub thumbnail: Option<ThumbnailFormat>,
+    pub thumbnail_size: u16,
+    pub frame: usize,
+    pub yuv_size: Option<FrameSize>,
+    pub yuv_range: ColorRange,
+    pub resize_widths: Vec<u16>,
+    pub resize_filter: ResizeFilter,
+}
+
+/// Parse the command line arguments (without the program name).
+///
+/// Supported arguments:
+/// * `compare`: As the first argument, compare the encoded and decoded image with the input
+///   instead of writing it to a file.
+/// * `batch`: As the first argument, encode every image of the input directory or multi-image Netpbm file
+///   into the output directory, `output` by default.
+/// * `<input>`: The image file to encode, `-` to read it from stdin.
+/// * `-o <path>` or `--output <path>`: Where to write the encoded file, `-` for stdout, `output.jpg` by default.
+/// * `--decoded <path>`: With `compare`, also write the decoded image to this PPM file.
+/// * `--target-bytes <bytes>`: Encode with the highest quality that fits into this many bytes.
+/// * `--target-bpp <bits>`: Encode with the highest quality that fits into this many bits per pixel.
+/// * `--target-psnr <dB>`: Encode with the lowest quality that reaches at least this PSNR.
+/// * `--target-ssim <value>`: Encode with the lowest quality that reaches at least this SSIM.
+/// * `--rdo-lambda <lambda>`: Quantize with rate-distortion optimization, weighing one bit with lambda.
+/// * `--aq-strength <strength>`: Apply activity masking, widening the deadzone of busy blocks.
+/// * `--color-matrix <bt601|bt709|bt2020>`: The matrix to convert from RGB to YCbCr with, BT.601 by default.
+/// * `--color-space <ycbcr|cmyk|ycck>`: The colour space to encode in, YCbCr by default.
+/// * `--alpha <composite[:RRGGBB]|premultiply|sidecar>`: What to do with the alpha channel of RGBA images,
+///   compositing over white by default.
+/// * `--icc-profile <path>`: Embed the ICC profile from this file.
+/// * `--exif-date <YYYY:MM:DD HH:MM:SS>`: Embed this capture time as Exif metadata.
+/// * `--exif-orientation <1-8>`: Embed this orientation as Exif metadata.
+/// * `--exif-make <make>` and `--exif-model <model>`: Embed the camera as Exif metadata.
+/// * `--exif-copyright <notice>`: Embed this copyright notice as Exif metadata.
+/// * `--exif-gps <latitude,longitude[,altitude]>`: Embed this position as Exif metadata.
+/// * `--exif-little-endian`: Write the Exif metadata in little instead of big endian byte order.
+/// * `--xmp <path>`: Embed the XMP packet from this file (e.g. an .xmp sidecar).
+/// * `--comment <text>`: Write this comment instead of the default one. Can be given several times.
+/// * `--density <x[xy](dpi|dpcm)|x:y>`: Store this pixel density or pixel aspect ratio in the JFIF header, e.g. `300dpi`.
+/// * `--thumbnail <rgb|jpeg>`: Embed a thumbnail of the image in the JFIF header.
+/// * `--thumbnail-size <pixels>`: The maximum length of the thumbnail's longer side, 160 by default.
+/// * `--frame <index>`: The frame to read from Y4M and raw YUV files, starting at 0 (the default).
+/// * `--yuv-size <width>x<height>`: The size of the frames of raw YUV files, which is required for them.
+/// * `--yuv-range <limited|full>`: The range of the samples of raw YUV files, limited by default.
+/// * `--resize <width>[,<width>...]`: Resize the image to these widths, keeping the aspect ratio, and encode each.
+///   With several widths, the width is added to the name of the output, e.g. `output_640.jpg`.
+/// * `--resize-filter <box|bilinear|bicubic|lanczos3>`: The filter to resize with, Lanczos-3 by default.
+///
+/// # Examples
+/// ```
+/// let args: Vec<String> = std::env::args().skip(1).collect();
+/// let arguments = parse_arguments(&args);
+/// ```
+///
+/// # Panics
+/// * If no input file is specified.
+/// * If an option is missing its value or the value can't be parsed.
+/// * If an unknown option is given.
+/// * If both a rate and a quality target are given.
+/// * If CMYK or YCCK is combined with a target or `compare`, which only support YCbCr.
+/// * If `--decoded` is given without `compare`.
+/// * If `batch` is combined with stdin or stdout.
+/// * If several resize widths are combined with stdout.
+pub fn parse_arguments(args: &[String]) -> Arguments {
+    let mut input: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut decoded_output: Option<String> = None;
+    let mut rate_target: Option<RateTarget> = None;
+    let mut quality_target: Option<QualityTarget> = None;
+    let mut rdo_lambda: Option<f32> = None;
+    let mut masking_strength: Option<f32> = None;
+    let mut color_matrix = ColorMatrix::default();
+    let mut color_space = ColorSpace::default();
+    let mut alpha = AlphaPolicy::default();
+    let mut icc_profile: Option<String> = None;
+    let mut exif: Option<Exif> = None;
+    let mut xmp: Option<String> = None;
+    let mut comments: Option<Vec<String>> = None;
+    let mut density = Density::default();
+    let mut thumbnail: Option<ThumbnailFormat> = None;
+    let mut thumbnail_size = DEFAULT_THUMBNAIL_SIZE;
+    let mut frame = 0;
+    let mut yuv_size: Option<FrameSize> = None;
+    let mut yuv_range = ColorRange::default();
+    let mut resize_widths: Vec<u16> = vec![];
+    let mut resize_filter = ResizeFilter::default();
+
+    let (command, args) = match args.first().map(String::as_str) {
+        Some("compare") => (Command::Compare, &args[1..]),
+        Some("batch") => (Command::Batch, &args[1..]),
+        _ => (Command::Encode, args),
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(parse_value(arg, iter.next()));
+            }
+            "--decoded" => {
+                decoded_output = Some(parse_value(arg, iter.next()));
+            }
+            "--target-bytes" => {
+                rate_target = Some(RateTarget::Bytes(parse_value(arg, iter.next())));
+            }
+            "--target-bpp" => {
+                rate_target = Some(RateTarget::BitsPerPixel(parse_value(arg, iter.next())));
+            }
+            "--target-psnr" => {
+                quality_target = Some(QualityTarget::Psnr(parse_value(arg, iter.next())));
+            }
+            "--target-ssim" => {
+                quality_target = Some(QualityTarget::Ssim(parse_value(arg, iter.next())));
+            }
+            "--rdo-lambda" => {
+                rdo_lambda = Some(parse_value(arg, iter.next()));
+            }
+            "--aq-strength" => {
+                masking_strength = Some(parse_value(arg, iter.next()));
+            }
+            "--color-matrix" => {
+                color_matrix = parse_value(arg, iter.next());
+            }
+            "--color-space" => {
+                color_space = parse_value(arg, iter.next());
+            }
+            "--alpha" => {
+                alpha = parse_value(arg, iter.next());
+            }
+            "--icc-profile" => {
+                icc_profile = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-date" => {
+                exif.get_or_insert_with(Exif::default).date_time = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-orientation" => {
+                exif.get_or_insert_with(Exif::default).orientation = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-make" => {
+                exif.get_or_insert_with(Exif::default).make = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-model" => {
+                exif.get_or_insert_with(Exif::default).model = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-copyright" => {
+                exif.get_or_insert_with(Exif::default).copyright = Some(parse_value(arg, iter.next()));
+            }
+            "--exif-gps" => {
+                exif.get_or_insert_with(Exif::default).gps = Some(parse_value(arg, iter.next()));
+            }
+            "--xmp" => {
+                xmp = Some(parse_value(arg, iter.next()));
+            }
+            "--comment" => {
+                comments.get_or_insert_with(Vec::new).push(parse_value(arg, iter.next()));
+            }
+            "--density" => {
+                density = parse_value(arg, iter.next());
+            }
+            "--thumbnail" => {
+                thumbnail = Some(parse_value(arg, iter.next()));
+            }
+            "--thumbnail-size" => {
+                thumbnail_size = parse_value(arg, iter.next());
+            }
+            "--resize" => {
+                let widths: String = parse_value(arg, iter.next());
+                resize_widths = widths
+                    .split(',')
+                    .map(|width| match width.trim().parse::<u16>() {
+                        Ok(width) if width > 0 => width,
+                        _ => panic!("Invalid value for {}!", arg),
+                    })
+                    .collect();
+            }
+            "--resize-filter" => {
+                resize_filter = parse_value(arg, iter.next());
+            }
+            "--frame" => {
+                frame = parse_value(arg, iter.next());
+            }
+            "--yuv-size" => {
+                yuv_size = Some(parse_value(arg, iter.next()));
+            }
+            "--yuv-range" => {
+                yuv_range = parse_value(arg, iter.next());
+            }
+            "--exif-little-endian" => {
+                exif.get_or_insert_with(Exif::default).byte_order = ByteOrder::LittleEndian;
+            }
+            _ if arg.starts_with('-') && arg != STDIO_PATH => panic!("Unknown option {}!", arg),
+            _ => input = Some(arg.clone()),
+        }
+    }
+
+    if rate_target.is_some() && quality_target.is_some() {
+        panic!("A rate target and a quality target can't be combined!");
+    }
+    let targets_or_compare = rate_target.is_some() || quality_target.is_some() || command == Command::Compare;
+    if color_space != ColorSpace::YCbCr && targets_or_compare {
+        panic!("Targets and compare only support the YCbCr colour space!");
+    }
+    if decoded_output.is_some() && command != Command::Compare {
+        panic!("The decoded image can only be written with compare!");
+    }
+    let input = input.expect("No file name specified!");
+    let output = output.unwrap_or(match command {
+        Command::Batch => DEFAULT_BATCH_OUTPUT.to_string(),
+        _ => DEFAULT_OUTPUT.to_string(),
+    });
+    if command == Command::Batch && (input == STDIO_PATH || output == STDIO_PATH) {
+        panic!("Batch mode can't read from stdin or write to stdout!");
+    }
+    if resize_widths.len() > 1 && output == STDIO_PATH {
+        panic!("Several sizes can't be written to stdout!");
+    }
+
+    Arguments {
+        command,
+        input,
+        output,
+        decoded_output,
+        rate_target,
+        quality_target,
+        rdo_lambda,
+        masking_strength,
+        color_matrix,
+        color_space,
+        alpha,
+        icc_profile,
+        exif,
+        xmp,
+        comments,
+        density,
+        thumbnail,
+        thumbnail_size,
+        frame,
+        yuv_size,
+        yuv_range,
+        resize_widths,
+        resize_filter,
+    }
+}
+
+/// Parse the value following an option.
+///
+/// # Panics
+/// * If the value is missing or can't be parsed.
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<&String>) -> T {
+    match value.map(|value| value.parse::<T>()) {
+        Some(Ok(value)) => value,
+        Some(Err(_)) => panic!("Invalid value for {}!", option),
+        None => panic!("Missing value for {}!", option),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exif::ByteOrder;
+    use crate::image::{AlphaPolicy, ColorMatrix, ColorSpace};
+    use crate::jpg_writer::Density;
+    use crate::quality_control::QualityTarget;
+    use crate::rate_control::RateTarget;
+    use crate::resize::ResizeFilter;
+    use crate::thumbnail::{ThumbnailFormat, DEFAULT_THUMBNAIL_SIZE};
+    use crate::yuv_reader::{ColorRange, FrameSize};
+
+    use super::{parse_arguments, Command, DEFAULT_BATCH_OUTPUT, DEFAULT_OUTPUT};
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_arguments_input_only() {
+        let arguments = parse_arguments(&to_args(&["image.ppm"]));
+        assert_eq!(Command::Encode, arguments.command);
+        assert_eq!("image.ppm", arguments.input);
+        assert!(arguments.rate_target.is_none());
+        assert!(arguments.quality_target.is_none());
+        assert!(arguments.rdo_lambda.is_none());
+        assert!(arguments.masking_strength.is_none());
+        assert_eq!(ColorMatrix::Bt601, arguments.color_matrix);
+        assert_eq!(ColorSpace::YCbCr, arguments.color_space);
+        assert!(arguments.icc_profile.is_none());
+        assert!(arguments.exif.is_none());
+        assert!(arguments.xmp.is_none());
+        assert!(arguments.comments.is_none());
+        assert_eq!(Density::AspectRatio(1, 1), arguments.density);
+    }
+
+    #[test]
+    fn test_parse_arguments_output() {
+        let arguments = parse_arguments(&to_args(&["image.ppm"]));
+        assert_eq!(DEFAULT_OUTPUT, arguments.output);
+        let arguments = parse_arguments(&to_args(&["-", "-o", "-"]));
+        assert_eq!("-", arguments.input);
+        assert_eq!("-", arguments.output);
+        let arguments = parse_arguments(&to_args(&["--output", "small.jpg", "image.ppm"]));
+        assert_eq!("small.jpg", arguments.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown option -x!")]
+    fn test_parse_arguments_unknown_short_option() {
+        parse_arguments(&to_args(&["image.ppm", "-x"]));
+    }
+
+    #[test]
+    fn test_parse_arguments_rdo_lambda() {
+        let arguments = parse_arguments(&to_args(&["--rdo-lambda", "12.5", "image.ppm", "--target-bytes", "1000"]));
+        assert_eq!(Some(12.5), arguments.rdo_lambda);
+        assert!(matches!(arguments.rate_target, Some(RateTarget::Bytes(1000))));
+    }
+
+    #[test]
+    fn test_parse_arguments_aq_strength() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--aq-strength", "0.25"]));
+        assert_eq!(Some(0.25), arguments.masking_strength);
+    }
+
+    #[test]
+    fn test_parse_arguments_compare() {
+        let arguments = parse_arguments(&to_args(&["compare", "image.ppm", "--target-psnr", "40"]));
+        assert_eq!(Command::Compare, arguments.command);
+        assert_eq!("image.ppm", arguments.input);
+        assert!(matches!(arguments.quality_target, Some(QualityTarget::Psnr(psnr)) if psnr == 40.0));
+        assert!(arguments.decoded_output.is_none());
+
+        let arguments = parse_arguments(&to_args(&["compare", "image.ppm", "--decoded", "decoded.ppm"]));
+        assert_eq!(Some("decoded.ppm".to_string()), arguments.decoded_output);
+    }
+
+    #[test]
+    fn test_parse_arguments_resize() {
+        let arguments = parse_arguments(&to_args(&["image.ppm"]));
+        assert!(arguments.resize_widths.is_empty());
+        assert_eq!(ResizeFilter::Lanczos3, arguments.resize_filter);
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--resize", "320,640, 1280", "--resize-filter", "bilinear"]));
+        assert_eq!(vec![320, 640, 1280], arguments.resize_widths);
+        assert_eq!(ResizeFilter::Bilinear, arguments.resize_filter);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid value for --resize!")]
+    fn test_parse_arguments_resize_zero() {
+        parse_arguments(&to_args(&["image.ppm", "--resize", "640,0"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Several sizes can't be written to stdout!")]
+    fn test_parse_arguments_resize_stdout() {
+        parse_arguments(&to_args(&["image.ppm", "--resize", "320,640", "-o", "-"]));
+    }
+
+    #[test]
+    fn test_parse_arguments_batch() {
+        let arguments = parse_arguments(&to_args(&["batch", "images", "--target-psnr", "40"]));
+        assert_eq!(Command::Batch, arguments.command);
+        assert_eq!("images", arguments.input);
+        assert_eq!(DEFAULT_BATCH_OUTPUT, arguments.output);
+        let arguments = parse_arguments(&to_args(&["batch", "frames.ppm", "-o", "frames"]));
+        assert_eq!("frames", arguments.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Batch mode can't read from stdin or write to stdout!")]
+    fn test_parse_arguments_batch_stdin() {
+        parse_arguments(&to_args(&["batch", "-"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "The decoded image can only be written with compare!")]
+    fn test_parse_arguments_decoded_without_compare() {
+        parse_arguments(&to_args(&["image.ppm", "--decoded", "decoded.ppm"]));
+    }
+
+    #[test]
+    fn test_parse_arguments_target_bytes() {
+        let arguments = parse_arguments(&to_args(&["--target-bytes", "50000", "image.ppm"]));
+        assert_eq!("image.ppm", arguments.input);
+        assert!(matches!(arguments.rate_target, Some(RateTarget::Bytes(50000))));
+    }
+
+    #[test]
+    fn test_parse_arguments_target_bpp() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--target-bpp", "1.5"]));
+        assert!(matches!(arguments.rate_target, Some(RateTarget::BitsPerPixel(bpp)) if bpp == 1.5));
+    }
+
+    #[test]
+    fn test_parse_arguments_target_psnr() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--target-psnr", "40"]));
+        assert!(matches!(arguments.quality_target, Some(QualityTarget::Psnr(psnr)) if psnr == 40.0));
+    }
+
+    #[test]
+    fn test_parse_arguments_target_ssim() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--target-ssim", "0.98"]));
+        assert!(matches!(arguments.quality_target, Some(QualityTarget::Ssim(ssim)) if ssim == 0.98));
+    }
+
+    #[test]
+    fn test_parse_arguments_color_matrix() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--color-matrix", "bt709"]));
+        assert_eq!(ColorMatrix::Bt709, arguments.color_matrix);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_unknown_color_matrix() {
+        parse_arguments(&to_args(&["image.ppm", "--color-matrix", "srgb"]));
+    }
+
+    #[test]
+    fn test_parse_arguments_color_space() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--color-space", "ycck"]));
+        assert_eq!(ColorSpace::Ycck, arguments.color_space);
+    }
+
+    #[test]
+    fn test_parse_arguments_alpha() {
+        let arguments = parse_arguments(&to_args(&["image.pam"]));
+        assert_eq!(AlphaPolicy::Composite(255, 255, 255), arguments.alpha);
+        let arguments = parse_arguments(&to_args(&["--alpha", "sidecar", "image.pam"]));
+        assert_eq!(AlphaPolicy::Sidecar, arguments.alpha);
+    }
+
+    #[test]
+    fn test_parse_arguments_icc_profile() {
+        let arguments = parse_arguments(&to_args(&["--icc-profile", "sRGB.icc", "image.ppm"]));
+        assert_eq!(Some("sRGB.icc".to_string()), arguments.icc_profile);
+        assert_eq!("image.ppm", arguments.input);
+    }
+
+    #[test]
+    fn test_parse_arguments_comments_and_density() {
+        let arguments = parse_arguments(&to_args(&[
+            "--comment",
+            "first",
+            "image.ppm",
+            "--comment",
+            "second",
+            "--density",
+            "300dpi",
+        ]));
+        assert_eq!(Some(vec!["first".to_string(), "second".to_string()]), arguments.comments);
+        assert_eq!(Density::DotsPerInch(300, 300), arguments.density);
+    }
+
+    #[test]
+    fn test_parse_arguments_thumbnail() {
+        let arguments = parse_arguments(&to_args(&["image.ppm"]));
+        assert_eq!(None, arguments.thumbnail);
+        assert_eq!(DEFAULT_THUMBNAIL_SIZE, arguments.thumbnail_size);
+
+        let arguments = parse_arguments(&to_args(&["--thumbnail", "rgb", "--thumbnail-size", "64", "image.ppm"]));
+        assert_eq!(Some(ThumbnailFormat::Rgb), arguments.thumbnail);
+        assert_eq!(64, arguments.thumbnail_size);
+    }
+
+    #[test]
+    fn test_parse_arguments_yuv() {
+        let arguments = parse_arguments(&to_args(&["video.y4m"]));
+        assert_eq!(0, arguments.frame);
+        assert_eq!(None, arguments.yuv_size);
+        assert_eq!(ColorRange::Limited, arguments.yuv_range);
+
+        let arguments = parse_arguments(&to_args(&["video.yuv", "--frame", "12", "--yuv-size", "640x480", "--yuv-range", "full"]));
+        assert_eq!(12, arguments.frame);
+        assert_eq!(Some(FrameSize { width: 640, height: 480 }), arguments.yuv_size);
+        assert_eq!(ColorRange::Full, arguments.yuv_range);
+    }
+
+    #[test]
+    fn test_parse_arguments_xmp() {
+        let arguments = parse_arguments(&to_args(&["image.ppm", "--xmp", "image.xmp"]));
+        assert_eq!(Some("image.xmp".to_string()), arguments.xmp);
+    }
+
+    #[test]
+    fn test_parse_arguments_exif() {
+        let arguments = parse_arguments(&to_args(&[
+            "image.ppm",
+            "--exif-date",
+            "2024:01:02 03:04:05",
+            "--exif-orientation",
+            "6",
+            "--exif-make",
+            "Maker",
+            "--exif-model",
+            "Camera",
+            "--exif-copyright",
+            "Someone",
+            "--exif-gps",
+            "52.5,13.4",
+            "--exif-little-endian",
+        ]));
+        let exif = arguments.exif.unwrap();
+        assert_eq!(ByteOrder::LittleEndian, exif.byte_order);
+        assert_eq!(Some("2024:01:02 03:04:05".to_string()), exif.date_time);
+        assert_eq!(Some(6), exif.orientation);
+        assert_eq!(Some("Maker".to_string()), exif.make);
+        assert_eq!(Some("Camera".to_string()), exif.model);
+        assert_eq!(Some("Someone".to_string()), exif.copyright);
+        assert_eq!(Some(13.4), exif.gps.map(|gps| gps.longitude));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_cmyk_and_target() {
+        parse_arguments(&to_args(&["image.ppm", "--color-space", "cmyk", "--target-bytes", "1000"]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_rate_and_quality_target() {
+        parse_arguments(&to_args(&["image.ppm", "--target-psnr", "40", "--target-bytes", "1000"]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_no_input() {
+        parse_arguments(&to_args(&["--target-bytes", "50000"]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_missing_value() {
+        parse_arguments(&to_args(&["image.ppm", "--target-bytes"]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_arguments_invalid_value() {
+        parse_arguments(&to_args(&["image.ppm", "--target-bpp", "abc"]));
+    }
+}
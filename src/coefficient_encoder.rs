@@ -165,13 +165,7 @@ fn runlength_encode_single_ac_table(table: &[i32]) -> Vec<(u8, CategoryCode)> {
 fn huffman_encode_ac_coefficients(
     runlength_encoded: &Vec<Vec<(u8, CategoryCode)>>,
 ) -> (Vec<Vec<(HuffmanCode, CategoryCode)>>, HuffmanCodeMap) {
-    let mut categories = crate::BitStream::open();
-    runlength_encoded
-        .iter()
-        .for_each(|table| table.iter().for_each(|val| categories.append(val.0)));
-
-    let category_code = crate::huffman::parse_u8_stream(&mut categories).code_map();
-    // let category_code = crate::package_merge::package_merge_experimental(&mut categories, 16);
+    let category_code = ac_category_code(runlength_encoded);
 
     let mut huffman_encoded: Vec<Vec<(HuffmanCode, CategoryCode)>> =
         Vec::with_capacity(runlength_encoded.len());
@@ -185,6 +179,63 @@ fn huffman_encode_ac_coefficients(
     (huffman_encoded, category_code)
 }
 
+/// Create the huffman code for the zeros/category values of run-length encoded AC coefficients.
+fn ac_category_code(runlength_encoded: &[Vec<(u8, CategoryCode)>]) -> HuffmanCodeMap {
+    let mut categories = crate::BitStream::open();
+    runlength_encoded
+        .iter()
+        .for_each(|table| table.iter().for_each(|val| categories.append(val.0)));
+
+    crate::huffman::parse_u8_stream(&mut categories).code_map()
+    // crate::package_merge::package_merge_experimental(&mut categories, 16)
+}
+
+/// Cost model for AC coefficients, estimating how many bits a coefficient takes up
+/// after run-length, category and huffman encoding.
+/// The huffman code lengths are taken from a code built for a set of AC coefficients,
+/// usually the same ones whose encoding is being optimised.
+pub struct AcCostModel {
+    code_lengths: [u8; 256],
+}
+
+/// Code length assumed for zeros/category values that don't appear in the huffman code.
+const MISSING_CODE_LENGTH: u8 = 16;
+
+impl AcCostModel {
+    /// Build the cost model from the huffman code the given AC coefficients would be encoded with.
+    pub fn from_ac_coefficients(ac_coefficients: &[[i32; 63]]) -> AcCostModel {
+        let runlength_encoded: Vec<Vec<(u8, CategoryCode)>> = ac_coefficients
+            .iter()
+            .map(|coeff| runlength_encode_single_ac_table(coeff))
+            .collect();
+        AcCostModel::from_code_map(&ac_category_code(&runlength_encoded))
+    }
+
+    /// Build the cost model from a huffman code map for zeros/category values.
+    pub fn from_code_map(code_map: &HuffmanCodeMap) -> AcCostModel {
+        let mut code_lengths = [MISSING_CODE_LENGTH; 256];
+        for (symbol, (length, _)) in code_map {
+            code_lengths[*symbol as usize] = *length;
+        }
+        AcCostModel { code_lengths }
+    }
+
+    /// Get the amount of bits needed to encode a non-zero coefficient preceded by `run` zeros.
+    /// This includes the huffman codes for runs of 16 zeros, the huffman code of the
+    /// zeros/category value and the bits of the categorised value itself.
+    pub fn coefficient_cost(&self, run: usize, value: i32) -> u32 {
+        let (cat, _) = categorize(value);
+        let zero_runs = (run / 16) as u32 * self.code_lengths[0xF0] as u32;
+        let zeros_cat = (((run % 16) as u8) << 4) + cat;
+        zero_runs + self.code_lengths[zeros_cat as usize] as u32 + cat as u32
+    }
+
+    /// Get the amount of bits needed to encode the end of block symbol.
+    pub fn end_of_block_cost(&self) -> u32 {
+        self.code_lengths[0x00] as u32
+    }
+}
+
 /// Get the categorised representation of the given value.
 /// Values get a category between 0 and 15 based on the amount
 /// of bits set. For negative values, an offset is applied
@@ -253,7 +304,38 @@ pub fn reorder_y_coefficients<T: Copy>(coefficients: &mut Vec<T>, width: u16) {
 mod tests {
     use crate::coefficient_encoder::runlength_encode_single_ac_table;
 
-    use super::{ac_coefficients, categorize, coefficients_to_diffs, dc_coefficients, reorder_y_coefficients};
+    use crate::huffman::HuffmanCodeMap;
+
+    use super::{ac_coefficients, categorize, coefficients_to_diffs, dc_coefficients, reorder_y_coefficients, AcCostModel};
+
+    #[test]
+    fn test_ac_cost_model_from_code_map() {
+        let mut code_map = HuffmanCodeMap::new();
+        code_map.insert(0x00, (2, 0b00));
+        code_map.insert(0x01, (2, 0b01));
+        code_map.insert(0x22, (3, 0b100));
+        code_map.insert(0xF0, (4, 0b1010));
+        let cost_model = AcCostModel::from_code_map(&code_map);
+
+        assert_eq!(2, cost_model.end_of_block_cost());
+        assert_eq!(2 + 1, cost_model.coefficient_cost(0, -1));
+        assert_eq!(3 + 2, cost_model.coefficient_cost(2, 3));
+        assert_eq!(4 + 3 + 2, cost_model.coefficient_cost(18, -2));
+        // not part of the code
+        assert_eq!(16 + 4, cost_model.coefficient_cost(1, 9));
+    }
+
+    #[test]
+    fn test_ac_cost_model_from_ac_coefficients() {
+        let mut ac = vec![[0; 63]; 4];
+        ac[0][0] = 1;
+        ac[1][0] = 1;
+        ac[2][0] = 1;
+        let cost_model = AcCostModel::from_ac_coefficients(&ac);
+        // both symbols used, so neither gets the default length
+        assert!(cost_model.end_of_block_cost() < 16);
+        assert!(cost_model.coefficient_cost(0, 1) < 16 + 1);
+    }
 
     #[test]
     fn test_get_dc_coefficients() {
@@ -0,0 +1,140 @@
+use crate::image::ColorMatrix;
+
+/// Amount of fractional bits of the fixed point coefficients.
+const SCALE_BITS: u32 = 16;
+/// 0.5 in fixed point, for rounding.
+const ONE_HALF: i32 = 1 << (SCALE_BITS - 1);
+/// The centre of Cb and Cr (128) in fixed point.
+const CBCR_OFFSET: i32 = 128 << SCALE_BITS;
+
+/// Lookup tables for converting 8 bit RGB samples to YCbCr with integer arithmetic only,
+/// like libjpeg's `jccolor`. Each table holds the product of a matrix coefficient
+/// (in 16 bit fixed point) with every possible sample value, so a conversion is nine lookups,
+/// six additions and three shifts.
+/// The rounding constants (and the centre of Cb and Cr) are folded into the red tables.
+///
+/// # Attributes
+///
+/// * `y`: The tables for the Y channel, indexed by the R, G and B channel.
+/// * `cb`: The tables for the Cb channel.
+/// * `cr`: The tables for the Cr channel.
+pub struct ColorConversionTables {
+    y: [[i32; 256]; 3],
+    cb: [[i32; 256]; 3],
+    cr: [[i32; 256]; 3],
+}
+
+impl ColorConversionTables {
+    /// Create the lookup tables for the given colour matrix.
+    pub fn new(matrix: ColorMatrix) -> ColorConversionTables {
+        let matrix = matrix.rgb_to_ycbcr_matrix();
+        let mut tables = ColorConversionTables {
+            y: [[0; 256]; 3],
+            cb: [[0; 256]; 3],
+            cr: [[0; 256]; 3],
+        };
+        for channel in 0..3 {
+            let y_factor = to_fixed_point(matrix[(0, channel)]);
+            let cb_factor = to_fixed_point(matrix[(1, channel)]);
+            let cr_factor = to_fixed_point(matrix[(2, channel)]);
+            for value in 0..256 {
+                tables.y[channel][value] = y_factor * value as i32;
+                tables.cb[channel][value] = cb_factor * value as i32;
+                tables.cr[channel][value] = cr_factor * value as i32;
+            }
+        }
+        for value in 0..256 {
+            tables.y[0][value] += ONE_HALF;
+            // rounding with ONE_HALF - 1 keeps the maximum of 127.5 from rounding up to 128
+            tables.cb[0][value] += CBCR_OFFSET + ONE_HALF - 1;
+            tables.cr[0][value] += CBCR_OFFSET + ONE_HALF - 1;
+        }
+        tables
+    }
+
+    /// Convert one RGB value to a level shifted YCbCr value, like `convert_rgb_values_to_ycbcr` in image.rs.
+    /// Samples outside of 0..=255 are clamped.
+    pub fn convert(&self, r: i16, g: i16, b: i16) -> (i16, i16, i16) {
+        let r = r.clamp(0, 255) as usize;
+        let g = g.clamp(0, 255) as usize;
+        let b = b.clamp(0, 255) as usize;
+        let y = (self.y[0][r] + self.y[1][g] + self.y[2][b]) >> SCALE_BITS;
+        let cb = (self.cb[0][r] + self.cb[1][g] + self.cb[2][b]) >> SCALE_BITS;
+        let cr = (self.cr[0][r] + self.cr[1][g] + self.cr[2][b]) >> SCALE_BITS;
+        (
+            (y.clamp(0, 255) - 128) as i16,
+            (cb.clamp(0, 255) - 128) as i16,
+            (cr.clamp(0, 255) - 128) as i16,
+        )
+    }
+
+    /// Convert a row of RGB samples to level shifted YCbCr in place.
+    ///
+    /// # Arguments
+    /// * `row1`: The R samples, replaced by the Y samples.
+    /// * `row2`: The G samples, replaced by the Cb samples.
+    /// * `row3`: The B samples, replaced by the Cr samples.
+    pub fn convert_row(&self, row1: &mut [i16], row2: &mut [i16], row3: &mut [i16]) {
+        for ((first, second), third) in row1.iter_mut().zip(row2.iter_mut()).zip(row3.iter_mut()) {
+            (*first, *second, *third) = self.convert(*first, *second, *third);
+        }
+    }
+}
+
+/// Convert a coefficient to fixed point with `SCALE_BITS` fractional bits.
+fn to_fixed_point(value: f32) -> i32 {
+    (value as f64 * (1 << SCALE_BITS) as f64).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::{create_image, ColorMatrix};
+
+    use super::ColorConversionTables;
+
+    #[test]
+    fn test_convert_primaries() {
+        let tables = ColorConversionTables::new(ColorMatrix::Bt601);
+        assert_eq!((-128, 0, 0), tables.convert(0, 0, 0));
+        assert_eq!((-52, -43, 127), tables.convert(255, 0, 0));
+        assert_eq!((22, -84, -107), tables.convert(0, 255, 0));
+        assert_eq!((-99, 127, -21), tables.convert(0, 0, 255));
+        assert_eq!((127, 0, 0), tables.convert(255, 255, 255));
+        assert_eq!((0, 0, 0), tables.convert(128, 128, 128));
+    }
+
+    #[test]
+    fn test_convert_matches_float_conversion() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020] {
+            let tables = ColorConversionTables::new(matrix);
+            for r in (0..=255).step_by(5) {
+                let size = 52 * 52;
+                let mut image = create_image(
+                    1,
+                    size as u16,
+                    vec![vec![r; size]],
+                    vec![(0..size).map(|index| (index / 52 * 5) as i16).collect()],
+                    vec![(0..size).map(|index| (index % 52 * 5) as i16).collect()],
+                );
+                let mut row1 = image.channel1()[0].clone();
+                let mut row2 = image.channel2()[0].clone();
+                let mut row3 = image.channel3()[0].clone();
+                tables.convert_row(&mut row1, &mut row2, &mut row3);
+                image.rgb_to_ycbcr_with_matrix(matrix);
+                // both round, but may disagree on values very close to .5
+                for (lut, float) in [(&row1, image.channel1()), (&row2, image.channel2()), (&row3, image.channel3())] {
+                    for (a, b) in lut.iter().zip(&float[0]) {
+                        assert!((a - b).abs() <= 1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert_clamps_input() {
+        let tables = ColorConversionTables::new(ColorMatrix::Bt601);
+        assert_eq!(tables.convert(255, 255, 255), tables.convert(300, 1000, 256));
+        assert_eq!(tables.convert(0, 0, 0), tables.convert(-1, -50, 0));
+    }
+}
@@ -102,12 +102,45 @@ pub fn inverse_dct(input: &SMatrix<f32, 8, 8>) -> SMatrix<f32, 8, 8> {
     output
 }
 
+/// Inverse Discrete Cosine Transform on a 8x8 matrix, implemented using matrix multiplication A^TYA
+/// with O(n^3) complexity. This is the inverse of `matrix_dct`, but much faster than `inverse_dct`.
+/// # Arguments
+/// * `input`: The matrix to perform the IDCT on.
+pub fn matrix_inverse_dct(input: &SMatrix<f32, 8, 8>) -> SMatrix<f32, 8, 8> {
+    MATRIX_A_MATRIX_TRANS.mul(input).mul(MATRIX_A_MATRIX)
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_abs_diff_eq;
     use nalgebra::SMatrix;
 
-    use super::{arai_dct, direct_dct, inverse_dct, matrix_dct};
+    use super::{arai_dct, direct_dct, inverse_dct, matrix_dct, matrix_inverse_dct};
+
+    #[test]
+    fn test_matrix_inverse_dct_equals_inverse_dct() {
+        let y: SMatrix<f32, 8, 8> = SMatrix::from_fn(|i, j| ((i * 8 + j) as f32 * 7.3).sin() * 100.0);
+        let expected = inverse_dct(&y);
+        let actual = matrix_inverse_dct(&y);
+        for i in 0..8 {
+            for j in 0..8 {
+                assert_abs_diff_eq!(expected[(i, j)], actual[(i, j)], epsilon = 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_inverse_dct_reverts_arai_dct() {
+        let expected: SMatrix<f32, 8, 8> = SMatrix::from_fn(|i, j| (i * 31 + j * 17) as f32 % 255.0 - 128.0);
+        let mut x = expected;
+        arai_dct(&mut x);
+        let actual = matrix_inverse_dct(&x);
+        for i in 0..8 {
+            for j in 0..8 {
+                assert_abs_diff_eq!(expected[(i, j)], actual[(i, j)], epsilon = 0.01);
+            }
+        }
+    }
 
     #[test]
     fn test_direct_dct_from_slides() {
@@ -1,24 +1,23 @@
-use std::fs::File;
-use std::io::{Error, Write};
-use std::usize;
+use std::io::Error;
 
-use lazy_static::lazy_static;
 use nalgebra::SMatrix;
-use regex::Regex;
 
-lazy_static! {
-    /// This regex checks correct paths given to the to_ppm function
-    /// - We start at the beginning of the path string
-    /// - The first group checks for (back-)slashes, in case of unixoid systems zero or forward,
-    /// in case of Windows zero to two backslashes
-    /// - Then we check for dots, there are zero to two allowed
-    /// - then we allow every char which is not forbidden by the OS as part of a file/directory
-    /// - This whole block has to appear at least once
-    /// - Then we check for the right file suffix and the ending of the string
-    static ref PPM_FILEPATH_REGEX: Regex =
-        Regex::new(r#"^(([/]{0,1} | [\\]{0,2}){0,1}[.]{0,2}[^,;<>:"|\?\*]+)+(.ppm)$"#).unwrap();
-}
+use crate::image::create_image;
+use crate::ppm_writer::{write_ppm_to_file, PpmEncoding};
 
+/// Write three channels of 8x8 blocks, e.g. the output of the IDCT, to a plain PPM file.
+/// The blocks are stored row by row, with each row of blocks covering the width rounded up to a multiple of 8;
+/// the padding beyond the width and height is left out. The values are rounded and clamped to 0..=255.
+///
+/// # Arguments
+/// * `data`: The blocks of the red, green and blue channel.
+/// * `height`: The height of the image.
+/// * `width`: The width of the image.
+/// * `path`: The path of the file to write to.
+///
+/// # Errors
+/// * If there are fewer blocks than needed for the size.
+/// * If the file can't be written.
 pub fn to_ppm(
     data: (
         &Vec<SMatrix<f32, 8, 8>>,
@@ -29,40 +28,69 @@ pub fn to_ppm(
     width: u16,
     path: &str,
 ) -> Result<(), Error> {
-    if !PPM_FILEPATH_REGEX.is_match(path) {
-        return Err(Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "File path doesn't match our regex!",
-        ));
-    }
-    if !(height % 8 == 0 && width % 8 == 0) {
+    let blocks_per_row = (width as usize).div_ceil(8);
+    let block_count = blocks_per_row * (height as usize).div_ceil(8);
+    if [data.0, data.1, data.2].iter().any(|blocks| blocks.len() < block_count) {
         return Err(Error::new(
             std::io::ErrorKind::InvalidInput,
-            "Width or height not divisible by 8",
+            "Not enough blocks for the given size!",
         ));
     }
-    let r_values: Vec<_> = data.0.chunks(width as usize / 8usize).collect();
-    let g_values: Vec<_> = data.1.chunks(width as usize / 8usize).collect();
-    let b_values: Vec<_> = data.2.chunks(width as usize / 8usize).collect();
+    let to_channel = |blocks: &Vec<SMatrix<f32, 8, 8>>| -> Vec<Vec<i16>> {
+        (0..height as usize)
+            .map(|y| {
+                (0..width as usize)
+                    .map(|x| {
+                        let value = blocks[y / 8 * blocks_per_row + x / 8][(y % 8, x % 8)];
+                        value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+    let image = create_image(height, width, to_channel(data.0), to_channel(data.1), to_channel(data.2));
+    write_ppm_to_file(&image, PpmEncoding::Plain, path)
+}
 
-    let mut file = File::create(path)?;
-    writeln!(file, "P3")?;
-    write!(file, "{}", width.to_string())?;
-    write!(file, " ")?;
-    writeln!(file, "{}", height.to_string())?;
-    writeln!(file, "{}", 255)?;
-    for y in 0..r_values.len() {
-        for i in 0..8 {
-            for j in 0..width as usize {
-                let x = j / 8;
-                let x_index = j % 8;
-                let r_val = (r_values[y][x][(i, x_index)]) as u16;
-                let g_val = (g_values[y][x][(i, x_index)]) as u16;
-                let b_val = (b_values[y][x][(i, x_index)]) as u16;
-                write!(file, "{} {} {} ", r_val, g_val, b_val)?;
-            }
-            writeln!(file)?;
+#[cfg(test)]
+mod tests {
+    use nalgebra::SMatrix;
+
+    use crate::ppm_parser::read_ppm_from_file;
+
+    use super::to_ppm;
+
+    #[test]
+    fn test_to_ppm_crops_and_clamps() {
+        // 12x9 pixels take 2x2 blocks, stored row by row
+        let mut position = SMatrix::<f32, 8, 8>::zeros();
+        for (index, value) in position.iter_mut().enumerate() {
+            // column major, so this is 8 * column + row
+            *value = index as f32 + 0.4;
         }
+        let blocks = vec![
+            SMatrix::<f32, 8, 8>::repeat(-20.0),
+            SMatrix::<f32, 8, 8>::repeat(300.0),
+            position,
+            SMatrix::<f32, 8, 8>::repeat(200.6),
+        ];
+        let green = vec![SMatrix::<f32, 8, 8>::repeat(17.0); 4];
+        let path = std::env::temp_dir().join(format!("jpeg_encoder_to_ppm_{}.ppm", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        to_ppm((&blocks, &green, &blocks), 9, 12, &path).unwrap();
+        let image = read_ppm_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((12, 9), (image.width(), image.height()));
+        assert_eq!((0, 17, 0), image.pixel_at(7, 7));
+        assert_eq!((255, 17, 255), image.pixel_at(8, 0));
+        assert_eq!((8 * 3, 17, 8 * 3), image.pixel_at(3, 8));
+        assert_eq!((201, 17, 201), image.pixel_at(11, 8));
+    }
+
+    #[test]
+    fn test_to_ppm_too_few_blocks() {
+        let blocks = vec![SMatrix::<f32, 8, 8>::zeros(); 3];
+        assert!(to_ppm((&blocks, &blocks, &blocks), 9, 12, "unused.ppm").is_err());
     }
-    Ok(())
 }
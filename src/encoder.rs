@@ -0,0 +1,466 @@
+use nalgebra::SMatrix;
+use scoped_threadpool::Pool;
+
+use crate::bit_stream::BitStream;
+use crate::exif::Exif;
+use crate::image::Image;
+use crate::jpg_writer::{AdobeTransform, JfifHeader, DEFAULT_COMMENT};
+use crate::{coefficient_encoder, image_data_writer, jpg_writer, parallel_quantize, quantization};
+
+/// Settings controlling how DCT output is turned into a JPEG file.
+///
+/// # Attributes
+///
+/// * `luminance_q_table`: The quantization table for the Y channel, in format 1/x.
+/// * `chrominance_q_table`: The quantization table for the Cb and Cr channels, in format 1/x.
+/// * `rdo_lambda`: If set, quantize with rate-distortion optimization using this lambda
+///   (see `quantization::trellis_quantize`) instead of plain rounding.
+/// * `masking_strength`: If set, apply activity masking with this strength before quantizing
+///   (see `parallel_quantize::apply_activity_masking`).
+/// * `comments`: The comments to write, each in its own COM segment(s).
+/// * `jfif_header`: The pixel density and thumbnail (see `thumbnail::create_thumbnail`) to store in the JFIF header.
+///   If unset, no JFIF header is written, e.g. for thumbnails embedded in another file's JFXX segment.
+/// * `exif`: If set, embed this Exif metadata (see `jpg_writer::write_exif_segment`).
+/// * `xmp`: If set, embed this XMP packet (see `jpg_writer::write_xmp_segments`).
+/// * `icc_profile`: If set, embed this ICC profile (see `jpg_writer::write_icc_profile_segments`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderSettings {
+    pub luminance_q_table: SMatrix<f32, 8, 8>,
+    pub chrominance_q_table: SMatrix<f32, 8, 8>,
+    pub rdo_lambda: Option<f32>,
+    pub masking_strength: Option<f32>,
+    pub comments: Vec<String>,
+    pub jfif_header: Option<JfifHeader>,
+    pub exif: Option<Exif>,
+    pub xmp: Option<String>,
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> EncoderSettings {
+        EncoderSettings {
+            luminance_q_table: quantization::box_q_table(1f32, 3, 1f32),
+            chrominance_q_table: quantization::box_q_table(2f32, 3, 1f32),
+            rdo_lambda: None,
+            masking_strength: None,
+            comments: vec![DEFAULT_COMMENT.to_string()],
+            jfif_header: Some(JfifHeader::default()),
+            exif: None,
+            xmp: None,
+            icc_profile: None,
+        }
+    }
+}
+
+impl EncoderSettings {
+    /// Get a copy of these settings with both quantization tables scaled to the given quality.
+    /// See `quantization::scale_q_table`.
+    pub fn with_quality(&self, quality: u8) -> EncoderSettings {
+        let mut settings = self.clone();
+        settings.luminance_q_table = quantization::scale_q_table(&self.luminance_q_table, quality);
+        settings.chrominance_q_table = quantization::scale_q_table(&self.chrominance_q_table, quality);
+        settings
+    }
+}
+
+/// Quantize a channel's DCT output with the quantizer selected by the settings,
+/// then return a zigzag sampled array of the results.
+/// Activity masking, if enabled, is applied first and works with either quantizer.
+///
+/// # Arguments
+/// * `dct`: The DCT output of the channel. Consumed, as masking and plain quantization happen in place.
+/// * `q_table`: The quantization table for the channel, in format 1/x.
+/// * `settings`: The settings selecting the quantizer.
+/// * `pool`: The thread pool to quantize with.
+pub fn quantize_channel(
+    mut dct: Vec<SMatrix<f32, 8, 8>>,
+    q_table: &SMatrix<f32, 8, 8>,
+    settings: &EncoderSettings,
+    pool: &mut Pool,
+) -> Vec<[i32; 64]> {
+    if let Some(strength) = settings.masking_strength {
+        parallel_quantize::apply_activity_masking(&mut dct, *q_table, strength, pool);
+    }
+    match settings.rdo_lambda {
+        Some(lambda) => parallel_quantize::quantize_zigzag_trellis(&dct, *q_table, lambda, pool),
+        None => parallel_quantize::quantize_zigzag(&mut dct, *q_table, pool),
+    }
+}
+
+/// Quantize and entropy code the DCT output of an image, then write a complete JPEG file
+/// (headers and image data) to a new stream.
+/// The DCT matrices are consumed, as quantization happens in place. Callers that want to encode
+/// the same DCT output several times (e.g. with different quantization tables) should pass clones.
+///
+/// This assumes the image was downsampled with 4:2:0, as the image data writer does.
+///
+/// # Arguments
+/// * `image`: The image the DCT output was calculated from. Used for the header segments.
+/// * `y_dct`: The DCT output of the Y channel.
+/// * `cb_dct`: The DCT output of the Cb channel.
+/// * `cr_dct`: The DCT output of the Cr channel.
+/// * `settings`: The quantization tables etc. to encode with.
+/// * `pool`: The thread pool to quantize with.
+///
+/// # Example
+/// ```
+/// let (y_dct, cb_dct, cr_dct) = parallel_dct::dct(&mut image, &DCTMode::Arai, &mut pool);
+/// let mut stream = encode_dct_to_stream(&image, y_dct, cb_dct, cr_dct, &EncoderSettings::default(), &mut pool);
+/// stream.flush_to_file("output.jpg");
+/// ```
+pub fn encode_dct_to_stream(
+    image: &Image,
+    y_dct: Vec<SMatrix<f32, 8, 8>>,
+    cb_dct: Vec<SMatrix<f32, 8, 8>>,
+    cr_dct: Vec<SMatrix<f32, 8, 8>>,
+    settings: &EncoderSettings,
+    pool: &mut Pool,
+) -> BitStream {
+    let y_quant = quantize_channel(y_dct, &settings.luminance_q_table, settings, pool);
+    let cb_quant = quantize_channel(cb_dct, &settings.chrominance_q_table, settings, pool);
+    let cr_quant = quantize_channel(cr_dct, &settings.chrominance_q_table, settings, pool);
+
+    let mut y_dc = coefficient_encoder::dc_coefficients(&y_quant);
+    let cb_dc = coefficient_encoder::dc_coefficients(&cb_quant);
+    let cr_dc = coefficient_encoder::dc_coefficients(&cr_quant);
+
+    let mut y_ac = coefficient_encoder::ac_coefficients(&y_quant);
+    let cb_ac = coefficient_encoder::ac_coefficients(&cb_quant);
+    let cr_ac = coefficient_encoder::ac_coefficients(&cr_quant);
+
+    coefficient_encoder::reorder_y_coefficients(&mut y_dc, image.padded_width(16));
+    coefficient_encoder::reorder_y_coefficients(&mut y_ac, image.padded_width(16));
+
+    let (y_dc_encoded, huffman_dc_y) = coefficient_encoder::encode_dc_coefficients(&y_dc);
+    let (cbcr_dc_encoded, huffman_dc_cbcr) = coefficient_encoder::encode_two_dc_coefficients(&cb_dc, &cr_dc);
+    let cb_dc_encoded = &cbcr_dc_encoded[0..cbcr_dc_encoded.len() / 2];
+    let cr_dc_encoded = &cbcr_dc_encoded[(cbcr_dc_encoded.len() / 2)..cbcr_dc_encoded.len()];
+
+    let (y_ac_encoded, huffman_ac_y) = coefficient_encoder::encode_ac_coefficients(&y_ac);
+    let (cbcr_ac_encoded, huffman_ac_cbcr) = coefficient_encoder::encode_two_ac_coefficients(&cb_ac, &cr_ac);
+    let cb_ac_encoded = &cbcr_ac_encoded[0..cbcr_ac_encoded.len() / 2];
+    let cr_ac_encoded = &cbcr_ac_encoded[(cbcr_ac_encoded.len() / 2)..cbcr_ac_encoded.len()];
+
+    let mut target_stream = BitStream::open();
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOI);
+    if let Some(header) = &settings.jfif_header {
+        jpg_writer::write_jfif_segments(&mut target_stream, header);
+    }
+    if let Some(exif) = &settings.exif {
+        jpg_writer::write_exif_segment(&mut target_stream, exif);
+    }
+    if let Some(packet) = &settings.xmp {
+        jpg_writer::write_xmp_segments(&mut target_stream, packet);
+    }
+    if let Some(profile) = &settings.icc_profile {
+        jpg_writer::write_icc_profile_segments(&mut target_stream, profile);
+    }
+    for comment in &settings.comments {
+        jpg_writer::write_comment_segments(&mut target_stream, comment);
+    }
+    jpg_writer::write_dqt_segment(&mut target_stream, &settings.luminance_q_table, 0);
+    jpg_writer::write_dqt_segment(&mut target_stream, &settings.chrominance_q_table, 1);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOF0);
+    jpg_writer::write_dht_segment(&mut target_stream, 0, &huffman_dc_y, false);
+    jpg_writer::write_dht_segment(&mut target_stream, 1, &huffman_dc_cbcr, false);
+
+    jpg_writer::write_dht_segment(&mut target_stream, 2, &huffman_ac_y, true);
+    jpg_writer::write_dht_segment(&mut target_stream, 3, &huffman_ac_cbcr, true);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOS);
+
+    target_stream.byte_stuffing(true);
+    image_data_writer::write_image_data_to_stream(&mut target_stream, &y_dc_encoded, cb_dc_encoded, cr_dc_encoded, &y_ac_encoded, cb_ac_encoded, cr_ac_encoded);
+    target_stream.byte_stuffing(false);
+
+    target_stream.pad_last_byte(true);
+
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::EOI);
+
+    target_stream
+}
+
+/// Quantize and entropy code the DCT output of a four channel (CMYK or YCCK) image,
+/// then write a complete JPEG file with an Adobe APP14 segment to a new stream.
+/// The first and fourth channel (C and K, or Y and K) share the luminance quantization table and
+/// huffman tables, the second and third channel share the others.
+/// For CMYK, the luminance quantization table is used for all channels, as none of them is chroma.
+///
+/// # Arguments
+/// * `image`: The image the DCT output was calculated from. Used for the header segments.
+/// * `first_dct`: The DCT output of the C or Y channel.
+/// * `second_dct`: The DCT output of the M or Cb channel.
+/// * `third_dct`: The DCT output of the Y or Cr channel.
+/// * `fourth_dct`: The DCT output of the K channel.
+/// * `transform`: The transform the channels were prepared with,
+///   `AdobeTransform::Unknown` for CMYK (see `Image::cmyk_to_adobe`) or `Ycck` (see `Image::cmyk_to_ycck`).
+/// * `settings`: The quantization tables etc. to encode with.
+/// * `pool`: The thread pool to quantize with.
+///
+/// # Panics
+/// * If `transform` is `AdobeTransform::YCbCr`, as that needs three channels.
+#[allow(clippy::too_many_arguments)]
+pub fn encode_four_channel_dct_to_stream(
+    image: &Image,
+    first_dct: Vec<SMatrix<f32, 8, 8>>,
+    second_dct: Vec<SMatrix<f32, 8, 8>>,
+    third_dct: Vec<SMatrix<f32, 8, 8>>,
+    fourth_dct: Vec<SMatrix<f32, 8, 8>>,
+    transform: AdobeTransform,
+    settings: &EncoderSettings,
+    pool: &mut Pool,
+) -> BitStream {
+    let second_q_table = match transform {
+        AdobeTransform::Unknown => settings.luminance_q_table,
+        AdobeTransform::Ycck => settings.chrominance_q_table,
+        AdobeTransform::YCbCr => panic!("Four channel images can't use the YCbCr transform!"),
+    };
+    let first_quant = quantize_channel(first_dct, &settings.luminance_q_table, settings, pool);
+    let second_quant = quantize_channel(second_dct, &second_q_table, settings, pool);
+    let third_quant = quantize_channel(third_dct, &second_q_table, settings, pool);
+    let fourth_quant = quantize_channel(fourth_dct, &settings.luminance_q_table, settings, pool);
+
+    let (first_fourth_dc_encoded, huffman_dc_first) = coefficient_encoder::encode_two_dc_coefficients(
+        &coefficient_encoder::dc_coefficients(&first_quant),
+        &coefficient_encoder::dc_coefficients(&fourth_quant),
+    );
+    let (second_third_dc_encoded, huffman_dc_second) = coefficient_encoder::encode_two_dc_coefficients(
+        &coefficient_encoder::dc_coefficients(&second_quant),
+        &coefficient_encoder::dc_coefficients(&third_quant),
+    );
+    let (first_fourth_ac_encoded, huffman_ac_first) = coefficient_encoder::encode_two_ac_coefficients(
+        &coefficient_encoder::ac_coefficients(&first_quant),
+        &coefficient_encoder::ac_coefficients(&fourth_quant),
+    );
+    let (second_third_ac_encoded, huffman_ac_second) = coefficient_encoder::encode_two_ac_coefficients(
+        &coefficient_encoder::ac_coefficients(&second_quant),
+        &coefficient_encoder::ac_coefficients(&third_quant),
+    );
+    let (first_dc_encoded, fourth_dc_encoded) = first_fourth_dc_encoded.split_at(first_fourth_dc_encoded.len() / 2);
+    let (second_dc_encoded, third_dc_encoded) = second_third_dc_encoded.split_at(second_third_dc_encoded.len() / 2);
+    let (first_ac_encoded, fourth_ac_encoded) = first_fourth_ac_encoded.split_at(first_fourth_ac_encoded.len() / 2);
+    let (second_ac_encoded, third_ac_encoded) = second_third_ac_encoded.split_at(second_third_ac_encoded.len() / 2);
+
+    let mut target_stream = BitStream::open();
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOI);
+    // Exif requires its APP1 segment to follow SOI directly
+    if let Some(exif) = &settings.exif {
+        jpg_writer::write_exif_segment(&mut target_stream, exif);
+    }
+    jpg_writer::write_app14_segment(&mut target_stream, transform);
+    if let Some(packet) = &settings.xmp {
+        jpg_writer::write_xmp_segments(&mut target_stream, packet);
+    }
+    if let Some(profile) = &settings.icc_profile {
+        jpg_writer::write_icc_profile_segments(&mut target_stream, profile);
+    }
+    for comment in &settings.comments {
+        jpg_writer::write_comment_segments(&mut target_stream, comment);
+    }
+    jpg_writer::write_dqt_segment(&mut target_stream, &settings.luminance_q_table, 0);
+    jpg_writer::write_dqt_segment(&mut target_stream, &second_q_table, 1);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOF0);
+    jpg_writer::write_dht_segment(&mut target_stream, 0, &huffman_dc_first, false);
+    jpg_writer::write_dht_segment(&mut target_stream, 1, &huffman_dc_second, false);
+    jpg_writer::write_dht_segment(&mut target_stream, 2, &huffman_ac_first, true);
+    jpg_writer::write_dht_segment(&mut target_stream, 3, &huffman_ac_second, true);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOS);
+
+    target_stream.byte_stuffing(true);
+    image_data_writer::write_interleaved_image_data_to_stream(
+        &mut target_stream,
+        &[first_dc_encoded, second_dc_encoded, third_dc_encoded, fourth_dc_encoded],
+        &[first_ac_encoded, second_ac_encoded, third_ac_encoded, fourth_ac_encoded],
+    );
+    target_stream.byte_stuffing(false);
+
+    target_stream.pad_last_byte(true);
+
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::EOI);
+
+    target_stream
+}
+
+/// Quantize and entropy code the DCT output of a grayscale image (e.g. the alpha sidecar of
+/// `Image::apply_alpha_policy`), then write a complete single component JPEG file to a new stream.
+/// The component uses the luminance quantization table. Only the density of the JFIF header and the comments
+/// are taken from the settings, as the thumbnail and the other metadata describe the colour image.
+///
+/// # Arguments
+/// * `image`: The image the DCT output was calculated from. Used for the header segments.
+/// * `dct`: The DCT output of the image's channel, see `parallel_dct::dct_grayscale`.
+/// * `settings`: The quantization tables etc. to encode with.
+/// * `pool`: The thread pool to quantize with.
+///
+/// # Panics
+/// * If the image isn't grayscale.
+pub fn encode_grayscale_dct_to_stream(
+    image: &Image,
+    dct: Vec<SMatrix<f32, 8, 8>>,
+    settings: &EncoderSettings,
+    pool: &mut Pool,
+) -> BitStream {
+    if image.channel_count() != 1 {
+        panic!("Image isn't grayscale!");
+    }
+    let quant = quantize_channel(dct, &settings.luminance_q_table, settings, pool);
+    let (dc_encoded, huffman_dc) = coefficient_encoder::encode_dc_coefficients(&coefficient_encoder::dc_coefficients(&quant));
+    let (ac_encoded, huffman_ac) = coefficient_encoder::encode_ac_coefficients(&coefficient_encoder::ac_coefficients(&quant));
+    let jfif_header = settings.jfif_header.as_ref().map(|header| match JfifHeader::new(header.density(), None) {
+        Ok(header) => header,
+        Err(error) => panic!("{}", error),
+    });
+
+    let mut target_stream = BitStream::open();
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOI);
+    if let Some(header) = &jfif_header {
+        jpg_writer::write_jfif_segments(&mut target_stream, header);
+    }
+    for comment in &settings.comments {
+        jpg_writer::write_comment_segments(&mut target_stream, comment);
+    }
+    jpg_writer::write_dqt_segment(&mut target_stream, &settings.luminance_q_table, 0);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOF0);
+    jpg_writer::write_dht_segment(&mut target_stream, 0, &huffman_dc, false);
+    jpg_writer::write_dht_segment(&mut target_stream, 2, &huffman_ac, true);
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::SOS);
+
+    target_stream.byte_stuffing(true);
+    // a single component scan isn't interleaved, so the blocks are simply written in order
+    image_data_writer::write_interleaved_image_data_to_stream(&mut target_stream, &[&dc_encoded], &[&ac_encoded]);
+    target_stream.byte_stuffing(false);
+
+    target_stream.pad_last_byte(true);
+
+    jpg_writer::write_segment_to_stream(&mut target_stream, image, jpg_writer::SegmentType::EOI);
+
+    target_stream
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use scoped_threadpool::Pool;
+
+    use crate::dct::DCTMode;
+    use crate::exif::Exif;
+    use crate::image::ColorMatrix;
+    use crate::jpg_reader;
+    use crate::jpg_writer::AdobeTransform;
+    use crate::parallel_dct;
+    use crate::ppm_parser::read_ppm_from_file;
+    use crate::quantization;
+    use crate::test_utils::prepare_image;
+
+    use super::{encode_dct_to_stream, encode_four_channel_dct_to_stream, encode_grayscale_dct_to_stream, EncoderSettings};
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    #[test]
+    fn test_encode_dct_to_stream_starts_with_soi_and_ends_with_eoi() {
+        let mut pool = get_pool();
+        let (image, (y_dct, cb_dct, cr_dct)) = prepare_image("test/test_16x16_color.ppm", ColorMatrix::Bt601, &mut pool);
+
+        let stream = encode_dct_to_stream(
+            &image,
+            y_dct,
+            cb_dct,
+            cr_dct,
+            &EncoderSettings::default(),
+            &mut pool,
+        );
+
+        let data = stream.data();
+        assert_eq!(&[0xff, 0xd8], &data[0..2]);
+        assert_eq!(&[0xff, 0xd9], &data[data.len() - 2..]);
+    }
+
+    #[test]
+    fn test_encode_four_channel_dct_to_stream_writes_exif_after_soi() {
+        let mut pool = get_pool();
+        let mut image = read_ppm_from_file("test/test_16x16_color.ppm");
+        image.rgb_to_cmyk();
+        image.cmyk_to_adobe();
+        let [c, m, y, k] = parallel_dct::dct_four_channels(&mut image, &DCTMode::Arai, &mut pool);
+        let settings = EncoderSettings {
+            exif: Some(Exif { orientation: Some(6), ..Default::default() }),
+            ..Default::default()
+        };
+
+        let stream = encode_four_channel_dct_to_stream(&image, c, m, y, k, AdobeTransform::Unknown, &settings, &mut pool);
+        let data = stream.data();
+        // SOI, then the Exif APP1 segment, then the Adobe APP14 segment
+        assert_eq!(&[0xff, 0xd8, 0xff, 0xe1], &data[0..4]);
+        assert_eq!(b"Exif\0\0", &data[6..12]);
+        let app14_start = 4 + ((data[4] as usize) << 8 | data[5] as usize);
+        assert_eq!(&[0xff, 0xee], &data[app14_start..app14_start + 2]);
+    }
+
+    /// Encode the 73x117 test image with the given settings and return the length of the stream.
+    fn encoded_length(settings: &EncoderSettings) -> usize {
+        let mut pool = get_pool();
+        let (image, (y_dct, cb_dct, cr_dct)) = prepare_image("test/test_73x117_color.ppm", ColorMatrix::Bt601, &mut pool);
+        encode_dct_to_stream(&image, y_dct, cb_dct, cr_dct, settings, &mut pool).data().len()
+    }
+
+    #[test]
+    fn test_encode_dct_to_stream_higher_quantization_is_smaller() {
+        let fine = encoded_length(&EncoderSettings {
+            luminance_q_table: quantization::uniform_q_table(1f32),
+            chrominance_q_table: quantization::uniform_q_table(1f32),
+            ..Default::default()
+        });
+        let coarse = encoded_length(&EncoderSettings {
+            luminance_q_table: quantization::uniform_q_table(32f32),
+            chrominance_q_table: quantization::uniform_q_table(32f32),
+            ..Default::default()
+        });
+
+        assert!(coarse < fine);
+    }
+
+    #[test]
+    fn test_encode_dct_to_stream_rdo_is_smaller() {
+        let rounded = encoded_length(&EncoderSettings::default());
+        let optimized = encoded_length(&EncoderSettings {
+            rdo_lambda: Some(20.0),
+            ..Default::default()
+        });
+
+        assert!(optimized < rounded);
+    }
+
+    #[test]
+    fn test_encode_dct_to_stream_masking_is_smaller() {
+        let unmasked = encoded_length(&EncoderSettings::default());
+        let masked = encoded_length(&EncoderSettings {
+            masking_strength: Some(0.5),
+            ..Default::default()
+        });
+
+        assert!(masked < unmasked);
+    }
+
+    #[test]
+    fn test_encode_grayscale_dct_to_stream_decodes() {
+        let mut pool = get_pool();
+        // a 21x13 gradient, so the image has to be padded
+        let gray: Vec<Vec<i16>> = (0..13).map(|y| (0..21).map(|x| (x * 11 + y * 2) as i16 - 128).collect()).collect();
+        let mut image = crate::image::create_grayscale_image(13, 21, gray.clone());
+        let dct = parallel_dct::dct_grayscale(&mut image, &DCTMode::Arai, &mut pool);
+        let stream = encode_grayscale_dct_to_stream(&image, dct, &EncoderSettings::default(), &mut pool);
+
+        let decoded = jpg_reader::decode_jpg(stream.data()).unwrap();
+        assert_eq!((21, 13), (decoded.width(), decoded.height()));
+        for (y, row) in gray.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                let (r, g, b) = decoded.pixel_at(x as u16, y as u16);
+                assert_eq!((r, r), (g, b));
+                assert!((r - (value + 128)).abs() <= 4, "{} differs from {} at {},{}", r, value + 128, x, y);
+            }
+        }
+    }
+}
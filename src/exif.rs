@@ -0,0 +1,469 @@
+/// The identifier at the start of the APP1 segment holding the Exif data.
+pub const EXIF_IDENTIFIER: &[u8; 6] = b"Exif\0\0";
+
+/// The byte order of the TIFF structure holding the Exif data.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ByteOrder {
+    /// "MM", Motorola byte order.
+    #[default]
+    BigEndian,
+    /// "II", Intel byte order.
+    LittleEndian,
+}
+
+impl ByteOrder {
+    fn u16_bytes(&self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::BigEndian => value.to_be_bytes(),
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+        }
+    }
+
+    fn u32_bytes(&self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::BigEndian => value.to_be_bytes(),
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+        }
+    }
+}
+
+/// A GPS position, stored in the GPS IFD.
+///
+/// # Attributes
+///
+/// * `latitude`: The latitude in degrees, negative for south.
+/// * `longitude`: The longitude in degrees, negative for west.
+/// * `altitude`: The altitude in metres, negative for below sea level, if known.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+impl std::str::FromStr for GpsPosition {
+    type Err = String;
+
+    /// Parse a position given as "latitude,longitude" or "latitude,longitude,altitude".
+    fn from_str(value: &str) -> Result<GpsPosition, String> {
+        let parts: Result<Vec<f64>, _> = value.split(',').map(|part| part.trim().parse::<f64>()).collect();
+        let position = match parts.as_deref() {
+            Ok([latitude, longitude]) => GpsPosition { latitude: *latitude, longitude: *longitude, altitude: None },
+            Ok([latitude, longitude, altitude]) => GpsPosition {
+                latitude: *latitude,
+                longitude: *longitude,
+                altitude: Some(*altitude),
+            },
+            _ => return Err(format!("Invalid GPS position {}!", value)),
+        };
+        if position.latitude.abs() > 90.0 || position.longitude.abs() > 180.0 {
+            return Err(format!("GPS position {} is out of range!", value));
+        }
+        Ok(position)
+    }
+}
+
+/// The Exif metadata to embed in a file. Only fields that are set are written.
+///
+/// # Attributes
+///
+/// * `byte_order`: The byte order to write the TIFF structure in.
+/// * `date_time`: The capture time, formatted as "YYYY:MM:DD HH:MM:SS".
+///   Written as DateTime, DateTimeOriginal and DateTimeDigitized.
+/// * `orientation`: The orientation of the image, 1 to 8 (1 being upright).
+/// * `make`: The manufacturer of the camera.
+/// * `model`: The model of the camera.
+/// * `copyright`: The copyright notice.
+/// * `gps`: The position the image was captured at.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exif {
+    pub byte_order: ByteOrder,
+    pub date_time: Option<String>,
+    pub orientation: Option<u16>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub copyright: Option<String>,
+    pub gps: Option<GpsPosition>,
+}
+
+/// The TIFF field types used by the writer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldType {
+    Byte = 1,
+    Ascii = 2,
+    Short = 3,
+    Long = 4,
+    Rational = 5,
+    Undefined = 7,
+}
+
+/// An entry of an IFD, with its value already in the file's byte order.
+///
+/// # Attributes
+///
+/// * `tag`: The tag identifying the field.
+/// * `field_type`: The type of the values.
+/// * `count`: The amount of values.
+/// * `value`: The values. Up to four bytes are stored in the entry itself, more in the IFD's data area.
+struct IfdEntry {
+    tag: u16,
+    field_type: FieldType,
+    count: u32,
+    value: Vec<u8>,
+}
+
+impl IfdEntry {
+    fn ascii(tag: u16, text: &str) -> IfdEntry {
+        let mut value = text.as_bytes().to_vec();
+        value.push(0);
+        IfdEntry { tag, field_type: FieldType::Ascii, count: value.len() as u32, value }
+    }
+
+    fn short(tag: u16, number: u16, byte_order: ByteOrder) -> IfdEntry {
+        IfdEntry { tag, field_type: FieldType::Short, count: 1, value: byte_order.u16_bytes(number).to_vec() }
+    }
+
+    fn long(tag: u16, number: u32, byte_order: ByteOrder) -> IfdEntry {
+        IfdEntry { tag, field_type: FieldType::Long, count: 1, value: byte_order.u32_bytes(number).to_vec() }
+    }
+
+    fn bytes(tag: u16, field_type: FieldType, bytes: &[u8]) -> IfdEntry {
+        IfdEntry { tag, field_type, count: bytes.len() as u32, value: bytes.to_vec() }
+    }
+
+    fn rationals(tag: u16, fractions: &[(u32, u32)], byte_order: ByteOrder) -> IfdEntry {
+        let value = fractions
+            .iter()
+            .flat_map(|(numerator, denominator)| {
+                [byte_order.u32_bytes(*numerator), byte_order.u32_bytes(*denominator)].concat()
+            })
+            .collect();
+        IfdEntry { tag, field_type: FieldType::Rational, count: fractions.len() as u32, value }
+    }
+
+    /// The amount of bytes the value takes up in the IFD's data area, padded to an even offset.
+    fn data_length(&self) -> usize {
+        if self.value.len() > 4 {
+            self.value.len() + self.value.len() % 2
+        } else {
+            0
+        }
+    }
+}
+
+// IFD0 tags
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_COPYRIGHT: u16 = 0x8298;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+// Exif IFD tags
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+// GPS IFD tags
+const TAG_GPS_VERSION: u16 = 0x0000;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+/// The size of the TIFF header: byte order, magic number 42 and the offset of IFD0.
+const TIFF_HEADER_LENGTH: usize = 8;
+
+impl Exif {
+    /// Build the content of the APP1 segment: the Exif identifier followed by a TIFF structure
+    /// with IFD0, the Exif sub-IFD and (if a position is set) the GPS IFD.
+    /// All offsets are relative to the start of the TIFF header, as the Exif specification requires.
+    ///
+    /// # Examples
+    /// ```
+    /// let exif = Exif { orientation: Some(6), ..Default::default() };
+    /// let data = exif.to_bytes();
+    /// ```
+    ///
+    /// # Panics
+    /// * If `date_time` isn't formatted as "YYYY:MM:DD HH:MM:SS".
+    /// * If `orientation` isn't between 1 and 8.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let order = self.byte_order;
+        if let Some(date_time) = &self.date_time {
+            if !is_valid_date_time(date_time) {
+                panic!("Invalid Exif date {}, expected YYYY:MM:DD HH:MM:SS!", date_time);
+            }
+        }
+        if let Some(orientation) = self.orientation {
+            if !(1..=8).contains(&orientation) {
+                panic!("Invalid Exif orientation {}!", orientation);
+            }
+        }
+
+        let mut exif_entries = vec![IfdEntry::bytes(TAG_EXIF_VERSION, FieldType::Undefined, b"0232")];
+        if let Some(date_time) = &self.date_time {
+            exif_entries.push(IfdEntry::ascii(TAG_DATE_TIME_ORIGINAL, date_time));
+            exif_entries.push(IfdEntry::ascii(TAG_DATE_TIME_DIGITIZED, date_time));
+        }
+        let gps_entries = self.gps.map(|gps| gps_entries(&gps, order));
+
+        // the pointers to the sub-IFDs are filled in once the size of IFD0 is known
+        let mut ifd0_entries = vec![];
+        if let Some(make) = &self.make {
+            ifd0_entries.push(IfdEntry::ascii(TAG_MAKE, make));
+        }
+        if let Some(model) = &self.model {
+            ifd0_entries.push(IfdEntry::ascii(TAG_MODEL, model));
+        }
+        if let Some(orientation) = self.orientation {
+            ifd0_entries.push(IfdEntry::short(TAG_ORIENTATION, orientation, order));
+        }
+        if let Some(date_time) = &self.date_time {
+            ifd0_entries.push(IfdEntry::ascii(TAG_DATE_TIME, date_time));
+        }
+        if let Some(copyright) = &self.copyright {
+            ifd0_entries.push(IfdEntry::ascii(TAG_COPYRIGHT, copyright));
+        }
+        ifd0_entries.push(IfdEntry::long(TAG_EXIF_IFD, 0, order));
+        if gps_entries.is_some() {
+            ifd0_entries.push(IfdEntry::long(TAG_GPS_IFD, 0, order));
+        }
+
+        let exif_offset = TIFF_HEADER_LENGTH + ifd_length(&ifd0_entries);
+        let gps_offset = exif_offset + ifd_length(&exif_entries);
+        for entry in ifd0_entries.iter_mut() {
+            match entry.tag {
+                TAG_EXIF_IFD => entry.value = order.u32_bytes(exif_offset as u32).to_vec(),
+                TAG_GPS_IFD => entry.value = order.u32_bytes(gps_offset as u32).to_vec(),
+                _ => (),
+            }
+        }
+
+        let mut tiff = match order {
+            ByteOrder::BigEndian => b"MM".to_vec(),
+            ByteOrder::LittleEndian => b"II".to_vec(),
+        };
+        tiff.extend_from_slice(&order.u16_bytes(42));
+        tiff.extend_from_slice(&order.u32_bytes(TIFF_HEADER_LENGTH as u32));
+        write_ifd(&mut tiff, ifd0_entries, order);
+        write_ifd(&mut tiff, exif_entries, order);
+        if let Some(gps_entries) = gps_entries {
+            write_ifd(&mut tiff, gps_entries, order);
+        }
+
+        let mut data = EXIF_IDENTIFIER.to_vec();
+        data.append(&mut tiff);
+        data
+    }
+}
+
+/// Check that a date is formatted as "YYYY:MM:DD HH:MM:SS", as Exif requires.
+fn is_valid_date_time(date_time: &str) -> bool {
+    let bytes = date_time.as_bytes();
+    bytes.len() == 19
+        && bytes.iter().enumerate().all(|(index, byte)| match index {
+            4 | 7 | 13 | 16 => *byte == b':',
+            10 => *byte == b' ',
+            _ => byte.is_ascii_digit(),
+        })
+}
+
+/// Build the entries of the GPS IFD for a position.
+fn gps_entries(gps: &GpsPosition, order: ByteOrder) -> Vec<IfdEntry> {
+    let mut entries = vec![
+        IfdEntry::bytes(TAG_GPS_VERSION, FieldType::Byte, &[2, 3, 0, 0]),
+        IfdEntry::ascii(TAG_GPS_LATITUDE_REF, if gps.latitude < 0.0 { "S" } else { "N" }),
+        IfdEntry::rationals(TAG_GPS_LATITUDE, &degrees_to_rationals(gps.latitude), order),
+        IfdEntry::ascii(TAG_GPS_LONGITUDE_REF, if gps.longitude < 0.0 { "W" } else { "E" }),
+        IfdEntry::rationals(TAG_GPS_LONGITUDE, &degrees_to_rationals(gps.longitude), order),
+    ];
+    if let Some(altitude) = gps.altitude {
+        entries.push(IfdEntry::bytes(TAG_GPS_ALTITUDE_REF, FieldType::Byte, &[u8::from(altitude < 0.0)]));
+        entries.push(IfdEntry::rationals(TAG_GPS_ALTITUDE, &[((altitude.abs() * 100.0).round() as u32, 100)], order));
+    }
+    entries
+}
+
+/// Split an angle into degrees, minutes and seconds (with a precision of a thousandth of a second).
+fn degrees_to_rationals(angle: f64) -> [(u32, u32); 3] {
+    let total_seconds = (angle.abs() * 3600.0 * 1000.0).round() as u64;
+    let degrees = total_seconds / (3600 * 1000);
+    let minutes = total_seconds / (60 * 1000) % 60;
+    let seconds = total_seconds % (60 * 1000);
+    [(degrees as u32, 1), (minutes as u32, 1), (seconds as u32, 1000)]
+}
+
+/// The amount of bytes an IFD with these entries takes up, including its data area.
+fn ifd_length(entries: &[IfdEntry]) -> usize {
+    2 + 12 * entries.len() + 4 + entries.iter().map(IfdEntry::data_length).sum::<usize>()
+}
+
+/// Append an IFD to the TIFF structure, followed by the values that don't fit into its entries.
+/// The entries are sorted by tag, as TIFF requires. There's never a next IFD, as no thumbnail is written.
+fn write_ifd(tiff: &mut Vec<u8>, mut entries: Vec<IfdEntry>, order: ByteOrder) {
+    entries.sort_by_key(|entry| entry.tag);
+    let mut data_offset = tiff.len() + 2 + 12 * entries.len() + 4;
+    let mut data_area = vec![];
+
+    tiff.extend_from_slice(&order.u16_bytes(entries.len() as u16));
+    for entry in &entries {
+        tiff.extend_from_slice(&order.u16_bytes(entry.tag));
+        tiff.extend_from_slice(&order.u16_bytes(entry.field_type as u16));
+        tiff.extend_from_slice(&order.u32_bytes(entry.count));
+        if entry.value.len() <= 4 {
+            let mut value = entry.value.clone();
+            value.resize(4, 0);
+            tiff.extend_from_slice(&value);
+        } else {
+            tiff.extend_from_slice(&order.u32_bytes(data_offset as u32));
+            data_area.extend_from_slice(&entry.value);
+            data_area.resize(data_area.len() + entry.value.len() % 2, 0);
+            data_offset += entry.data_length();
+        }
+    }
+    // no next IFD
+    tiff.extend_from_slice(&[0; 4]);
+    tiff.append(&mut data_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteOrder, Exif, GpsPosition, EXIF_IDENTIFIER};
+
+    /// Read a 16 or 32 bit value from the TIFF structure.
+    fn read(tiff: &[u8], offset: usize, length: usize, order: ByteOrder) -> u32 {
+        let bytes = &tiff[offset..offset + length];
+        let bytes: Vec<u8> = match order {
+            ByteOrder::BigEndian => bytes.to_vec(),
+            ByteOrder::LittleEndian => bytes.iter().rev().copied().collect(),
+        };
+        bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u32)
+    }
+
+    /// Find the entry with the given tag in the IFD at `ifd_offset`.
+    /// Returns the field type, the count and the raw value (read from the data area if needed).
+    fn find_entry(tiff: &[u8], ifd_offset: usize, tag: u16, order: ByteOrder) -> Option<(u16, u32, Vec<u8>)> {
+        let count = read(tiff, ifd_offset, 2, order) as usize;
+        (0..count).map(|index| ifd_offset + 2 + 12 * index).find_map(|entry| {
+            if read(tiff, entry, 2, order) != tag as u32 {
+                return None;
+            }
+            let field_type = read(tiff, entry + 2, 2, order) as u16;
+            let value_count = read(tiff, entry + 4, 4, order);
+            let size = match field_type {
+                3 => 2,
+                4 => 4,
+                5 => 8,
+                _ => 1,
+            } * value_count as usize;
+            let value_offset = if size <= 4 { entry + 8 } else { read(tiff, entry + 8, 4, order) as usize };
+            Some((field_type, value_count, tiff[value_offset..value_offset + size].to_vec()))
+        })
+    }
+
+    #[test]
+    fn test_orientation_only_big_endian() {
+        let exif = Exif {
+            orientation: Some(6),
+            ..Default::default()
+        };
+        let data = exif.to_bytes();
+        let mut expected = EXIF_IDENTIFIER.to_vec();
+        expected.extend_from_slice(&[
+            b'M', b'M', 0, 42, 0, 0, 0, 8, // header
+            0, 2, // two entries
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // orientation
+            0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 38, // Exif IFD pointer
+            0, 0, 0, 0, // no next IFD
+            0, 1, // Exif IFD: one entry
+            0x90, 0x00, 0, 7, 0, 0, 0, 4, b'0', b'2', b'3', b'2', // Exif version
+            0, 0, 0, 0, // no next IFD
+        ]);
+        assert_eq!(expected, data);
+    }
+
+    #[test]
+    fn test_orientation_only_little_endian() {
+        let exif = Exif {
+            byte_order: ByteOrder::LittleEndian,
+            orientation: Some(6),
+            ..Default::default()
+        };
+        let tiff = &exif.to_bytes()[6..];
+        assert_eq!(&[b'I', b'I', 42, 0, 8, 0, 0, 0], &tiff[0..8]);
+        assert_eq!(Some((3, 1, vec![6, 0])), find_entry(tiff, 8, 0x0112, ByteOrder::LittleEndian));
+    }
+
+    #[test]
+    fn test_all_fields() {
+        for order in [ByteOrder::BigEndian, ByteOrder::LittleEndian] {
+            let exif = Exif {
+                byte_order: order,
+                date_time: Some("2024:02:29 13:37:00".to_string()),
+                orientation: Some(1),
+                make: Some("Maker".to_string()),
+                model: Some("Camera 3000".to_string()),
+                copyright: Some("(c) Someone".to_string()),
+                gps: Some(GpsPosition { latitude: 52.5, longitude: -13.25, altitude: Some(-12.5) }),
+            };
+            let data = exif.to_bytes();
+            let tiff = &data[6..];
+
+            assert_eq!(Some((2, 6, b"Maker\0".to_vec())), find_entry(tiff, 8, 0x010f, order));
+            assert_eq!(Some((2, 12, b"Camera 3000\0".to_vec())), find_entry(tiff, 8, 0x0110, order));
+            assert_eq!(Some((2, 20, b"2024:02:29 13:37:00\0".to_vec())), find_entry(tiff, 8, 0x0132, order));
+            assert_eq!(Some((2, 12, b"(c) Someone\0".to_vec())), find_entry(tiff, 8, 0x8298, order));
+
+            let (_, _, exif_pointer) = find_entry(tiff, 8, 0x8769, order).unwrap();
+            let exif_offset = read(&exif_pointer, 0, 4, order) as usize;
+            assert_eq!(Some((2, 20, b"2024:02:29 13:37:00\0".to_vec())), find_entry(tiff, exif_offset, 0x9003, order));
+            assert_eq!(Some((7, 4, b"0232".to_vec())), find_entry(tiff, exif_offset, 0x9000, order));
+
+            let (_, _, gps_pointer) = find_entry(tiff, 8, 0x8825, order).unwrap();
+            let gps_offset = read(&gps_pointer, 0, 4, order) as usize;
+            assert_eq!(Some((2, 2, b"N\0".to_vec())), find_entry(tiff, gps_offset, 0x0001, order));
+            assert_eq!(Some((2, 2, b"W\0".to_vec())), find_entry(tiff, gps_offset, 0x0003, order));
+            assert_eq!(Some((1, 1, vec![1])), find_entry(tiff, gps_offset, 0x0005, order));
+            let (_, count, longitude) = find_entry(tiff, gps_offset, 0x0004, order).unwrap();
+            assert_eq!(3, count);
+            // 13° 15' 0"
+            let values: Vec<u32> = (0..6).map(|index| read(&longitude, 4 * index, 4, order)).collect();
+            assert_eq!(vec![13, 1, 15, 1, 0, 1000], values);
+            let (_, _, altitude) = find_entry(tiff, gps_offset, 0x0006, order).unwrap();
+            assert_eq!((1250, 100), (read(&altitude, 0, 4, order), read(&altitude, 4, 4, order)));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_date_time() {
+        Exif {
+            date_time: Some("2024-02-29 13:37".to_string()),
+            ..Default::default()
+        }
+        .to_bytes();
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_invalid_orientation() {
+        Exif {
+            orientation: Some(9),
+            ..Default::default()
+        }
+        .to_bytes();
+    }
+
+    #[test]
+    fn test_gps_position_from_str() {
+        assert_eq!(Ok(GpsPosition { latitude: 1.5, longitude: -2.0, altitude: None }), "1.5,-2".parse());
+        assert_eq!(
+            Ok(GpsPosition { latitude: 1.5, longitude: -2.0, altitude: Some(100.0) }),
+            "1.5, -2, 100".parse()
+        );
+        assert!("1.5".parse::<GpsPosition>().is_err());
+        assert!("91,0".parse::<GpsPosition>().is_err());
+    }
+}
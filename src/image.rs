@@ -4,9 +4,12 @@ use std::sync::mpsc::{self, Receiver};
 use std::thread::{self, JoinHandle};
 
 use na::{Matrix3, SMatrix, Vector3};
+use scoped_threadpool::Pool;
 
 use crate::downsample::downsample_channel;
+use crate::parallel_color_conversion;
 use crate::parallel_downsample;
+use crate::resize::{resize_channel, ResizeFilter};
 use crate::utils::THREAD_COUNT;
 
 /// Image data structure for parsed image files
@@ -16,6 +19,11 @@ use crate::utils::THREAD_COUNT;
 /// * `height`: Image height in pixels
 /// * `width`: Image width in pixels
 /// * `channel`: The three channels for pixel data, either RGB or YCbCr in this order 1-3
+/// * `channel4`: The fourth channel of CMYK (K) and YCCK images, empty for all others.
+///   For those, channels 1-3 hold C, M and Y or Y, Cb and Cr.
+///   Grayscale images only have the first channel, 2 and 3 are empty.
+/// * `alpha`: The opacity of RGBA images (0 transparent, 255 opaque), empty for all others.
+///   It has to be removed with `apply_alpha_policy` before converting the colour space.
 /// * `downsample_factors`: The factor of downsampling for the corresponding channels, 1 by default.
 /// E.g. for 4:2:0 the downsampling factor for Cb and Cr is 2, because we only keep every second value
 /// * `downsampled_vertically`: True if two rows have been combined (e.g. for 4:2:0)
@@ -26,43 +34,111 @@ pub struct Image {
     channel1: Vec<Vec<i16>>,
     channel2: Vec<Vec<i16>>,
     channel3: Vec<Vec<i16>>,
+    channel4: Vec<Vec<i16>>,
+    alpha: Vec<Vec<i16>>,
     y_downsample_factor: usize,
     cb_downsample_factor: usize,
     cr_downsample_factor: usize,
     downsampled_vertically: bool,
 }
 
-const TRANSFORM_RGB_YCBCR_MATRIX: Matrix3<f32> = Matrix3::new(
-    0.299, 0.587, 0.114, -0.1687, -0.3312, 0.5, 0.5, -0.4186, -0.0813,
-);
+/// The offset between the level shifted samples stored after conversion to YCbCr
+/// (and used for the DCT) and the unsigned 8 bit samples, as well as the centre of Cb and Cr.
+const LEVEL_SHIFT: f32 = 128.0;
+
+/// The matrix used to convert between RGB and YCbCr.
+/// All of them are used with full range (0..=255) samples and chroma centred at 128, as in JFIF.
+/// Note that JFIF prescribes BT.601, so decoders will assume that matrix for all files.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorMatrix {
+    /// ITU-R BT.601, as used by JFIF.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, used for HD video.
+    Bt709,
+    /// ITU-R BT.2020 (non-constant luminance), used for UHD video.
+    Bt2020,
+}
+
+impl ColorMatrix {
+    /// Get the red and blue luma coefficients Kr and Kb.
+    fn luma_coefficients(&self) -> (f32, f32) {
+        match self {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+            ColorMatrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+
+    /// Get the matrix converting RGB values to Y, Cb and Cr, with Cb and Cr centred at 0.
+    pub fn rgb_to_ycbcr_matrix(&self) -> Matrix3<f32> {
+        let (kr, kb) = self.luma_coefficients();
+        let kg = 1.0 - kr - kb;
+        let cb_scale = 2.0 * (1.0 - kb);
+        let cr_scale = 2.0 * (1.0 - kr);
+        Matrix3::new(
+            kr, kg, kb,
+            -kr / cb_scale, -kg / cb_scale, 0.5,
+            0.5, -kg / cr_scale, -kb / cr_scale,
+        )
+    }
+
+    /// Get the matrix converting Y, Cb and Cr values (with Cb and Cr centred at 0) to RGB values.
+    /// This is the inverse of `rgb_to_ycbcr_matrix`.
+    pub fn ycbcr_to_rgb_matrix(&self) -> Matrix3<f32> {
+        let (kr, kb) = self.luma_coefficients();
+        let kg = 1.0 - kr - kb;
+        let cb_scale = 2.0 * (1.0 - kb);
+        let cr_scale = 2.0 * (1.0 - kr);
+        Matrix3::new(
+            1.0, 0.0, cr_scale,
+            1.0, -kb * cb_scale / kg, -kr * cr_scale / kg,
+            1.0, cb_scale, 0.0,
+        )
+    }
+}
+
+impl std::str::FromStr for ColorMatrix {
+    type Err = String;
 
-const RGB_TO_YCBCR_OFFSET: Vector3<f32> = Vector3::new(0.0, 127.0, 127.0);
-const RGB_HALF_OFFSET: Vector3<f32> = Vector3::new(127.0, 127.0, 127.0);
+    fn from_str(value: &str) -> Result<ColorMatrix, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "bt601" => Ok(ColorMatrix::Bt601),
+            "bt709" => Ok(ColorMatrix::Bt709),
+            "bt2020" => Ok(ColorMatrix::Bt2020),
+            _ => Err(format!("Unknown colour matrix {}!", value)),
+        }
+    }
+}
 
 /// Convert an RGB value to a YCbCr value.
+/// The result is level shifted by -128 (so all channels are centred at 0, as needed for the DCT)
+/// and clamped to -128..=127.
 ///
 /// # Arguments
 ///
 /// * `r`: The input's "Red" channel
 /// * `g`: The input's "Green" channel
 /// * `b`: The input's "Blue" channel
+/// * `matrix`: The conversion matrix, see `ColorMatrix::rgb_to_ycbcr_matrix`.
 ///
 /// # Examples
 ///
 /// ```
-/// let color = convert_rgb_values_to_ycbcr(0, 0, 0);
-/// assert_eq!(color, (0, 127, 127))
+/// let color = convert_rgb_values_to_ycbcr(0, 0, 0, &ColorMatrix::Bt601.rgb_to_ycbcr_matrix());
+/// assert_eq!(color, (-128, 0, 0))
 /// ```
 ///
 /// # Panics
 ///
 /// * Error casting back from floating point to integer numbers.
-fn convert_rgb_values_to_ycbcr(r: i16, g: i16, b: i16) -> (i16, i16, i16) {
-    let mut result = TRANSFORM_RGB_YCBCR_MATRIX * Vector3::new(r as f32, g as f32, b as f32);
+fn convert_rgb_values_to_ycbcr(r: i16, g: i16, b: i16, matrix: &Matrix3<f32>) -> (i16, i16, i16) {
+    let mut result = matrix * Vector3::new(r as f32, g as f32, b as f32);
 
-    result += RGB_TO_YCBCR_OFFSET;
-    result -= RGB_HALF_OFFSET;
-    let result_as_int = result.map(|value| value.round()).try_cast::<i16>();
+    result[0] -= LEVEL_SHIFT;
+    let result_as_int = result
+        .map(|value| value.round().clamp(-LEVEL_SHIFT, LEVEL_SHIFT - 1.0))
+        .try_cast::<i16>();
 
     match result_as_int {
         Some(value) => (value[0], value[1], value[2]),
@@ -70,6 +146,116 @@ fn convert_rgb_values_to_ycbcr(r: i16, g: i16, b: i16) -> (i16, i16, i16) {
     }
 }
 
+/// Convert a level shifted YCbCr value (as returned by `convert_rgb_values_to_ycbcr`) to an RGB value,
+/// clamped to 0..=255.
+///
+/// # Arguments
+///
+/// * `y`: The input's "Y" channel
+/// * `cb`: The input's "Cb" channel
+/// * `cr`: The input's "Cr" channel
+/// * `matrix`: The conversion matrix, see `ColorMatrix::ycbcr_to_rgb_matrix`.
+fn convert_ycbcr_values_to_rgb(y: i16, cb: i16, cr: i16, matrix: &Matrix3<f32>) -> (i16, i16, i16) {
+    let result = matrix * Vector3::new(y as f32 + LEVEL_SHIFT, cb as f32, cr as f32);
+    let result = result.map(|value| value.round().clamp(0.0, 255.0) as i16);
+    (result[0], result[1], result[2])
+}
+
+/// The colour space an image is encoded in.
+/// JFIF files are always YCbCr, the others are marked with an Adobe APP14 segment.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    /// Y, Cb and Cr, as used by JFIF.
+    #[default]
+    YCbCr,
+    /// C, M, Y and K, stored inverted as Adobe applications do.
+    Cmyk,
+    /// C, M and Y converted to Y, Cb and Cr, plus an inverted K channel.
+    Ycck,
+}
+
+impl std::str::FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ColorSpace, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "ycbcr" => Ok(ColorSpace::YCbCr),
+            "cmyk" => Ok(ColorSpace::Cmyk),
+            "ycck" => Ok(ColorSpace::Ycck),
+            _ => Err(format!("Unknown colour space {}!", value)),
+        }
+    }
+}
+
+/// What to do with the alpha channel of RGBA images, as JPEG can't store it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaPolicy {
+    /// Blend the image over an opaque background of this R, G and B colour.
+    Composite(u8, u8, u8),
+    /// Multiply the colour with the alpha, then drop it. This is the same as compositing over black.
+    Premultiply,
+    /// Keep the colour as it is and encode the alpha as a separate grayscale image.
+    Sidecar,
+}
+
+impl Default for AlphaPolicy {
+    /// Composite over white.
+    fn default() -> AlphaPolicy {
+        AlphaPolicy::Composite(255, 255, 255)
+    }
+}
+
+impl std::str::FromStr for AlphaPolicy {
+    type Err = String;
+
+    /// Parse a policy given as "composite" (over white), "composite:RRGGBB" (over a hex colour),
+    /// "premultiply" or "sidecar".
+    fn from_str(value: &str) -> Result<AlphaPolicy, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "composite" => Ok(AlphaPolicy::default()),
+            "premultiply" => Ok(AlphaPolicy::Premultiply),
+            "sidecar" => Ok(AlphaPolicy::Sidecar),
+            policy => {
+                let colour = policy
+                    .strip_prefix("composite:")
+                    .map(|hex| hex.trim_start_matches('#'))
+                    .filter(|hex| hex.len() == 6)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                match colour {
+                    Some(rgb) => Ok(AlphaPolicy::Composite((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+                    None => Err(format!("Unknown alpha policy {}!", value)),
+                }
+            }
+        }
+    }
+}
+
+/// Convert an RGB value to the amounts of C, M, Y and K ink (0 being no ink, 255 full coverage),
+/// using as much K as possible.
+/// This is a naive conversion without a colour profile, meant for data that doesn't come in CMYK already.
+///
+/// # Arguments
+///
+/// * `r`: The input's "Red" channel
+/// * `g`: The input's "Green" channel
+/// * `b`: The input's "Blue" channel
+///
+/// # Examples
+///
+/// ```
+/// let color = convert_rgb_values_to_cmyk(255, 0, 0);
+/// assert_eq!(color, (0, 255, 255, 0))
+/// ```
+fn convert_rgb_values_to_cmyk(r: i16, g: i16, b: i16) -> (i16, i16, i16, i16) {
+    let k = 255 - r.max(g).max(b).clamp(0, 255);
+    if k == 255 {
+        return (0, 0, 0, 255);
+    }
+    let white = (255 - k) as i32;
+    let ink = |value: i16| (((255 - value.clamp(0, 255) - k) as i32 * 255 + white / 2) / white) as i16;
+    (ink(r), ink(g), ink(b), k)
+}
+
 /// Create an image.
 ///
 /// # Arguments
@@ -96,6 +282,121 @@ pub fn create_image(
     }
 }
 
+/// Create a CMYK image.
+///
+/// # Arguments
+///
+/// * height: The image height.
+/// * width: The image width.
+/// * cyan: The amount of cyan ink, 0..=255.
+/// * magenta: The amount of magenta ink, 0..=255.
+/// * yellow: The amount of yellow ink, 0..=255.
+/// * black: The amount of black ink, 0..=255.
+pub fn create_cmyk_image(
+    height: u16,
+    width: u16,
+    cyan: Vec<Vec<i16>>,
+    magenta: Vec<Vec<i16>>,
+    yellow: Vec<Vec<i16>>,
+    black: Vec<Vec<i16>>,
+) -> Image {
+    Image {
+        height,
+        width,
+        channel1: cyan,
+        channel2: magenta,
+        channel3: yellow,
+        channel4: black,
+        ..Default::default()
+    }
+}
+
+/// Create an RGB image with an alpha channel.
+///
+/// # Arguments
+///
+/// * height: The image height.
+/// * width: The image width.
+/// * red: The red channel, 0..=255.
+/// * green: The green channel, 0..=255.
+/// * blue: The blue channel, 0..=255.
+/// * alpha: The opacity, 0 (transparent) ..=255 (opaque).
+pub fn create_rgba_image(
+    height: u16,
+    width: u16,
+    red: Vec<Vec<i16>>,
+    green: Vec<Vec<i16>>,
+    blue: Vec<Vec<i16>>,
+    alpha: Vec<Vec<i16>>,
+) -> Image {
+    Image {
+        height,
+        width,
+        channel1: red,
+        channel2: green,
+        channel3: blue,
+        alpha,
+        ..Default::default()
+    }
+}
+
+/// Create a grayscale image with a single, level shifted channel, ready for the DCT.
+///
+/// # Arguments
+///
+/// * height: The image height.
+/// * width: The image width.
+/// * gray: The brightness, level shifted by -128 like `rgb_to_ycbcr` does for Y.
+pub fn create_grayscale_image(height: u16, width: u16, gray: Vec<Vec<i16>>) -> Image {
+    Image {
+        height,
+        width,
+        channel1: gray,
+        ..Default::default()
+    }
+}
+
+/// Create a YCbCr image with 4:2:0 subsampled chroma, such as a decoded video frame.
+/// The image is in the state `rgb_to_ycbcr` and `downsample(4, 2, 0)` would leave it in, ready for the DCT.
+///
+/// # Arguments
+///
+/// * height: The image height.
+/// * width: The image width.
+/// * luma: The Y channel, level shifted by -128.
+/// * cb: The Cb channel, level shifted by -128, with half the width and height of Y (rounded up).
+///   Longer rows are allowed, as `downsample` pads them to full segments.
+/// * cr: The Cr channel, like `cb`.
+///
+/// # Panics
+///
+/// * If the chroma channels don't have half the size of the Y channel.
+pub fn create_ycbcr_420_image(
+    height: u16,
+    width: u16,
+    luma: Vec<Vec<i16>>,
+    cb: Vec<Vec<i16>>,
+    cr: Vec<Vec<i16>>,
+) -> Image {
+    let chroma_size = (height.div_ceil(2) as usize, width.div_ceil(2) as usize);
+    for channel in [&cb, &cr] {
+        if channel.len() != chroma_size.0 || channel.iter().any(|row| row.len() < chroma_size.1) {
+            panic!("Chroma channels of a 4:2:0 image must have half the size of the Y channel!");
+        }
+    }
+    Image {
+        height,
+        width,
+        channel1: luma,
+        channel2: cb,
+        channel3: cr,
+        cb_downsample_factor: 2,
+        cr_downsample_factor: 2,
+        downsampled_vertically: true,
+        ..Default::default()
+    }
+}
+
 /// Convert one channel into a Vec of 8x8 matrices containing its data.
 /// This assumes the channel's dimensions can be divided by 8!
 ///
@@ -253,7 +554,7 @@ impl Image {
         )
     }
 
-    /// Convert this image from RGB to YCbCr color space.
+    /// Convert this image from RGB to YCbCr color space, using the BT.601 matrix of JFIF.
     ///
     /// # Arguments
     ///
@@ -271,27 +572,326 @@ impl Image {
     /// * Method is called after the image was downsampled (the different channels aren't the same size)
     /// * Internal error when calling convert_rgb_values_to_ycbcr
     pub fn rgb_to_ycbcr(&mut self) {
-        if self.y_downsample_factor != 1
-            || self.cb_downsample_factor != 1
-            || self.cr_downsample_factor != 1
-            || self.downsampled_vertically
-        {
+        self.rgb_to_ycbcr_with_matrix(ColorMatrix::Bt601)
+    }
+
+    /// Convert this image from RGB to YCbCr color space, using the given matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `matrix`: The colour matrix to convert with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let image = read_ppm_from_file("../path/to/image.ppm");
+    /// image.rgb_to_ycbcr_with_matrix(ColorMatrix::Bt709)
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// * Method is called after the image was downsampled (the different channels aren't the same size)
+    /// * Internal error when calling convert_rgb_values_to_ycbcr
+    pub fn rgb_to_ycbcr_with_matrix(&mut self, matrix: ColorMatrix) {
+        if self.is_downsampled() {
             panic!("rgb_to_ycbcr called after downsampling!")
         }
+        let matrix = matrix.rgb_to_ycbcr_matrix();
         for row in 0..self.channel1.len() {
             for col in 0..self.channel1[row].len() {
-                let (y, cr, cb) = convert_rgb_values_to_ycbcr(
+                let (y, cb, cr) = convert_rgb_values_to_ycbcr(
                     self.channel1[row][col],
                     self.channel2[row][col],
                     self.channel3[row][col],
+                    &matrix,
                 );
                 self.channel1[row][col] = y;
-                self.channel2[row][col] = cr;
-                self.channel3[row][col] = cb;
+                self.channel2[row][col] = cb;
+                self.channel3[row][col] = cr;
             }
         }
     }
 
+    /// Convert this image from RGB to YCbCr color space, using the given matrix.
+    /// Uses fixed point lookup tables instead of floating point math and converts the rows in parallel.
+    /// The result may differ from `rgb_to_ycbcr_with_matrix` by one where a value is very close to .5.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `matrix`: The colour matrix to convert with.
+    /// * `pool`: The thread pool to use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let image = read_ppm_from_file("../path/to/image.ppm");
+    /// image.rgb_to_ycbcr_parallel(ColorMatrix::Bt601, &mut pool)
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// * Method is called after the image was downsampled (the different channels aren't the same size)
+    pub fn rgb_to_ycbcr_parallel(&mut self, matrix: ColorMatrix, pool: &mut Pool) {
+        if self.is_downsampled() {
+            panic!("rgb_to_ycbcr called after downsampling!")
+        }
+        parallel_color_conversion::rgb_to_ycbcr(&mut self.channel1, &mut self.channel2, &mut self.channel3, matrix, pool);
+    }
+
+    /// Remove the alpha channel of an RGBA image according to the policy, so the image can be
+    /// converted and encoded like any RGB image. Images without alpha are left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `policy`: What to do with the alpha channel.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let sidecar = image.apply_alpha_policy(AlphaPolicy::Sidecar);
+    /// image.rgb_to_ycbcr();
+    /// ```
+    ///
+    /// Returns the alpha channel as a grayscale image for `AlphaPolicy::Sidecar`, `None` otherwise.
+    ///
+    /// # Panics
+    ///
+    /// * Method is called after the image was downsampled
+    pub fn apply_alpha_policy(&mut self, policy: AlphaPolicy) -> Option<Image> {
+        if self.alpha.is_empty() {
+            return None;
+        }
+        if self.is_downsampled() {
+            panic!("apply_alpha_policy called on a downsampled image!")
+        }
+        let alpha = std::mem::take(&mut self.alpha);
+        let background = match policy {
+            AlphaPolicy::Composite(r, g, b) => [r, g, b],
+            AlphaPolicy::Premultiply => [0, 0, 0],
+            AlphaPolicy::Sidecar => {
+                let gray = alpha.iter().map(|row| row.iter().map(|value| value.clamp(&0, &255) - 128).collect()).collect();
+                return Some(create_grayscale_image(self.height, self.width, gray));
+            }
+        };
+        for (channel, background) in [&mut self.channel1, &mut self.channel2, &mut self.channel3].into_iter().zip(background) {
+            for (row, alpha_row) in channel.iter_mut().zip(&alpha) {
+                for (value, alpha) in row.iter_mut().zip(alpha_row) {
+                    let alpha = (*alpha).clamp(0, 255) as i32;
+                    let blended = *value as i32 * alpha + background as i32 * (255 - alpha);
+                    *value = ((blended + 127) / 255) as i16;
+                }
+            }
+        }
+        None
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        !self.alpha.is_empty()
+    }
+
+    /// Convert this image from RGB to CMYK, see `convert_rgb_values_to_cmyk`.
+    /// Afterwards, the image has four channels holding the amounts of ink.
+    ///
+    /// # Panics
+    ///
+    /// * Method is called after the image was downsampled or on an image that already has four channels
+    pub fn rgb_to_cmyk(&mut self) {
+        if self.is_downsampled() || self.channel_count() == 4 {
+            panic!("rgb_to_cmyk called on a downsampled or CMYK image!")
+        }
+        self.channel4 = self.channel1.clone();
+        for row in 0..self.channel1.len() {
+            for col in 0..self.channel1[row].len() {
+                let (c, m, y, k) = convert_rgb_values_to_cmyk(
+                    self.channel1[row][col],
+                    self.channel2[row][col],
+                    self.channel3[row][col],
+                );
+                self.channel1[row][col] = c;
+                self.channel2[row][col] = m;
+                self.channel3[row][col] = y;
+                self.channel4[row][col] = k;
+            }
+        }
+    }
+
+    /// Prepare this CMYK image for encoding as Adobe CMYK.
+    /// Adobe applications store inverted values (255 meaning no ink), so all channels are inverted,
+    /// then level shifted by -128 like `rgb_to_ycbcr` does.
+    ///
+    /// # Panics
+    ///
+    /// * Method is called on an image without four channels
+    pub fn cmyk_to_adobe(&mut self) {
+        if self.channel_count() != 4 {
+            panic!("cmyk_to_adobe called on an image without four channels!")
+        }
+        for channel in [&mut self.channel1, &mut self.channel2, &mut self.channel3, &mut self.channel4] {
+            for value in channel.iter_mut().flatten() {
+                *value = 127 - (*value).clamp(0, 255);
+            }
+        }
+    }
+
+    /// Convert this CMYK image to YCCK, as Adobe applications (and libjpeg) do:
+    /// The C, M and Y channels are converted like R, G and B with the BT.601 matrix,
+    /// the K channel is inverted. All channels are level shifted by -128 like `rgb_to_ycbcr` does.
+    ///
+    /// # Panics
+    ///
+    /// * Method is called on an image without four channels
+    pub fn cmyk_to_ycck(&mut self) {
+        if self.channel_count() != 4 {
+            panic!("cmyk_to_ycck called on an image without four channels!")
+        }
+        let matrix = ColorMatrix::Bt601.rgb_to_ycbcr_matrix();
+        for row in 0..self.channel1.len() {
+            for col in 0..self.channel1[row].len() {
+                let (y, cb, cr) = convert_rgb_values_to_ycbcr(
+                    self.channel1[row][col],
+                    self.channel2[row][col],
+                    self.channel3[row][col],
+                    &matrix,
+                );
+                self.channel1[row][col] = y;
+                self.channel2[row][col] = cb;
+                self.channel3[row][col] = cr;
+                self.channel4[row][col] = 127 - self.channel4[row][col].clamp(0, 255);
+            }
+        }
+    }
+
+    /// Convert this image from (level shifted) YCbCr back to RGB color space, using the given matrix.
+    /// This reverts `rgb_to_ycbcr_with_matrix` up to rounding.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `matrix`: The colour matrix the image was converted to YCbCr with.
+    ///
+    /// # Panics
+    ///
+    /// * Method is called after the image was downsampled (the different channels aren't the same size)
+    pub fn ycbcr_to_rgb(&mut self, matrix: ColorMatrix) {
+        if self.is_downsampled() {
+            panic!("ycbcr_to_rgb called after downsampling!")
+        }
+        let matrix = matrix.ycbcr_to_rgb_matrix();
+        for row in 0..self.channel1.len() {
+            for col in 0..self.channel1[row].len() {
+                let (r, g, b) = convert_ycbcr_values_to_rgb(
+                    self.channel1[row][col],
+                    self.channel2[row][col],
+                    self.channel3[row][col],
+                    &matrix,
+                );
+                self.channel1[row][col] = r;
+                self.channel2[row][col] = g;
+                self.channel3[row][col] = b;
+            }
+        }
+    }
+
+    /// Create an RGB copy of this (level shifted) YCbCr image, using the given matrix.
+    /// Subsampled chroma channels are upsampled by repeating samples, as `jpg_reader` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `matrix`: The colour matrix the image was converted to YCbCr with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let image = read_y4m_from_file("../path/to/video.y4m", 0);
+    /// let original = image.to_rgb(ColorMatrix::Bt601);
+    /// ```
+    pub fn to_rgb(&self, matrix: ColorMatrix) -> Image {
+        let matrix = matrix.ycbcr_to_rgb_matrix();
+        let (mut red, mut green, mut blue) = (vec![], vec![], vec![]);
+        for y in 0..self.height {
+            let pixels: Vec<(i16, i16, i16)> = (0..self.width)
+                .map(|x| {
+                    let (luma, cb, cr) = self.pixel_at(x, y);
+                    convert_ycbcr_values_to_rgb(luma, cb, cr, &matrix)
+                })
+                .collect();
+            red.push(pixels.iter().map(|pixel| pixel.0).collect());
+            green.push(pixels.iter().map(|pixel| pixel.1).collect());
+            blue.push(pixels.iter().map(|pixel| pixel.2).collect());
+        }
+        create_image(self.height, self.width, red, green, blue)
+    }
+
+    /// Resize this image to the given size, e.g. to encode one source at several sizes.
+    /// All channels, including the alpha channel, are resized with a separable filter, in parallel rows
+    /// (see `resize_channel`). To keep the aspect ratio, calculate the height with `resized_height`.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `width`: The width to resize to.
+    /// * `height`: The height to resize to.
+    /// * `filter`: The filter to resize with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let image = read_ppm_from_file("../path/to/image.ppm");
+    /// let small = image.resize(640, image.resized_height(640), ResizeFilter::Lanczos3);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// * When the width or height is 0.
+    /// * When the image is downsampled, as its channels have different sizes.
+    pub fn resize(&self, width: u16, height: u16, filter: ResizeFilter) -> Image {
+        if width == 0 || height == 0 {
+            panic!("Images can't be resized to {}x{}!", width, height);
+        }
+        if self.is_downsampled() {
+            panic!("Downsampled images can't be resized!");
+        }
+        let resize = |channel: &Vec<Vec<i16>>| {
+            if channel.is_empty() {
+                vec![]
+            } else {
+                resize_channel(channel, self.width as usize, self.height as usize, width as usize, height as usize, filter)
+            }
+        };
+        Image {
+            height,
+            width,
+            channel1: resize(&self.channel1),
+            channel2: resize(&self.channel2),
+            channel3: resize(&self.channel3),
+            channel4: resize(&self.channel4),
+            alpha: resize(&self.alpha),
+            ..Default::default()
+        }
+    }
+
+    /// The height of this image resized to the given width, keeping the aspect ratio. It is at least 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: This image
+    /// * `width`: The width the image is resized to.
+    pub fn resized_height(&self, width: u16) -> u16 {
+        let height = (self.height as u32 * width as u32 + self.width as u32 / 2) / self.width as u32;
+        height.clamp(1, u16::MAX as u32) as u16
+    }
+
+    fn is_downsampled(&self) -> bool {
+        self.y_downsample_factor != 1
+            || self.cb_downsample_factor != 1
+            || self.cr_downsample_factor != 1
+            || self.downsampled_vertically
+    }
+
     /// Down-sample this image.
     /// `a`, `b` and `c` are expected to fit the segments of standard subsampling notation: https://en.wikipedia.org/wiki/Chroma_subsampling
     ///
@@ -310,10 +910,14 @@ impl Image {
     /// # Panics
     ///
     /// * When a, b or c is not a power of two.
+    /// * When the image has four channels, as those are always encoded without downsampling.
     pub fn downsample(&mut self, a: usize, b: usize, c: usize) {
         if a == b && a == c && b == c {
             return;
         }
+        if self.channel_count() == 4 {
+            panic!("Four channel images can't be downsampled!");
+        }
         let product = (a * b * c) as isize;
         if (product & (product - 1)) != 0 {
             panic!("One of the values is not in power of two");
@@ -334,6 +938,9 @@ impl Image {
         if a == b && a == c && b == c {
             return;
         }
+        if self.channel_count() == 4 {
+            panic!("Four channel images can't be downsampled!");
+        }
         let product = (a * b * c) as isize;
         if (product & (product - 1)) != 0 {
             panic!("One of the values is not in power of two");
@@ -371,7 +978,9 @@ impl Image {
         )
     }
 
-    fn pad_image_if_necessary(&mut self) {
+    /// Pad all channels by repeating the last row and column, so they can be split into 8x8 blocks
+    /// (or 16x16 for the Y channel of 4:2:0 images).
+    pub fn pad_image_if_necessary(&mut self) {
         let y_factor: usize;
         let cb_factor: usize;
         let cr_factor: usize;
@@ -389,8 +998,14 @@ impl Image {
         }
 
         pad_channel(&mut self.channel1, y_factor);
+        if self.channel_count() == 1 {
+            return;
+        }
         pad_channel(&mut self.channel2, cb_factor);
         pad_channel(&mut self.channel3, cr_factor);
+        if !self.channel4.is_empty() {
+            pad_channel(&mut self.channel4, y_factor);
+        }
     }
 
     /// Get the data of this image's first channel (Y) as a vector of 8x8 matrices.
@@ -405,6 +1020,7 @@ impl Image {
         let channel = match C {
             2 => &self.channel2,
             3 => &self.channel3,
+            4 => &self.channel4,
             _ => &self.channel1,
         };
         channel_to_matrices(channel)
@@ -428,6 +1044,23 @@ impl Image {
     pub fn channel3(&self) -> &Vec<Vec<i16>> {
         &self.channel3
     }
+    pub fn channel4(&self) -> &Vec<Vec<i16>> {
+        &self.channel4
+    }
+    /// The amount of channels, 1 for grayscale images, 4 for CMYK and YCCK images and 3 for all others.
+    /// The alpha channel isn't counted.
+    pub fn channel_count(&self) -> usize {
+        if self.channel2.is_empty() {
+            1
+        } else if self.channel4.is_empty() {
+            3
+        } else {
+            4
+        }
+    }
+    pub fn alpha(&self) -> &Vec<Vec<i16>> {
+        &self.alpha
+    }
     pub fn height(&self) -> u16 {
         self.height
     }
@@ -456,6 +1089,8 @@ impl Default for Image {
             channel1: vec![],
             channel2: vec![],
             channel3: vec![],
+            channel4: vec![],
+            alpha: vec![],
             y_downsample_factor: 1,
             cb_downsample_factor: 1,
             cr_downsample_factor: 1,
@@ -470,7 +1105,11 @@ mod tests {
 
     use crate::ppm_parser::read_ppm_from_file;
 
-    use super::{convert_rgb_values_to_ycbcr, Image};
+    use super::{
+        convert_rgb_values_to_cmyk, convert_rgb_values_to_ycbcr, convert_ycbcr_values_to_rgb, create_cmyk_image,
+        create_rgba_image, create_ycbcr_420_image, AlphaPolicy, ColorMatrix, ColorSpace, Image,
+    };
+    use crate::resize::ResizeFilter;
 
     #[test]
     fn test_downsample_image_factor_two() {
@@ -492,6 +1131,8 @@ mod tests {
                 cb_downsample_factor: 2,
                 cr_downsample_factor: 2,
                 downsampled_vertically: false,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -527,6 +1168,8 @@ mod tests {
                 cb_downsample_factor: 1,
                 cr_downsample_factor: 1,
                 downsampled_vertically: false,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -552,6 +1195,8 @@ mod tests {
                 cb_downsample_factor: 4,
                 cr_downsample_factor: 4,
                 downsampled_vertically: true,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -626,33 +1271,112 @@ mod tests {
     }
 
     fn test_convert_rgb_values_to_ycbcr_internal(start: (i16, i16, i16), target: (i16, i16, i16)) {
-        let result = convert_rgb_values_to_ycbcr(start.0, start.1, start.2);
+        let result = convert_rgb_values_to_ycbcr(start.0, start.1, start.2, &ColorMatrix::Bt601.rgb_to_ycbcr_matrix());
         assert_eq!(target, result);
     }
 
     #[test]
     fn test_convert_rgb_values_to_ycbcr_black() {
-        test_convert_rgb_values_to_ycbcr_internal((0, 0, 0), (-127, 0, 0));
+        test_convert_rgb_values_to_ycbcr_internal((0, 0, 0), (-128, 0, 0));
     }
 
     #[test]
     fn test_convert_rgb_values_to_ycbcr_red() {
-        test_convert_rgb_values_to_ycbcr_internal((255, 0, 0), (-51, -43, 128))
+        test_convert_rgb_values_to_ycbcr_internal((255, 0, 0), (-52, -43, 127))
     }
 
     #[test]
     fn test_convert_rgb_values_to_ycbcr_green() {
-        test_convert_rgb_values_to_ycbcr_internal((0, 255, 0), (23, -84, -107))
+        test_convert_rgb_values_to_ycbcr_internal((0, 255, 0), (22, -84, -107))
     }
 
     #[test]
     fn test_convert_rgb_values_to_ycbcr_blue() {
-        test_convert_rgb_values_to_ycbcr_internal((0, 0, 255), (-98, 128, -21))
+        test_convert_rgb_values_to_ycbcr_internal((0, 0, 255), (-99, 127, -21))
     }
 
     #[test]
     fn test_convert_rgb_values_to_ycbcr_white() {
-        test_convert_rgb_values_to_ycbcr_internal((255, 255, 255), (128, 0, 0))
+        test_convert_rgb_values_to_ycbcr_internal((255, 255, 255), (127, 0, 0))
+    }
+
+    #[test]
+    fn test_convert_rgb_values_to_ycbcr_gray_is_centred() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020] {
+            let result = convert_rgb_values_to_ycbcr(128, 128, 128, &matrix.rgb_to_ycbcr_matrix());
+            assert_eq!((0, 0, 0), result);
+        }
+    }
+
+    #[test]
+    fn test_convert_rgb_values_to_ycbcr_bt709() {
+        let matrix = ColorMatrix::Bt709.rgb_to_ycbcr_matrix();
+        // Y = 0.2126 * 255 = 54.2, Cr = 0.5 * 255 (clamped), Cb = -0.2126 / 1.8556 * 255 = -29.2
+        assert_eq!((-74, -29, 127), convert_rgb_values_to_ycbcr(255, 0, 0, &matrix));
+        // Y = 0.7152 * 255 = 182.4
+        assert_eq!((54, -98, -116), convert_rgb_values_to_ycbcr(0, 255, 0, &matrix));
+    }
+
+    #[test]
+    fn test_convert_rgb_values_to_ycbcr_bt2020() {
+        let matrix = ColorMatrix::Bt2020.rgb_to_ycbcr_matrix();
+        // Y = 0.0593 * 255 = 15.1
+        assert_eq!((-113, 127, -10), convert_rgb_values_to_ycbcr(0, 0, 255, &matrix));
+    }
+
+    #[test]
+    fn test_ycbcr_to_rgb_matrix_is_inverse() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020] {
+            let product = matrix.ycbcr_to_rgb_matrix() * matrix.rgb_to_ycbcr_matrix();
+            assert!((product - nalgebra::Matrix3::identity()).abs().max() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_convert_ycbcr_values_to_rgb_round_trip() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020] {
+            let forward = matrix.rgb_to_ycbcr_matrix();
+            let inverse = matrix.ycbcr_to_rgb_matrix();
+            for r in (0..=255).step_by(15) {
+                for g in (0..=255).step_by(15) {
+                    for b in (0..=255).step_by(15) {
+                        let (y, cb, cr) = convert_rgb_values_to_ycbcr(r, g, b, &forward);
+                        let (r2, g2, b2) = convert_ycbcr_values_to_rgb(y, cb, cr, &inverse);
+                        assert!((r - r2).abs() <= 2 && (g - g2).abs() <= 2 && (b - b2).abs() <= 2, "{:?} {:?}", (r, g, b), (r2, g2, b2));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_ycbcr_round_trip() {
+        for matrix in [ColorMatrix::Bt601, ColorMatrix::Bt709, ColorMatrix::Bt2020] {
+            let original = read_ppm_from_file("test/test_73x117_color.ppm");
+            let mut image = original.clone();
+            image.rgb_to_ycbcr_with_matrix(matrix);
+            assert_ne!(original, image);
+            image.ycbcr_to_rgb(matrix);
+            for channel in 0..3 {
+                let (a, b) = match channel {
+                    0 => (original.channel1(), image.channel1()),
+                    1 => (original.channel2(), image.channel2()),
+                    _ => (original.channel3(), image.channel3()),
+                };
+                for (row_a, row_b) in a.iter().zip(b) {
+                    for (value_a, value_b) in row_a.iter().zip(row_b) {
+                        assert!((value_a - value_b).abs() <= 2);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_matrix_from_str() {
+        assert_eq!(Ok(ColorMatrix::Bt709), "bt709".parse());
+        assert_eq!(Ok(ColorMatrix::Bt2020), "BT2020".parse());
+        assert!("srgb".parse::<ColorMatrix>().is_err());
     }
 
     #[test]
@@ -669,9 +1393,9 @@ mod tests {
         let expected_image = Image {
             height: 1,
             width: 5,
-            channel1: Vec::from([Vec::from([-127, -51, 23, -98, 128])]),
-            channel2: Vec::from([Vec::from([0, -43, -84, 128, 0])]),
-            channel3: Vec::from([Vec::from([0, 128, -107, -21, 0])]),
+            channel1: Vec::from([Vec::from([-128, -52, 22, -99, 127])]),
+            channel2: Vec::from([Vec::from([0, -43, -84, 127, 0])]),
+            channel3: Vec::from([Vec::from([0, 127, -107, -21, 0])]),
             ..Default::default()
         };
         assert_eq!(expected_image, image);
@@ -711,6 +1435,8 @@ mod tests {
             cb_downsample_factor: 1,
             cr_downsample_factor: 1,
             downsampled_vertically: false,
+            channel4: vec![],
+            alpha: vec![],
         };
         assert_eq!(expected_image, image);
     }
@@ -743,6 +1469,8 @@ mod tests {
             cb_downsample_factor: 1,
             cr_downsample_factor: 1,
             downsampled_vertically: false,
+            channel4: vec![],
+            alpha: vec![],
         };
         assert_eq!(expected_image, image);
     }
@@ -822,14 +1550,14 @@ mod tests {
         let (y, cb, cr) = image.to_matrices();
 
         let y_expected_vec = vec![
-            -127.0, -127.0, -127.0, -22.0, -127.0, -127.0, -127.0, -22.0, //row 1
-            -127.0, 36.0, -127.0, -127.0, -127.0, 36.0, -127.0, -127.0, //row 2
-            -127.0, -127.0, 36.0, -127.0, -127.0, -127.0, 36.0, -127.0, //row 3
-            -22.0, -127.0, -127.0, -127.0, -22.0, -127.0, -127.0, -127.0, //row 4
-            -127.0, -127.0, -127.0, -22.0, -127.0, -127.0, -127.0, -22.0, //row 5
-            -127.0, 36.0, -127.0, -127.0, -127.0, 36.0, -127.0, -127.0, //row 6
-            -127.0, -127.0, 36.0, -127.0, -127.0, -127.0, 36.0, -127.0, //row 7
-            -22.0, -127.0, -127.0, -127.0, -22.0, -127.0, -127.0, -127.0, // row 8
+            -128.0, -128.0, -128.0, -23.0, -128.0, -128.0, -128.0, -23.0, //row 1
+            -128.0, 35.0, -128.0, -128.0, -128.0, 35.0, -128.0, -128.0, //row 2
+            -128.0, -128.0, 35.0, -128.0, -128.0, -128.0, 35.0, -128.0, //row 3
+            -23.0, -128.0, -128.0, -128.0, -23.0, -128.0, -128.0, -128.0, //row 4
+            -128.0, -128.0, -128.0, -23.0, -128.0, -128.0, -128.0, -23.0, //row 5
+            -128.0, 35.0, -128.0, -128.0, -128.0, 35.0, -128.0, -128.0, //row 6
+            -128.0, -128.0, 35.0, -128.0, -128.0, -128.0, 35.0, -128.0, //row 7
+            -23.0, -128.0, -128.0, -128.0, -23.0, -128.0, -128.0, -128.0, // row 8
         ];
         let y_expected: Vec<SMatrix<f32, 8, 8>> = vec![
             SMatrix::from_iterator(y_expected_vec.clone()),
@@ -896,6 +1624,8 @@ mod tests {
                 cb_downsample_factor: 2,
                 cr_downsample_factor: 2,
                 downsampled_vertically: false,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -931,6 +1661,8 @@ mod tests {
                 cb_downsample_factor: 1,
                 cr_downsample_factor: 1,
                 downsampled_vertically: false,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -956,6 +1688,8 @@ mod tests {
                 cb_downsample_factor: 4,
                 cr_downsample_factor: 4,
                 downsampled_vertically: true,
+                channel4: vec![],
+                alpha: vec![],
             },
             read_image
         );
@@ -971,4 +1705,182 @@ mod tests {
         read_image_p.downsample_parallel(4, 1, 0);
         assert_eq!(read_image, read_image_p);
     }
+
+    #[test]
+    fn test_convert_rgb_values_to_cmyk() {
+        assert_eq!((0, 0, 0, 255), convert_rgb_values_to_cmyk(0, 0, 0));
+        assert_eq!((0, 0, 0, 0), convert_rgb_values_to_cmyk(255, 255, 255));
+        assert_eq!((0, 255, 255, 0), convert_rgb_values_to_cmyk(255, 0, 0));
+        assert_eq!((0, 0, 0, 128), convert_rgb_values_to_cmyk(127, 127, 127));
+        assert_eq!((0, 128, 255, 55), convert_rgb_values_to_cmyk(200, 100, 0));
+    }
+
+    #[test]
+    fn test_rgb_to_cmyk() {
+        let mut image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        image.rgb_to_cmyk();
+        assert_eq!(4, image.channel_count());
+        assert_eq!(image.channel1().len(), image.channel4().len());
+        // top left pixel is black, top right magenta
+        assert_eq!(255, image.channel4()[0][0]);
+        assert_eq!(
+            (0, 255, 0, 0),
+            (image.channel1()[0][3], image.channel2()[0][3], image.channel3()[0][3], image.channel4()[0][3])
+        );
+    }
+
+    #[test]
+    fn test_cmyk_to_adobe() {
+        let mut image = create_cmyk_image(1, 2, vec![vec![0, 255]], vec![vec![10, 20]], vec![vec![0, 0]], vec![vec![255, 128]]);
+        image.cmyk_to_adobe();
+        assert_eq!(&vec![vec![127, -128]], image.channel1());
+        assert_eq!(&vec![vec![117, 107]], image.channel2());
+        assert_eq!(&vec![vec![127, 127]], image.channel3());
+        assert_eq!(&vec![vec![-128, -1]], image.channel4());
+    }
+
+    #[test]
+    fn test_cmyk_to_ycck() {
+        let mut image = create_cmyk_image(1, 2, vec![vec![0, 255]], vec![vec![0, 0]], vec![vec![0, 0]], vec![vec![255, 0]]);
+        image.cmyk_to_ycck();
+        // C, M and Y are converted like R, G and B
+        assert_eq!(&vec![vec![-128, -52]], image.channel1());
+        assert_eq!(&vec![vec![0, -43]], image.channel2());
+        assert_eq!(&vec![vec![0, 127]], image.channel3());
+        assert_eq!(&vec![vec![-128, 127]], image.channel4());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_downsample_four_channels() {
+        let mut image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        image.rgb_to_cmyk();
+        image.downsample(4, 2, 0);
+    }
+
+    #[test]
+    fn test_color_space_from_str() {
+        assert_eq!(Ok(ColorSpace::YCbCr), "ycbcr".parse());
+        assert_eq!(Ok(ColorSpace::Cmyk), "CMYK".parse());
+        assert_eq!(Ok(ColorSpace::Ycck), "ycck".parse());
+        assert!("rgb".parse::<ColorSpace>().is_err());
+    }
+
+    fn rgba_test_image() -> Image {
+        // an opaque, a half transparent and a fully transparent red pixel
+        create_rgba_image(1, 3, vec![vec![255, 255, 255]], vec![vec![0, 0, 0]], vec![vec![0, 0, 0]], vec![vec![255, 128, 0]])
+    }
+
+    #[test]
+    fn test_apply_alpha_policy_composite() {
+        let mut image = rgba_test_image();
+        assert!(image.has_alpha());
+        assert_eq!(None, image.apply_alpha_policy(AlphaPolicy::Composite(0, 0, 255)));
+        assert!(!image.has_alpha());
+        assert_eq!(&vec![vec![255, 128, 0]], image.channel1());
+        assert_eq!(&vec![vec![0, 0, 0]], image.channel2());
+        assert_eq!(&vec![vec![0, 127, 255]], image.channel3());
+    }
+
+    #[test]
+    fn test_apply_alpha_policy_premultiply() {
+        let mut image = rgba_test_image();
+        assert_eq!(None, image.apply_alpha_policy(AlphaPolicy::Premultiply));
+        assert!(!image.has_alpha());
+        assert_eq!(&vec![vec![255, 128, 0]], image.channel1());
+        assert_eq!(&vec![vec![0, 0, 0]], image.channel3());
+    }
+
+    #[test]
+    fn test_apply_alpha_policy_sidecar() {
+        let mut image = rgba_test_image();
+        let sidecar = image.apply_alpha_policy(AlphaPolicy::Sidecar).unwrap();
+        assert!(!image.has_alpha());
+        assert_eq!(&vec![vec![255, 255, 255]], image.channel1());
+        assert_eq!(1, sidecar.channel_count());
+        assert_eq!((3, 1), (sidecar.width(), sidecar.height()));
+        assert_eq!(&vec![vec![127, 0, -128]], sidecar.channel1());
+    }
+
+    #[test]
+    fn test_apply_alpha_policy_without_alpha() {
+        let mut image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        let expected = image.clone();
+        assert_eq!(None, image.apply_alpha_policy(AlphaPolicy::Sidecar));
+        assert_eq!(expected, image);
+    }
+
+    #[test]
+    fn test_alpha_policy_from_str() {
+        assert_eq!(Ok(AlphaPolicy::Composite(255, 255, 255)), "composite".parse());
+        assert_eq!(Ok(AlphaPolicy::Composite(0x12, 0x34, 0xab)), "composite:1234AB".parse());
+        assert_eq!(Ok(AlphaPolicy::Composite(0, 0, 0)), "composite:#000000".parse());
+        assert_eq!(Ok(AlphaPolicy::Premultiply), "premultiply".parse());
+        assert_eq!(Ok(AlphaPolicy::Sidecar), "Sidecar".parse());
+        assert!("composite:fff".parse::<AlphaPolicy>().is_err());
+        assert!("drop".parse::<AlphaPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_create_ycbcr_420_image() {
+        let mut converted = read_ppm_from_file("test/test_73x117_color.ppm");
+        converted.rgb_to_ycbcr();
+        converted.downsample(4, 2, 0);
+        let image = create_ycbcr_420_image(
+            converted.height(),
+            converted.width(),
+            converted.channel1().clone(),
+            converted.channel2().clone(),
+            converted.channel3().clone(),
+        );
+        assert_eq!(converted, image);
+    }
+
+    #[test]
+    #[should_panic(expected = "Chroma channels of a 4:2:0 image must have half the size of the Y channel!")]
+    fn test_create_ycbcr_420_image_wrong_chroma_size() {
+        create_ycbcr_420_image(2, 3, vec![vec![0; 3]; 2], vec![vec![0; 1]], vec![vec![0; 1]]);
+    }
+
+    #[test]
+    fn test_to_rgb() {
+        let image = create_ycbcr_420_image(2, 3, vec![vec![-128, 0, 127], vec![0, 0, 0]], vec![vec![0, 0]], vec![vec![0, 127]]);
+        let rgb = image.to_rgb(ColorMatrix::Bt601);
+        assert_eq!((3, 2), (rgb.width(), rgb.height()));
+        assert_eq!((0, 0, 0), rgb.pixel_at(0, 0));
+        assert_eq!((128, 128, 128), rgb.pixel_at(1, 0));
+        assert_eq!((128, 128, 128), rgb.pixel_at(1, 1));
+        // the third column shares the second chroma sample, which is shifted towards red
+        let (r, g, b) = rgb.pixel_at(2, 1);
+        assert!(r > 200 && g < 128 && b == 128);
+    }
+
+    #[test]
+    fn test_resize() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        let resized = image.resize(40, image.resized_height(40), ResizeFilter::Bicubic);
+        assert_eq!((40, 64), (resized.width(), resized.height()));
+        assert_eq!(3, resized.channel_count());
+        assert!(resized.channel3().iter().all(|row| row.len() == 40));
+        // a box filter halving the size averages 2x2 pixels
+        let image = create_rgba_image(2, 2, vec![vec![0, 10], vec![20, 30]], vec![vec![4; 2]; 2], vec![vec![0; 2]; 2], vec![vec![255, 255], vec![0, 0]]);
+        let resized = image.resize(1, 1, ResizeFilter::Box);
+        assert_eq!((15, 4, 0), resized.pixel_at(0, 0));
+        assert_eq!(&vec![vec![128]], resized.alpha());
+    }
+
+    #[test]
+    #[should_panic(expected = "Downsampled images can't be resized!")]
+    fn test_resize_downsampled() {
+        let image = create_ycbcr_420_image(2, 2, vec![vec![0; 2]; 2], vec![vec![0]], vec![vec![0]]);
+        image.resize(4, 4, ResizeFilter::Bilinear);
+    }
+
+    #[test]
+    fn test_resized_height() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        assert_eq!(117, image.resized_height(73));
+        assert_eq!(160, image.resized_height(100));
+        assert_eq!(1, image.resized_height(0));
+    }
 }
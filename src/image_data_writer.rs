@@ -25,6 +25,25 @@ pub fn write_image_data_to_stream(
     }
 }
 
+/// Write the DC and AC coefficients of an image without downsampling to the stream,
+/// e.g. a CMYK image. Each MCU then consists of one block of every component, in order.
+///
+/// # Arguments
+/// * `stream`: The stream to write to.
+/// * `dc_encoded`: The encoded DC coefficients of each component.
+/// * `ac_encoded`: The encoded AC coefficients of each component.
+pub fn write_interleaved_image_data_to_stream(
+    stream: &mut BitStream,
+    dc_encoded: &[&[(HuffmanCode, CategoryCode)]],
+    ac_encoded: &[&[Vec<(HuffmanCode, CategoryCode)>]],
+) {
+    for index in 0..dc_encoded[0].len() {
+        for (dc, ac) in dc_encoded.iter().zip(ac_encoded) {
+            write_data_at_index(stream, dc, ac, index);
+        }
+    }
+}
+
 /// write the DC, then the AC coefficients at this index.
 fn write_data_at_index(
     stream: &mut BitStream,
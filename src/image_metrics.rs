@@ -0,0 +1,455 @@
+use crate::image::Image;
+
+/// The peak value of an 8 bit sample, used for the PSNR.
+const PEAK_VALUE: f64 = 255.0;
+/// Size of the gaussian window used for the SSIM.
+const SSIM_WINDOW_SIZE: usize = 11;
+/// Standard deviation of the gaussian window used for the SSIM.
+const SSIM_WINDOW_SIGMA: f64 = 1.5;
+/// Stabilising constants of the SSIM, (0.01 * 255)^2 and (0.03 * 255)^2.
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+/// Weights of the scales of the MS-SSIM, from the finest to the coarsest (as proposed by Wang et al.).
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// A plane of samples, as rows of values between 0 and 255.
+pub type Plane = Vec<Vec<f64>>;
+
+/// Quality metrics comparing one channel (or a whole image) against the original.
+///
+/// # Attributes
+///
+/// * `mse`: The mean squared error.
+/// * `psnr`: The peak signal to noise ratio in dB, infinite for identical data.
+/// * `ssim`: The structural similarity, between -1 and 1 (1 for identical data).
+/// * `ms_ssim`: The multi-scale structural similarity, between 0 and 1 (1 for identical data).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    pub mse: f64,
+    pub psnr: f64,
+    pub ssim: f64,
+    pub ms_ssim: f64,
+}
+
+/// Quality metrics comparing an image against the original, per channel and overall.
+/// The overall MSE is the mean of the channels' MSEs and the overall PSNR is calculated from it,
+/// while the overall SSIM and MS-SSIM are the mean of the channels' values.
+///
+/// # Attributes
+///
+/// * `channels`: The metrics of the three channels, in the image's channel order.
+/// * `overall`: The metrics over all channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageMetrics {
+    pub channels: [Metrics; 3],
+    pub overall: Metrics,
+}
+
+impl ImageMetrics {
+    /// Format the metrics as a table with one row per channel and one for the overall values.
+    ///
+    /// # Arguments
+    /// * `channel_names`: The names of the channels, e.g. `["R", "G", "B"]`.
+    pub fn table(&self, channel_names: [&str; 3]) -> String {
+        let mut table = format!("{:<8}{:>12}{:>12}{:>10}{:>10}\n", "Channel", "MSE", "PSNR (dB)", "SSIM", "MS-SSIM");
+        let names = channel_names.into_iter().chain(["Overall"]);
+        let metrics = self.channels.iter().chain([&self.overall]);
+        for (name, metrics) in names.zip(metrics) {
+            table += &format!(
+                "{:<8}{:>12.4}{:>12.4}{:>10.4}{:>10.4}\n",
+                name, metrics.mse, metrics.psnr, metrics.ssim, metrics.ms_ssim
+            );
+        }
+        table
+    }
+}
+
+/// Compare two images channel by channel.
+/// Both images need to have the same dimensions and must not be downsampled.
+/// Their samples are expected to be between 0 and 255, as read from a PPM file.
+///
+/// # Arguments
+/// * `original`: The reference image.
+/// * `distorted`: The image to measure, e.g. the decoded JPEG.
+///
+/// # Examples
+/// ```
+/// let original = read_ppm_from_file("image.ppm");
+/// let decoded = read_jpg_from_file("image.jpg");
+/// println!("{}", compare_images(&original, &decoded).overall.psnr);
+/// ```
+///
+/// # Panics
+/// * If the images' dimensions differ.
+/// * If one of the images is downsampled.
+pub fn compare_images(original: &Image, distorted: &Image) -> ImageMetrics {
+    if original.width() != distorted.width() || original.height() != distorted.height() {
+        panic!("Can't compare images with different dimensions!");
+    }
+    for image in [original, distorted] {
+        if image.cb_downsample_factor() != 1 || image.cr_downsample_factor() != 1 || image.downsampled_vertically() {
+            panic!("Can't compare downsampled images!");
+        }
+    }
+    let width = original.width() as usize;
+    let height = original.height() as usize;
+    let planes = |image: &Image| {
+        [image.channel1(), image.channel2(), image.channel3()]
+            .map(|channel| plane_from_channel(channel, width, height, 0.0))
+    };
+    compare_planes(&planes(original), &planes(distorted))
+}
+
+/// Compare two interleaved 8 bit RGB buffers channel by channel.
+///
+/// # Arguments
+/// * `width`: The width of the images in pixels.
+/// * `height`: The height of the images in pixels.
+/// * `original`: The reference image, as RGB triples row by row.
+/// * `distorted`: The image to measure, in the same layout.
+///
+/// # Panics
+/// * If one of the buffers doesn't have exactly `width * height * 3` values.
+pub fn compare_rgb_buffers(width: usize, height: usize, original: &[u8], distorted: &[u8]) -> ImageMetrics {
+    if original.len() != width * height * 3 || distorted.len() != width * height * 3 {
+        panic!("RGB buffer size doesn't match the dimensions!");
+    }
+    let planes = |buffer: &[u8]| {
+        [0, 1, 2].map(|channel| {
+            (0..height)
+                .map(|y| (0..width).map(|x| buffer[(y * width + x) * 3 + channel] as f64).collect())
+                .collect::<Plane>()
+        })
+    };
+    compare_planes(&planes(original), &planes(distorted))
+}
+
+/// Compare three pairs of planes and combine the results.
+fn compare_planes(original: &[Plane; 3], distorted: &[Plane; 3]) -> ImageMetrics {
+    let channels = [0, 1, 2].map(|channel| Metrics {
+        mse: mean_squared_error(&original[channel], &distorted[channel]),
+        psnr: psnr(mean_squared_error(&original[channel], &distorted[channel])),
+        ssim: ssim(&original[channel], &distorted[channel]),
+        ms_ssim: ms_ssim(&original[channel], &distorted[channel]),
+    });
+    let mse = channels.iter().map(|metrics| metrics.mse).sum::<f64>() / 3.0;
+    ImageMetrics {
+        channels,
+        overall: Metrics {
+            mse,
+            psnr: psnr(mse),
+            ssim: channels.iter().map(|metrics| metrics.ssim).sum::<f64>() / 3.0,
+            ms_ssim: channels.iter().map(|metrics| metrics.ms_ssim).sum::<f64>() / 3.0,
+        },
+    }
+}
+
+/// Convert the top left `width` x `height` samples of an image channel into a plane.
+///
+/// # Arguments
+/// * `channel`: The channel to convert.
+/// * `width`: The width of the plane.
+/// * `height`: The height of the plane.
+/// * `offset`: Added to every sample, e.g. to revert the level shift of YCbCr channels.
+pub fn plane_from_channel(channel: &[Vec<i16>], width: usize, height: usize, offset: f64) -> Plane {
+    (0..height)
+        .map(|y| (0..width).map(|x| channel[y][x] as f64 + offset).collect())
+        .collect()
+}
+
+/// Get the mean squared error between two planes of the same size.
+pub fn mean_squared_error(original: &[Vec<f64>], distorted: &[Vec<f64>]) -> f64 {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (row_original, row_distorted) in original.iter().zip(distorted) {
+        for (a, b) in row_original.iter().zip(row_distorted) {
+            sum += (a - b) * (a - b);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    sum / count as f64
+}
+
+/// Get the PSNR in dB for the given mean squared error of 8 bit samples.
+/// Identical images (an error of 0) have an infinite PSNR.
+pub fn psnr(mean_squared_error: f64) -> f64 {
+    if mean_squared_error == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (PEAK_VALUE * PEAK_VALUE / mean_squared_error).log10()
+}
+
+/// Get the mean SSIM between two planes of the same size,
+/// using an 11x11 gaussian window with a standard deviation of 1.5 (as proposed by Wang et al.).
+/// Windows at the border use the nearest border sample for positions outside of the image.
+pub fn ssim(original: &[Vec<f64>], distorted: &[Vec<f64>]) -> f64 {
+    ssim_components(original, distorted).0
+}
+
+/// Get the MS-SSIM between two planes of the same size.
+/// The planes are halved in size (by averaging 2x2 samples) between the scales. The contrast and
+/// structure terms of all scales and the luminance term of the coarsest scale are combined with
+/// the weights proposed by Wang et al. Small images use fewer than the five scales, as long as
+/// the coarsest one is still as large as the SSIM window; the weights are normalised accordingly.
+pub fn ms_ssim(original: &[Vec<f64>], distorted: &[Vec<f64>]) -> f64 {
+    let mut x = original.to_vec();
+    let mut y = distorted.to_vec();
+    let mut scales = 1;
+    let mut size = std::cmp::min(x.len(), x.first().map_or(0, |row| row.len()));
+    while scales < MS_SSIM_WEIGHTS.len() && size / 2 >= SSIM_WINDOW_SIZE {
+        scales += 1;
+        size /= 2;
+    }
+    let weights = &MS_SSIM_WEIGHTS[0..scales];
+    let weight_sum: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, contrast_structure) = ssim_components(&x, &y);
+        let value = if scale == scales - 1 { ssim } else { contrast_structure };
+        result *= value.max(0.0).powf(weight / weight_sum);
+        x = halve(&x);
+        y = halve(&y);
+    }
+    result
+}
+
+/// Get the mean SSIM and the mean of its contrast and structure term between two planes.
+fn ssim_components(x: &[Vec<f64>], y: &[Vec<f64>]) -> (f64, f64) {
+    let height = x.len();
+    let width = x.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0 {
+        return (1.0, 1.0);
+    }
+    let product = |a: &[Vec<f64>], b: &[Vec<f64>]| -> Plane {
+        a.iter()
+            .zip(b)
+            .map(|(row_a, row_b)| row_a.iter().zip(row_b).map(|(a, b)| a * b).collect())
+            .collect()
+    };
+
+    let kernel = gaussian_kernel();
+    let mu_x = gaussian_blur(x, &kernel);
+    let mu_y = gaussian_blur(y, &kernel);
+    let xx = gaussian_blur(&product(x, x), &kernel);
+    let yy = gaussian_blur(&product(y, y), &kernel);
+    let xy = gaussian_blur(&product(x, y), &kernel);
+
+    let mut ssim_sum = 0.0;
+    let mut contrast_structure_sum = 0.0;
+    for row in 0..height {
+        for col in 0..width {
+            let (mx, my) = (mu_x[row][col], mu_y[row][col]);
+            let sigma_x = xx[row][col] - mx * mx;
+            let sigma_y = yy[row][col] - my * my;
+            let sigma_xy = xy[row][col] - mx * my;
+            let luminance = (2.0 * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+            let contrast_structure = (2.0 * sigma_xy + SSIM_C2) / (sigma_x + sigma_y + SSIM_C2);
+            ssim_sum += luminance * contrast_structure;
+            contrast_structure_sum += contrast_structure;
+        }
+    }
+    let count = (width * height) as f64;
+    (ssim_sum / count, contrast_structure_sum / count)
+}
+
+/// Halve a plane in both dimensions by averaging 2x2 samples. An odd last row or column is dropped.
+fn halve(plane: &[Vec<f64>]) -> Plane {
+    let height = plane.len() / 2;
+    let width = plane.first().map_or(0, |row| row.len()) / 2;
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    (plane[2 * y][2 * x] + plane[2 * y][2 * x + 1] + plane[2 * y + 1][2 * x] + plane[2 * y + 1][2 * x + 1]) / 4.0
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Create a normalised one dimensional gaussian kernel for the SSIM window.
+fn gaussian_kernel() -> [f64; SSIM_WINDOW_SIZE] {
+    let center = (SSIM_WINDOW_SIZE / 2) as f64;
+    let mut kernel = [0.0; SSIM_WINDOW_SIZE];
+    for (index, value) in kernel.iter_mut().enumerate() {
+        let distance = index as f64 - center;
+        *value = (-distance * distance / (2.0 * SSIM_WINDOW_SIGMA * SSIM_WINDOW_SIGMA)).exp();
+    }
+    let sum: f64 = kernel.iter().sum();
+    kernel.map(|value| value / sum)
+}
+
+/// Blur a plane with a separable kernel, first horizontally, then vertically.
+/// Positions outside of the plane use the nearest border sample.
+fn gaussian_blur(plane: &[Vec<f64>], kernel: &[f64; SSIM_WINDOW_SIZE]) -> Plane {
+    let height = plane.len();
+    let width = plane[0].len();
+    let radius = (SSIM_WINDOW_SIZE / 2) as isize;
+    let clamp = |value: isize, max: usize| value.clamp(0, max as isize - 1) as usize;
+
+    let horizontal: Plane = plane
+        .iter()
+        .map(|row| {
+            (0..width)
+                .map(|x| {
+                    kernel
+                        .iter()
+                        .enumerate()
+                        .map(|(k, weight)| weight * row[clamp(x as isize + k as isize - radius, width)])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect();
+
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    kernel
+                        .iter()
+                        .enumerate()
+                        .map(|(k, weight)| weight * horizontal[clamp(y as isize + k as isize - radius, height)][x])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::create_image;
+    use crate::ppm_parser::read_ppm_from_file;
+
+    use super::{compare_images, compare_rgb_buffers, halve, mean_squared_error, ms_ssim, psnr, ssim, Plane};
+
+    fn test_plane(width: usize, height: usize) -> Plane {
+        (0..height)
+            .map(|y| (0..width).map(|x| ((x * 7 + y * 13) % 256) as f64).collect())
+            .collect()
+    }
+
+    fn add_noise(plane: &Plane, amount: f64) -> Plane {
+        plane
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(x, value)| if (x + y) % 2 == 0 { value + amount } else { value - amount })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mean_squared_error() {
+        let plane = test_plane(16, 16);
+        assert_eq!(0.0, mean_squared_error(&plane, &plane));
+        assert_eq!(9.0, mean_squared_error(&plane, &add_noise(&plane, 3.0)));
+    }
+
+    #[test]
+    fn test_psnr() {
+        assert_eq!(f64::INFINITY, psnr(0.0));
+        assert!((psnr(1.0) - 48.1308).abs() < 1e-4);
+        assert!((psnr(255.0 * 255.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ssim_identical_planes() {
+        let plane = test_plane(24, 24);
+        assert!((ssim(&plane, &plane) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ssim_distorted_plane_is_lower() {
+        let plane = test_plane(24, 24);
+        let slightly = ssim(&plane, &add_noise(&plane, 2.0));
+        let strongly = ssim(&plane, &add_noise(&plane, 20.0));
+        assert!(slightly < 1.0);
+        assert!(strongly < slightly);
+    }
+
+    #[test]
+    fn test_ms_ssim() {
+        let plane = test_plane(100, 60);
+        assert!((ms_ssim(&plane, &plane) - 1.0).abs() < 1e-9);
+        let slightly = ms_ssim(&plane, &add_noise(&plane, 2.0));
+        let strongly = ms_ssim(&plane, &add_noise(&plane, 20.0));
+        assert!(slightly < 1.0);
+        assert!(strongly < slightly);
+        assert!(strongly >= 0.0);
+    }
+
+    #[test]
+    fn test_ms_ssim_tiny_plane() {
+        let plane = test_plane(3, 2);
+        assert!((ms_ssim(&plane, &plane) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_halve() {
+        let plane = vec![vec![0.0, 2.0, 4.0], vec![2.0, 4.0, 6.0], vec![9.0, 9.0, 9.0]];
+        assert_eq!(vec![vec![2.0]], halve(&plane));
+    }
+
+    #[test]
+    fn test_compare_images_identical() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        let metrics = compare_images(&image, &image);
+        assert_eq!(0.0, metrics.overall.mse);
+        assert_eq!(f64::INFINITY, metrics.overall.psnr);
+        for channel in metrics.channels {
+            assert!((channel.ssim - 1.0).abs() < 1e-9);
+            assert!((channel.ms_ssim - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_compare_images_per_channel() {
+        let original = create_image(2, 2, vec![vec![10, 10]; 2], vec![vec![20, 20]; 2], vec![vec![30, 30]; 2]);
+        let distorted = create_image(2, 2, vec![vec![10, 10]; 2], vec![vec![22, 18]; 2], vec![vec![34, 26]; 2]);
+        let metrics = compare_images(&original, &distorted);
+        assert_eq!(0.0, metrics.channels[0].mse);
+        assert_eq!(4.0, metrics.channels[1].mse);
+        assert_eq!(16.0, metrics.channels[2].mse);
+        assert_eq!(20.0 / 3.0, metrics.overall.mse);
+        assert_eq!(psnr(20.0 / 3.0), metrics.overall.psnr);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_compare_images_different_dimensions() {
+        let a = create_image(2, 2, vec![vec![0; 2]; 2], vec![vec![0; 2]; 2], vec![vec![0; 2]; 2]);
+        let b = create_image(1, 2, vec![vec![0; 2]; 1], vec![vec![0; 2]; 1], vec![vec![0; 2]; 1]);
+        compare_images(&a, &b);
+    }
+
+    #[test]
+    fn test_table() {
+        let image = create_image(1, 1, vec![vec![0]], vec![vec![0]], vec![vec![0]]);
+        let table = compare_images(&image, &image).table(["R", "G", "B"]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(5, lines.len());
+        assert!(lines[0].starts_with("Channel"));
+        assert!(lines[1].starts_with("R "));
+        assert!(lines[4].starts_with("Overall"));
+        assert!(lines[4].contains("inf"));
+    }
+
+    #[test]
+    fn test_compare_rgb_buffers() {
+        let original = [10, 20, 30, 10, 20, 30];
+        let distorted = [10, 22, 34, 10, 18, 26];
+        let metrics = compare_rgb_buffers(2, 1, &original, &distorted);
+        assert_eq!(0.0, metrics.channels[0].mse);
+        assert_eq!(4.0, metrics.channels[1].mse);
+        assert_eq!(16.0, metrics.channels[2].mse);
+    }
+}
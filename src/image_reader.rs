@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::bmp_reader::decode_bmp;
+use crate::image::Image;
+use crate::jpg_reader::decode_jpg;
+use crate::png_reader::{decode_png, PNG_SIGNATURE};
+use crate::ppm_parser::{parse_pam, parse_pnm, read_ppm};
+use crate::yuv_reader::{read_raw_yuv, read_y4m, ColorRange, FrameSize, RawYuvFormat};
+
+/// The amount of bytes needed to detect the format of a file, the length of the Y4M signature.
+const MAGIC_LENGTH: usize = 10;
+
+/// The formats of image files that can be read, see `detect_format`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// Plain (P1, P2) or binary (P4, P5, P6) PBM, PGM and PPM files.
+    Pnm,
+    /// Plain PPM (P3) files.
+    PlainPpm,
+    /// PAM (P7) files.
+    Pam,
+    Png,
+    Bmp,
+    /// Baseline JPEG files, which are decoded and encoded again.
+    Jpeg,
+    Y4m,
+    /// Raw YUV frames, which have no magic bytes and are recognised by the extension of their file instead.
+    RawYuv(RawYuvFormat),
+}
+
+impl InputFormat {
+    /// Whether images of this format are read as YCbCr with 4:2:0 subsampled chroma instead of RGB,
+    /// so they skip the colour conversion and downsampling.
+    pub fn is_ycbcr(&self) -> bool {
+        matches!(self, InputFormat::Y4m | InputFormat::RawYuv(_))
+    }
+}
+
+/// The settings of the readers for formats that need more than the file.
+///
+/// # Attributes
+///
+/// * `frame`: The index of the frame to read from Y4M and raw YUV files.
+/// * `yuv_size`: The width and height of the frames of raw YUV files, which they don't store.
+/// * `yuv_range`: The range of the samples of raw YUV files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReaderSettings {
+    pub frame: usize,
+    pub yuv_size: Option<FrameSize>,
+    pub yuv_range: ColorRange,
+}
+
+/// Detect the format of an image file by its magic bytes.
+/// Raw YUV files, which have none, are detected by their extension (see `RawYuvFormat::from_filename`).
+///
+/// # Arguments
+/// * `filename`: The path to the file.
+///
+/// # Examples
+/// ```
+/// let format = detect_format("image.png");
+/// ```
+///
+/// # Errors
+/// * If the file can't be read.
+/// * If the format is unknown.
+pub fn detect_format(filename: &str) -> Result<InputFormat, String> {
+    let mut magic = Vec::with_capacity(MAGIC_LENGTH);
+    File::open(filename)
+        .and_then(|file| file.take(MAGIC_LENGTH as u64).read_to_end(&mut magic))
+        .map_err(|error| format!("Error reading {}: {}!", filename, error))?;
+    detect_format_from_magic(&magic, filename)
+}
+
+/// Detect the format of an image file from its first bytes, see `detect_format`.
+fn detect_format_from_magic(magic: &[u8], filename: &str) -> Result<InputFormat, String> {
+    let format = match magic {
+        [b'P', number, separator, ..] if separator.is_ascii_whitespace() => match number {
+            b'1' | b'2' | b'4' | b'5' | b'6' => Some(InputFormat::Pnm),
+            b'3' => Some(InputFormat::PlainPpm),
+            b'7' => Some(InputFormat::Pam),
+            _ => None,
+        },
+        _ if magic.starts_with(PNG_SIGNATURE) => Some(InputFormat::Png),
+        [b'B', b'M', ..] => Some(InputFormat::Bmp),
+        [0xff, 0xd8, 0xff, ..] => Some(InputFormat::Jpeg),
+        _ if magic.starts_with(b"YUV4MPEG2 ") => Some(InputFormat::Y4m),
+        _ => RawYuvFormat::from_filename(filename).map(InputFormat::RawYuv),
+    };
+    format.ok_or(format!(
+        "Unknown image format of {}! Supported are PBM, PGM, PPM, PAM, PNG, BMP, JPEG and Y4M files, \
+         as well as raw YUV files ending with .yuv, .i420 or .nv12.",
+        filename
+    ))
+}
+
+/// Read an image file of any supported format, detected with `detect_format`.
+/// See `read_image`.
+///
+/// # Arguments
+/// * `filename`: The path to the file.
+/// * `settings`: The settings of the readers for video frames.
+///
+/// # Examples
+/// ```
+/// let (image, format) = read_image_from_file("image.png", &ReaderSettings::default())?;
+/// ```
+///
+/// # Errors
+/// * If the file can't be opened.
+/// * See `read_image`.
+pub fn read_image_from_file(filename: &str, settings: &ReaderSettings) -> Result<(Image, InputFormat), String> {
+    let file = File::open(filename).map_err(|error| format!("Error reading {}: {}!", filename, error))?;
+    read_image(file, filename, settings)
+}
+
+/// Read an image of any supported format from a reader, e.g. stdin.
+/// The format is detected by the first bytes, like `detect_format` does.
+///
+/// # Arguments
+/// * `reader`: The reader positioned at the start of the file.
+/// * `filename`: The name of the file, to detect raw YUV files by their extension and for error messages.
+/// * `settings`: The settings of the readers for video frames.
+///
+/// # Examples
+/// ```
+/// let (image, format) = read_image(std::io::stdin().lock(), "stdin", &ReaderSettings::default())?;
+/// ```
+///
+/// Returns the image, which is in YCbCr for the formats of `InputFormat::is_ycbcr` and in RGB otherwise,
+/// and its format.
+///
+/// # Errors
+/// * If the reader fails or the format is unknown.
+/// * If it is a raw YUV file and the settings have no frame size.
+/// * If it is a JPEG file with four components (CMYK or YCCK).
+/// * If the file is malformed or uses unsupported features, see the reader of its format.
+pub fn read_image<R: Read>(reader: R, filename: &str, settings: &ReaderSettings) -> Result<(Image, InputFormat), String> {
+    let mut reader = BufReader::new(reader);
+    // look at the magic bytes without consuming them, so the readers get the whole file
+    let magic = reader
+        .fill_buf()
+        .map_err(|error| format!("Error reading {}: {}!", filename, error))?;
+    let format = detect_format_from_magic(&magic[..magic.len().min(MAGIC_LENGTH)], filename)?;
+    // P3 files and video frames are streamed, the other formats are decoded from the whole file
+    let image = match format {
+        InputFormat::Pnm => parse_pnm(&read_to_end(reader, filename)?)?,
+        InputFormat::PlainPpm => read_ppm(reader)?,
+        InputFormat::Pam => parse_pam(&read_to_end(reader, filename)?)?,
+        InputFormat::Png => decode_png(&read_to_end(reader, filename)?)?,
+        InputFormat::Bmp => decode_bmp(&read_to_end(reader, filename)?)?,
+        InputFormat::Jpeg => {
+            let image = decode_jpg(&read_to_end(reader, filename)?)?;
+            if image.channel_count() == 4 {
+                return Err(format!("JPEG files with four components like {} can't be read as input!", filename));
+            }
+            image
+        }
+        InputFormat::Y4m => read_y4m(reader, settings.frame)?,
+        InputFormat::RawYuv(yuv_format) => {
+            let size = settings
+                .yuv_size
+                .ok_or(format!("The frame size of the raw YUV file {} is missing!", filename))?;
+            read_raw_yuv(reader, yuv_format, size, settings.yuv_range, settings.frame)?
+        }
+    };
+    Ok((image, format))
+}
+
+/// Read the rest of a file, for the formats that are decoded as a whole.
+///
+/// # Errors
+/// * If the reader fails.
+fn read_to_end<R: Read>(mut reader: R, filename: &str) -> Result<Vec<u8>, String> {
+    let mut data = vec![];
+    reader
+        .read_to_end(&mut data)
+        .map_err(|error| format!("Error reading {}: {}!", filename, error))?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use crate::yuv_reader::{FrameSize, RawYuvFormat};
+
+    use super::{detect_format, detect_format_from_magic, read_image, read_image_from_file, InputFormat, ReaderSettings};
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Ok(InputFormat::PlainPpm), detect_format("test/valid_test_8x8.ppm"));
+        assert_eq!(Ok(InputFormat::Pnm), detect_format("test/valid_test_binary.ppm"));
+        assert_eq!(Ok(InputFormat::Pam), detect_format("test/valid_test_grayscale.pam"));
+        assert_eq!(Ok(InputFormat::Png), detect_format("test/valid_test_gray_2bit.png"));
+        assert_eq!(Ok(InputFormat::Bmp), detect_format("test/valid_test_24bit_v4.bmp"));
+        assert_eq!(Ok(InputFormat::Y4m), detect_format("test/valid_test_5x3_420.y4m"));
+        assert_eq!(Ok(InputFormat::RawYuv(RawYuvFormat::Nv12)), detect_format("test/valid_test_5x3.nv12"));
+        assert!(detect_format("test/zlib_dynamic_block.bin").unwrap_err().starts_with("Unknown image format"));
+        assert!(detect_format("test/missing.ppm").unwrap_err().starts_with("Error reading test/missing.ppm"));
+    }
+
+    #[test]
+    fn test_detect_format_from_magic() {
+        assert_eq!(Ok(InputFormat::Pnm), detect_format_from_magic(b"P1\n", "image"));
+        assert_eq!(Ok(InputFormat::Pnm), detect_format_from_magic(b"P5 12 3", "image"));
+        assert_eq!(Ok(InputFormat::Jpeg), detect_format_from_magic(&[0xff, 0xd8, 0xff, 0xe0], "image"));
+        // the content wins over the extension
+        assert_eq!(Ok(InputFormat::Bmp), detect_format_from_magic(b"BM\x00\x00", "image.ppm"));
+        assert!(detect_format_from_magic(b"P8\n", "image").is_err());
+        assert!(detect_format_from_magic(b"P", "image").is_err());
+        assert!(detect_format_from_magic(b"", "image").is_err());
+    }
+
+    #[test]
+    fn test_read_image_from_file() {
+        let (image, format) = read_image_from_file("test/valid_test_rgb_8bit_filters.png", &ReaderSettings::default()).unwrap();
+        assert_eq!(InputFormat::Png, format);
+        assert!(!format.is_ycbcr());
+        assert_eq!((13, 7), (image.width(), image.height()));
+
+        let settings = ReaderSettings {
+            yuv_size: Some(FrameSize { width: 5, height: 3 }),
+            ..Default::default()
+        };
+        let (image, format) = read_image_from_file("test/valid_test_5x3.yuv", &settings).unwrap();
+        assert!(format.is_ycbcr());
+        assert_eq!(2, image.cb_downsample_factor());
+    }
+
+    #[test]
+    fn test_read_image_from_file_without_yuv_size() {
+        let error = read_image_from_file("test/valid_test_5x3.yuv", &ReaderSettings::default()).unwrap_err();
+        assert_eq!("The frame size of the raw YUV file test/valid_test_5x3.yuv is missing!", error);
+    }
+
+    #[test]
+    fn test_read_image_from_reader() {
+        let data = std::fs::read("test/valid_test_maxVal_15.ppm").unwrap();
+        let (image, format) = read_image(data.as_slice(), "stdin", &ReaderSettings::default()).unwrap();
+        assert_eq!(InputFormat::PlainPpm, format);
+        assert_eq!(read_image_from_file("test/valid_test_maxVal_15.ppm", &ReaderSettings::default()).unwrap().0, image);
+
+        // files shorter than the magic bytes of other formats
+        let (image, _) = read_image(&b"P1 1 1 1"[..], "stdin", &ReaderSettings::default()).unwrap();
+        assert_eq!((0, 0, 0), image.pixel_at(0, 0));
+    }
+
+    #[test]
+    fn test_read_image_streams_video_frames() {
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("read past the first frame"))
+            }
+        }
+
+        // the header, the frame header and the 5x3 samples of the first frame
+        let data = std::fs::read("test/valid_test_5x3_420.y4m").unwrap();
+        let first_frame_end = data.iter().position(|&byte| byte == b'\n').unwrap() + 1 + b"FRAME\n".len() + 5 * 3 + 2 * 3 * 2;
+        let reader = data[..first_frame_end].chain(FailingReader);
+        let (image, format) = read_image(reader, "stdin", &ReaderSettings::default()).unwrap();
+        assert_eq!(InputFormat::Y4m, format);
+        assert_eq!(read_image_from_file("test/valid_test_5x3_420.y4m", &ReaderSettings::default()).unwrap().0, image);
+    }
+}
@@ -0,0 +1,362 @@
+/// The base lengths of the length symbols 257 to 285, see RFC 1951, 3.2.5.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+/// The amount of extra bits of the length symbols 257 to 285.
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// The base distances of the distance symbols 0 to 29.
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+/// The amount of extra bits of the distance symbols 0 to 29.
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// The order in which the code lengths of the code length alphabet are stored in dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// A canonical huffman code as used by DEFLATE, decoded bit by bit.
+///
+/// # Attributes
+///
+/// * `counts`: The amount of codes of each length (index 0 to 15).
+/// * `symbols`: The symbols, ordered by code.
+struct HuffmanTree {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl HuffmanTree {
+    /// Build the code from the code length of each symbol (0 for unused symbols).
+    fn new(lengths: &[u8]) -> HuffmanTree {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        HuffmanTree { counts, symbols }
+    }
+}
+
+/// Reads the bits of a DEFLATE stream, least significant bit of each byte first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u8,
+}
+
+impl BitReader<'_> {
+    /// Read one bit.
+    ///
+    /// # Errors
+    /// * If the data ends.
+    fn read_bit(&mut self) -> Result<u32, String> {
+        let byte = *self.data.get(self.position).ok_or("Compressed data ends unexpectedly!")?;
+        let value = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+        Ok(value as u32)
+    }
+
+    /// Read `amount` bits as a number, the first bit read being the least significant one.
+    fn read_bits(&mut self, amount: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for index in 0..amount {
+            value |= self.read_bit()? << index;
+        }
+        Ok(value)
+    }
+
+    /// Skip the rest of the current byte.
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+
+    /// Decode one symbol of the huffman code. Codes are stored most significant bit first.
+    ///
+    /// # Errors
+    /// * If the data ends or the bits don't form a code.
+    fn decode(&mut self, tree: &HuffmanTree) -> Result<u16, String> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in 1..16 {
+            code |= self.read_bit()? as i32;
+            let count = tree.counts[length] as i32;
+            if code - first < count {
+                return Ok(tree.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid huffman code in compressed data!".to_string())
+    }
+}
+
+/// Decompress a zlib stream (RFC 1950) holding DEFLATE (RFC 1951) compressed data, as used in PNG files.
+///
+/// # Arguments
+/// * `data`: The zlib stream, starting with its two byte header.
+/// * `max_length`: The largest amount of bytes the data may decompress to, e.g. the size of a PNG's scanlines.
+///
+/// # Examples
+/// ```
+/// let raw = zlib_decompress(&idat, (scanline_length + 1) * height);
+/// ```
+///
+/// # Errors
+/// * If the header is invalid, the stream uses a preset dictionary or another method than DEFLATE.
+/// * If the compressed data is corrupted or its Adler-32 checksum doesn't match.
+/// * If the data decompresses to more than `max_length` bytes.
+pub fn zlib_decompress(data: &[u8], max_length: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 6 || data[0] & 0x0f != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31) {
+        return Err("Invalid zlib header!".to_string());
+    }
+    if data[1] & 0x20 != 0 {
+        return Err("zlib streams with a preset dictionary aren't supported!".to_string());
+    }
+    let (output, end) = inflate(&data[2..], max_length)?;
+    let checksum = data
+        .get(2 + end..2 + end + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or("zlib stream is missing its checksum!")?;
+    if checksum != adler32(&output) {
+        return Err("zlib checksum doesn't match!".to_string());
+    }
+    Ok(output)
+}
+
+/// Decompress raw DEFLATE data.
+/// Returns the decompressed data and the amount of bytes the compressed data took.
+/// Stops as soon as the output grows beyond `max_length`, so corrupted or malicious data can't exhaust the memory.
+///
+/// # Errors
+/// * If the compressed data is corrupted.
+/// * If the data decompresses to more than `max_length` bytes.
+fn inflate(data: &[u8], max_length: usize) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader { data, position: 0, bit: 0 };
+    let mut output: Vec<u8> = Vec::new();
+    loop {
+        let last_block = reader.read_bit()? == 1;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = data
+                    .get(reader.position..reader.position + 4)
+                    .ok_or("Compressed data ends unexpectedly!")?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("Invalid length of stored block!".to_string());
+                }
+                let start = reader.position + 4;
+                let block = data
+                    .get(start..start + length as usize)
+                    .ok_or("Compressed data ends unexpectedly!")?;
+                check_length(output.len() + block.len(), max_length)?;
+                output.extend_from_slice(block);
+                reader.position = start + length as usize;
+            }
+            1 => {
+                let (literals, distances) = fixed_trees();
+                inflate_block(&mut reader, &mut output, &literals, &distances, max_length)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_trees(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances, max_length)?;
+            }
+            _ => return Err("Invalid block type in compressed data!".to_string()),
+        }
+        if last_block {
+            break;
+        }
+    }
+    reader.align_to_byte();
+    Ok((output, reader.position))
+}
+
+/// Get the huffman codes of blocks compressed with fixed codes, see RFC 1951, 3.2.6.
+fn fixed_trees() -> (HuffmanTree, HuffmanTree) {
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    (HuffmanTree::new(&lengths), HuffmanTree::new(&[5; 30]))
+}
+
+/// Read the huffman codes of a block compressed with dynamic codes, see RFC 1951, 3.2.7.
+///
+/// # Errors
+/// * If the data ends or the code lengths are invalid.
+fn read_dynamic_trees(reader: &mut BitReader) -> Result<(HuffmanTree, HuffmanTree), String> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*index] = reader.read_bits(3)? as u8;
+    }
+    let code_length_tree = HuffmanTree::new(&code_length_lengths);
+
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = reader.decode(&code_length_tree)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or("Code length repeated without a previous one!")?,
+                3 + reader.read_bits(2)?,
+            ),
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Code lengths exceed the amount of symbols!".to_string());
+    }
+    Ok((
+        HuffmanTree::new(&lengths[..literal_count]),
+        HuffmanTree::new(&lengths[literal_count..]),
+    ))
+}
+
+/// Decode the symbols of a huffman compressed block up to its end of block symbol.
+///
+/// # Errors
+/// * If the data ends, a symbol is invalid or a distance reaches back before the start of the data.
+/// * If the output grows beyond `max_length` bytes.
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &HuffmanTree, distances: &HuffmanTree, max_length: usize) -> Result<(), String> {
+    loop {
+        let symbol = reader.decode(literals)? as usize;
+        match symbol {
+            0..=255 => {
+                check_length(output.len() + 1, max_length)?;
+                output.push(symbol as u8)
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let length =
+                    LENGTH_BASES[symbol - 257] as usize + reader.read_bits(LENGTH_EXTRA_BITS[symbol - 257])? as usize;
+                let distance_symbol = reader.decode(distances)? as usize;
+                if distance_symbol >= 30 {
+                    return Err("Invalid distance in compressed data!".to_string());
+                }
+                let distance = DISTANCE_BASES[distance_symbol] as usize
+                    + reader.read_bits(DISTANCE_EXTRA_BITS[distance_symbol])? as usize;
+                if distance > output.len() {
+                    return Err("Distance reaches before the start of the compressed data!".to_string());
+                }
+                check_length(output.len() + length, max_length)?;
+                // the copied range may overlap with the bytes being written, so copy byte by byte
+                let start = output.len() - distance;
+                for index in 0..length {
+                    output.push(output[start + index]);
+                }
+            }
+            _ => return Err("Invalid length in compressed data!".to_string()),
+        }
+    }
+}
+
+/// Make sure the output doesn't grow beyond the expected length.
+///
+/// # Errors
+/// * If `length` is larger than `max_length`.
+fn check_length(length: usize, max_length: usize) -> Result<(), String> {
+    if length > max_length {
+        return Err("Compressed data is longer than expected!".to_string());
+    }
+    Ok(())
+}
+
+/// Calculate the Adler-32 checksum of the data, see RFC 1950.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest amount of bytes that can be summed up without overflowing
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, inflate, zlib_decompress};
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(1, adler32(&[]));
+        assert_eq!(0x11e60398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn test_inflate_stored_block() {
+        let data = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        assert_eq!((b"abc".to_vec(), 8), inflate(&data, 3).unwrap());
+    }
+
+    #[test]
+    fn test_inflate_fixed_block_with_overlapping_copy() {
+        // "a" followed by a copy at distance 1, as compressed by zlib
+        let data = [0x4b, 0x4c, 0x84, 0x01, 0x00];
+        assert_eq!(b"aaaaaaaaaa".to_vec(), inflate(&data, 10).unwrap().0);
+    }
+
+    #[test]
+    fn test_zlib_decompress_dynamic_block() {
+        // zlib.compress(b"".join(bytes([i % 7, i % 13]) for i in range(400)), 9)
+        let data = std::fs::read("test/zlib_dynamic_block.bin").unwrap();
+        let expected: Vec<u8> = (0..400).flat_map(|i| [(i % 7) as u8, (i % 13) as u8]).collect();
+        assert_eq!(expected, zlib_decompress(&data, 800).unwrap());
+    }
+
+    #[test]
+    fn test_zlib_decompress_wrong_checksum() {
+        let error = zlib_decompress(&[0x78, 0xda, 0x4b, 0x4c, 0x84, 0x01, 0x00, 0, 0, 0, 0], 10).unwrap_err();
+        assert_eq!("zlib checksum doesn't match!", error);
+    }
+
+    #[test]
+    fn test_inflate_stops_at_max_length() {
+        // the copy of nine bytes goes past the limit
+        let error = inflate(&[0x4b, 0x4c, 0x84, 0x01, 0x00], 9).unwrap_err();
+        assert_eq!("Compressed data is longer than expected!", error);
+    }
+
+    #[test]
+    fn test_inflate_stored_block_longer_than_max_length() {
+        let error = inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'], 2).unwrap_err();
+        assert_eq!("Compressed data is longer than expected!", error);
+    }
+
+    #[test]
+    fn test_inflate_truncated() {
+        assert_eq!("Compressed data ends unexpectedly!", inflate(&[0x4b, 0x4c], 10).unwrap_err());
+    }
+}
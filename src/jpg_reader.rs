@@ -0,0 +1,891 @@
+use std::fs;
+
+use nalgebra::{SMatrix, Vector3};
+
+use crate::dct::matrix_inverse_dct;
+use crate::image::{create_cmyk_image, create_image, ColorMatrix, Image};
+use crate::jpg_writer::ICC_PROFILE_IDENTIFIER;
+use crate::quantization::unsample_zigzag;
+
+/// A huffman table as read from a DHT segment, prepared for decoding as described in
+/// Annex F.2.2.3 of the JPEG specification.
+///
+/// # Attributes
+///
+/// * `max_code`: The largest code of each length (index 1 to 16), -1 if there are none.
+/// * `value_pointer`: The index into `values` of the first code of each length.
+/// * `min_code`: The smallest code of each length.
+/// * `values`: The symbols, ordered by code.
+#[derive(Clone, Debug)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    value_pointer: [usize; 17],
+    min_code: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Default for HuffmanTable {
+    /// An empty table, which matches no code. Scans referencing a table that no DHT segment defined
+    /// fail to decode instead of reading a symbol that doesn't exist.
+    fn default() -> HuffmanTable {
+        HuffmanTable {
+            max_code: [-1; 17],
+            value_pointer: [0; 17],
+            min_code: [0; 17],
+            values: vec![],
+        }
+    }
+}
+
+impl HuffmanTable {
+    /// Build the table from the amount of codes per length and the symbols, as stored in a DHT segment.
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> HuffmanTable {
+        let mut table = HuffmanTable {
+            values,
+            ..Default::default()
+        };
+        let mut code = 0i32;
+        let mut index = 0usize;
+        for length in 1..=16 {
+            let count = counts[length - 1] as usize;
+            if count > 0 {
+                table.value_pointer[length] = index;
+                table.min_code[length] = code;
+                code += count as i32;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+/// A component of the frame, as declared in the SOF segment.
+///
+/// # Attributes
+///
+/// * `id`: The component's id, referenced by the SOS segment.
+/// * `horizontal_factor`: The horizontal sampling factor.
+/// * `vertical_factor`: The vertical sampling factor.
+/// * `q_table`: The id of the quantization table.
+/// * `dc_table`: The id of the DC huffman table, set by the SOS segment.
+/// * `ac_table`: The id of the AC huffman table, set by the SOS segment.
+/// * `blocks_per_line`: The amount of blocks per row, including the padding to whole MCUs.
+/// * `coefficients`: The decoded (still quantized) coefficients of all blocks in zigzag order,
+///   row by row.
+#[derive(Clone, Debug, Default)]
+struct Component {
+    id: u8,
+    horizontal_factor: usize,
+    vertical_factor: usize,
+    q_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    blocks_per_line: usize,
+    coefficients: Vec<[i32; 64]>,
+}
+
+/// The state of the decoder while going through the segments of a JPEG file.
+#[derive(Default)]
+struct Decoder {
+    q_tables: [Vec<u16>; 4],
+    dc_tables: [HuffmanTable; 4],
+    ac_tables: [HuffmanTable; 4],
+    height: usize,
+    width: usize,
+    components: Vec<Component>,
+    max_horizontal_factor: usize,
+    max_vertical_factor: usize,
+    restart_interval: usize,
+    adobe_transform: Option<u8>,
+}
+
+/// Reads the entropy coded data of a scan bit by bit, removing stuffed zero bytes.
+struct EntropyReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    current_byte: u8,
+    bits_left: u8,
+}
+
+impl EntropyReader<'_> {
+    /// Read the next bit. Once a marker is reached, zero bits are returned.
+    fn read_bit(&mut self) -> u16 {
+        if self.bits_left == 0 {
+            self.current_byte = match self.data.get(self.position) {
+                Some(0xff) if self.data.get(self.position + 1) == Some(&0x00) => {
+                    self.position += 2;
+                    0xff
+                }
+                Some(0xff) | None => 0,
+                Some(byte) => {
+                    self.position += 1;
+                    *byte
+                }
+            };
+            self.bits_left = 8;
+        }
+        self.bits_left -= 1;
+        ((self.current_byte >> self.bits_left) & 1) as u16
+    }
+
+    /// Read `amount` bits as an unsigned number.
+    fn read_bits(&mut self, amount: u8) -> u16 {
+        (0..amount).fold(0, |value, _| (value << 1) | self.read_bit())
+    }
+
+    /// Read `category` bits and convert them into the value they represent (the inverse of `coefficient_encoder::categorize`).
+    fn read_value(&mut self, category: u8) -> i32 {
+        if category == 0 {
+            return 0;
+        }
+        let bits = self.read_bits(category) as i32;
+        if bits < 1 << (category - 1) {
+            bits - (1 << category) + 1
+        } else {
+            bits
+        }
+    }
+
+    /// Decode the next huffman coded symbol.
+    ///
+    /// # Errors
+    /// * If the bits don't form a code of the table.
+    fn decode(&mut self, table: &HuffmanTable) -> Result<u8, String> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | self.read_bit() as i32;
+            if code <= table.max_code[length] {
+                return Ok(table.values[table.value_pointer[length] + (code - table.min_code[length]) as usize]);
+            }
+        }
+        Err("Invalid huffman code in JPEG data!".to_string())
+    }
+
+    /// Skip the rest of the current byte and the restart marker that has to follow.
+    fn restart(&mut self) {
+        self.bits_left = 0;
+        if self.data.get(self.position) == Some(&0xff)
+            && matches!(self.data.get(self.position + 1), Some(0xd0..=0xd7))
+        {
+            self.position += 2;
+        }
+    }
+}
+
+/// Read a baseline JPEG file and decode it into an RGB image.
+///
+/// # Arguments
+/// * `filename`: The path to the file to read.
+///
+/// # Examples
+/// ```
+/// let image = read_jpg_from_file("output.jpg");
+/// ```
+///
+/// # Panics
+/// * If the file can't be read.
+/// * If the file can't be decoded, see `decode_jpg`.
+pub fn read_jpg_from_file(filename: &str) -> Image {
+    let data = fs::read(filename).expect("Error reading JPEG file!");
+    match decode_jpg(&data) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Decode a baseline (sequential, huffman coded, 8 bit) JPEG file into an RGB image,
+/// assuming the BT.601 colour matrix that JFIF prescribes.
+/// See `decode_jpg_with_color_matrix`.
+///
+/// # Arguments
+/// * `data`: The content of the JPEG file.
+pub fn decode_jpg(data: &[u8]) -> Result<Image, String> {
+    decode_jpg_with_color_matrix(data, ColorMatrix::Bt601)
+}
+
+/// Decode a baseline (sequential, huffman coded, 8 bit) JPEG file into an RGB image.
+/// Grayscale images are decoded into three equal channels.
+/// Four component images are decoded into a CMYK image holding the amounts of ink.
+/// Their transform is taken from the Adobe APP14 segment, and if there is one, the values are
+/// expected to be stored inverted (as Adobe applications do).
+/// Subsampled chroma channels are upsampled by repeating samples.
+///
+/// # Arguments
+/// * `data`: The content of the JPEG file.
+/// * `color_matrix`: The matrix the file was converted to YCbCr with.
+///
+/// # Errors
+/// * If the data isn't a JPEG file or is corrupted.
+/// * If the file uses a process other than baseline or extended sequential huffman coding,
+///   e.g. progressive or arithmetic coding.
+/// * If the amount of components is neither 1, 3 nor 4.
+pub fn decode_jpg_with_color_matrix(data: &[u8], color_matrix: ColorMatrix) -> Result<Image, String> {
+    if data.len() < 4 || data[0..2] != [0xff, 0xd8] {
+        return Err("Not a JPEG file!".to_string());
+    }
+    let mut decoder = Decoder::default();
+    let mut position = 2;
+    loop {
+        let (marker, segment, next) = read_segment(data, position)?;
+        position = next;
+        match marker {
+            0xd9 => break,
+            0xdb => decoder.read_dqt(segment)?,
+            0xc4 => decoder.read_dht(segment)?,
+            0xc0 | 0xc1 => decoder.read_sof(segment)?,
+            0xdd if segment.len() < 2 => return Err("Invalid DRI segment!".to_string()),
+            0xdd => decoder.restart_interval = read_u16(segment, 0) as usize,
+            0xee if segment.len() >= 12 && segment.starts_with(b"Adobe") => decoder.adobe_transform = Some(segment[11]),
+            0xda => position = decoder.read_scan(segment, data, position)?,
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                return Err(format!("Unsupported JPEG process (SOF{})!", marker - 0xc0));
+            }
+            // other APPn, COM and other segments aren't needed for decoding the image
+            _ => (),
+        }
+    }
+    decoder.to_image(color_matrix)
+}
+
+/// Extract the ICC profile embedded in a JPEG file's APP2 segments, reassembling its chunks
+/// in the order of their sequence numbers. Only the segments before the first scan are searched,
+/// as the profile has to precede the image data.
+/// Returns `None` if the file has no ICC profile.
+///
+/// # Arguments
+/// * `data`: The content of the JPEG file.
+///
+/// # Examples
+/// ```
+/// let data = fs::read("image.jpg").unwrap();
+/// if let Some(profile) = extract_icc_profile(&data)? {
+///     fs::write("image.icc", profile).unwrap();
+/// }
+/// ```
+///
+/// # Errors
+/// * If the data isn't a JPEG file or is corrupted.
+/// * If chunks of the profile are missing, duplicated or disagree on the amount of chunks.
+pub fn extract_icc_profile(data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    if data.len() < 4 || data[0..2] != [0xff, 0xd8] {
+        return Err("Not a JPEG file!".to_string());
+    }
+    let mut chunks: Vec<(u8, u8, &[u8])> = vec![];
+    let mut position = 2;
+    loop {
+        let (marker, segment, next) = read_segment(data, position)?;
+        position = next;
+        match marker {
+            0xd9 | 0xda => break,
+            0xe2 if segment.len() >= ICC_PROFILE_IDENTIFIER.len() + 2 && segment.starts_with(ICC_PROFILE_IDENTIFIER) => {
+                let header = &segment[ICC_PROFILE_IDENTIFIER.len()..];
+                chunks.push((header[0], header[1], &header[2..]));
+            }
+            _ => (),
+        }
+    }
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    chunks.sort_by_key(|(sequence_number, _, _)| *sequence_number);
+    let chunk_count = chunks[0].1 as usize;
+    let complete = chunks.len() == chunk_count
+        && chunks
+            .iter()
+            .enumerate()
+            .all(|(index, (sequence_number, count, _))| *sequence_number as usize == index + 1 && *count as usize == chunk_count);
+    if !complete {
+        return Err("Invalid ICC profile chunks in JPEG data!".to_string());
+    }
+    Ok(Some(chunks.iter().flat_map(|(_, _, chunk)| chunk.iter().copied()).collect()))
+}
+
+/// Read the segment starting at `position`.
+/// Returns the marker (without the leading 0xff), the segment's content (without the length)
+/// and the position after the segment.
+///
+/// # Errors
+/// * If there is no marker at `position` or the segment is cut off.
+fn read_segment(data: &[u8], mut position: usize) -> Result<(u8, &[u8], usize), String> {
+    if data.get(position) != Some(&0xff) {
+        return Err(format!("Expected a JPEG marker at byte {}!", position));
+    }
+    // markers may be preceded by any amount of fill bytes
+    while data.get(position + 1) == Some(&0xff) {
+        position += 1;
+    }
+    let marker = *data.get(position + 1).ok_or("Unexpected end of JPEG data!")?;
+    position += 2;
+    if marker == 0xd8 || marker == 0xd9 || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+        return Ok((marker, &[], position));
+    }
+    if data.len() < position + 2 {
+        return Err("Unexpected end of JPEG data!".to_string());
+    }
+    let length = read_u16(data, position) as usize;
+    if length < 2 || data.len() < position + length {
+        return Err("Unexpected end of JPEG data!".to_string());
+    }
+    Ok((marker, &data[position + 2..position + length], position + length))
+}
+
+/// Read a big endian 16 bit value at `position`.
+fn read_u16(data: &[u8], position: usize) -> u16 {
+    ((data[position] as u16) << 8) | data[position + 1] as u16
+}
+
+impl Decoder {
+    /// Read the quantization tables of a DQT segment.
+    fn read_dqt(&mut self, mut segment: &[u8]) -> Result<(), String> {
+        while !segment.is_empty() {
+            let precision = segment[0] >> 4;
+            let id = (segment[0] & 0x0f) as usize;
+            let size = if precision == 0 { 64 } else { 128 };
+            if segment.len() < 1 + size {
+                return Err("Invalid DQT segment!".to_string());
+            }
+            self.q_tables[id & 3] = (0..64)
+                .map(|index| {
+                    if precision == 0 {
+                        segment[1 + index] as u16
+                    } else {
+                        read_u16(segment, 1 + 2 * index)
+                    }
+                })
+                .collect();
+            segment = &segment[1 + size..];
+        }
+        Ok(())
+    }
+
+    /// Read the huffman tables of a DHT segment.
+    fn read_dht(&mut self, mut segment: &[u8]) -> Result<(), String> {
+        while !segment.is_empty() {
+            if segment.len() < 17 {
+                return Err("Invalid DHT segment!".to_string());
+            }
+            let is_ac = segment[0] >> 4 == 1;
+            let id = (segment[0] & 0x0f) as usize & 3;
+            let mut counts = [0u8; 16];
+            counts.copy_from_slice(&segment[1..17]);
+            let total: usize = counts.iter().map(|count| *count as usize).sum();
+            if segment.len() < 17 + total {
+                return Err("Invalid DHT segment!".to_string());
+            }
+            let table = HuffmanTable::new(&counts, segment[17..17 + total].to_vec());
+            if is_ac {
+                self.ac_tables[id] = table;
+            } else {
+                self.dc_tables[id] = table;
+            }
+            segment = &segment[17 + total..];
+        }
+        Ok(())
+    }
+
+    /// Read the frame header of a SOF0 or SOF1 segment.
+    fn read_sof(&mut self, segment: &[u8]) -> Result<(), String> {
+        if segment.len() < 6 || segment[0] != 8 {
+            return Err("Only 8 bit JPEG files are supported!".to_string());
+        }
+        self.height = read_u16(segment, 1) as usize;
+        self.width = read_u16(segment, 3) as usize;
+        let count = segment[5] as usize;
+        if count != 1 && count != 3 && count != 4 {
+            return Err(format!("Unsupported amount of components: {}!", count));
+        }
+        if segment.len() < 6 + 3 * count || self.width == 0 || self.height == 0 {
+            return Err("Invalid SOF segment!".to_string());
+        }
+        self.components = (0..count)
+            .map(|index| {
+                let data = &segment[6 + 3 * index..9 + 3 * index];
+                Component {
+                    id: data[0],
+                    horizontal_factor: std::cmp::max(data[1] >> 4, 1) as usize,
+                    vertical_factor: std::cmp::max(data[1] & 0x0f, 1) as usize,
+                    q_table: (data[2] & 3) as usize,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        self.max_horizontal_factor = self.components.iter().map(|c| c.horizontal_factor).max().unwrap();
+        self.max_vertical_factor = self.components.iter().map(|c| c.vertical_factor).max().unwrap();
+
+        let (mcus_x, mcus_y) = self.mcu_count();
+        for component in &mut self.components {
+            component.blocks_per_line = mcus_x * component.horizontal_factor;
+            component.coefficients = vec![[0; 64]; component.blocks_per_line * mcus_y * component.vertical_factor];
+        }
+        Ok(())
+    }
+
+    /// The amount of MCUs per row and per column of an interleaved scan.
+    fn mcu_count(&self) -> (usize, usize) {
+        (
+            self.width.div_ceil(8 * self.max_horizontal_factor),
+            self.height.div_ceil(8 * self.max_vertical_factor),
+        )
+    }
+
+    /// Read the header of a SOS segment and decode the entropy coded data following it.
+    /// Returns the position after the entropy coded data.
+    fn read_scan(&mut self, segment: &[u8], data: &[u8], start: usize) -> Result<usize, String> {
+        if self.components.is_empty() {
+            return Err("Scan before frame header in JPEG data!".to_string());
+        }
+        let count = segment.first().copied().unwrap_or(0) as usize;
+        if count == 0 || segment.len() < 1 + 2 * count + 3 {
+            return Err("Invalid SOS segment!".to_string());
+        }
+        let mut scan_components = Vec::with_capacity(count);
+        for index in 0..count {
+            let id = segment[1 + 2 * index];
+            let tables = segment[2 + 2 * index];
+            let component = self
+                .components
+                .iter()
+                .position(|component| component.id == id)
+                .ok_or("Scan references an unknown component!")?;
+            self.components[component].dc_table = (tables >> 4) as usize & 3;
+            self.components[component].ac_table = (tables & 0x0f) as usize & 3;
+            scan_components.push(component);
+        }
+
+        let end = find_scan_end(data, start);
+        let mut reader = EntropyReader {
+            data: &data[start..end],
+            position: 0,
+            current_byte: 0,
+            bits_left: 0,
+        };
+
+        // a scan with a single component isn't interleaved: each MCU is one block of it
+        let (mcus_x, mcus_y) = if count == 1 {
+            let component = &self.components[scan_components[0]];
+            (
+                (self.width * component.horizontal_factor).div_ceil(8 * self.max_horizontal_factor),
+                (self.height * component.vertical_factor).div_ceil(8 * self.max_vertical_factor),
+            )
+        } else {
+            self.mcu_count()
+        };
+
+        let mut predictions = vec![0i32; count];
+        for mcu in 0..mcus_x * mcus_y {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                reader.restart();
+                predictions.iter_mut().for_each(|prediction| *prediction = 0);
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+            for (scan_index, component_index) in scan_components.iter().enumerate() {
+                let component = &self.components[*component_index];
+                let (blocks_x, blocks_y) = if count == 1 {
+                    (1, 1)
+                } else {
+                    (component.horizontal_factor, component.vertical_factor)
+                };
+                for block_y in 0..blocks_y {
+                    for block_x in 0..blocks_x {
+                        let row = mcu_y * blocks_y + block_y;
+                        let col = mcu_x * blocks_x + block_x;
+                        let block = self.decode_block(&mut reader, *component_index, &mut predictions[scan_index])?;
+                        let component = &mut self.components[*component_index];
+                        component.coefficients[row * component.blocks_per_line + col] = block;
+                    }
+                }
+            }
+        }
+        Ok(end)
+    }
+
+    /// Decode the DC and AC coefficients of one block.
+    fn decode_block(&self, reader: &mut EntropyReader, component_index: usize, prediction: &mut i32) -> Result<[i32; 64], String> {
+        let component = &self.components[component_index];
+        let mut block = [0i32; 64];
+
+        let category = reader.decode(&self.dc_tables[component.dc_table])?;
+        *prediction += reader.read_value(category);
+        block[0] = *prediction;
+
+        let ac_table = &self.ac_tables[component.ac_table];
+        let mut index = 1;
+        while index < 64 {
+            let symbol = reader.decode(ac_table)?;
+            let zeros = (symbol >> 4) as usize;
+            let category = symbol & 0x0f;
+            if category == 0 {
+                if zeros == 15 {
+                    index += 16;
+                    continue;
+                }
+                // end of block
+                break;
+            }
+            index += zeros;
+            if index > 63 {
+                return Err("Invalid AC coefficients in JPEG data!".to_string());
+            }
+            block[index] = reader.read_value(category);
+            index += 1;
+        }
+        Ok(block)
+    }
+
+    /// Dequantize and inverse DCT all components, upsample them and convert to RGB.
+    fn to_image(&self, color_matrix: ColorMatrix) -> Result<Image, String> {
+        if self.components.is_empty() {
+            return Err("No frame header in JPEG data!".to_string());
+        }
+        let planes: Vec<Vec<Vec<u8>>> = self
+            .components
+            .iter()
+            .map(|component| self.component_to_plane(component))
+            .collect::<Result<_, _>>()?;
+        let sample = |component: usize, x: usize, y: usize| -> f32 {
+            let c = &self.components[component];
+            let x = x * c.horizontal_factor / self.max_horizontal_factor;
+            let y = y * c.vertical_factor / self.max_vertical_factor;
+            planes[component][y][x] as f32
+        };
+
+        if self.components.len() == 4 {
+            return Ok(self.to_cmyk_image(&planes));
+        }
+
+        let matrix = color_matrix.ycbcr_to_rgb_matrix();
+        let mut red = vec![vec![0i16; self.width]; self.height];
+        let mut green = vec![vec![0i16; self.width]; self.height];
+        let mut blue = vec![vec![0i16; self.width]; self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b) = if self.components.len() == 1 {
+                    let value = sample(0, x, y);
+                    (value, value, value)
+                } else {
+                    let rgb = matrix * Vector3::new(sample(0, x, y), sample(1, x, y) - 128.0, sample(2, x, y) - 128.0);
+                    (rgb[0], rgb[1], rgb[2])
+                };
+                red[y][x] = clamp_sample(r);
+                green[y][x] = clamp_sample(g);
+                blue[y][x] = clamp_sample(b);
+            }
+        }
+        Ok(create_image(self.height as u16, self.width as u16, red, green, blue))
+    }
+
+    /// Convert the decoded planes of a four component file to a CMYK image, see `decode_jpg_with_color_matrix`.
+    /// Four component files are never subsampled by this encoder, but others might be.
+    fn to_cmyk_image(&self, planes: &[Vec<Vec<u8>>]) -> Image {
+        let inverted = self.adobe_transform.is_some();
+        let ycck = self.adobe_transform == Some(2);
+        let matrix = ColorMatrix::Bt601.ycbcr_to_rgb_matrix();
+        let sample = |component: usize, x: usize, y: usize| -> f32 {
+            let c = &self.components[component];
+            let x = x * c.horizontal_factor / self.max_horizontal_factor;
+            let y = y * c.vertical_factor / self.max_vertical_factor;
+            planes[component][y][x] as f32
+        };
+        let ink = |value: f32| if inverted { 255.0 - value } else { value };
+
+        let mut cyan = vec![vec![0i16; self.width]; self.height];
+        let mut magenta = vec![vec![0i16; self.width]; self.height];
+        let mut yellow = vec![vec![0i16; self.width]; self.height];
+        let mut black = vec![vec![0i16; self.width]; self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (c, m, ye) = if ycck {
+                    // YCCK holds C, M and Y converted like R, G and B
+                    let cmy = matrix * Vector3::new(sample(0, x, y), sample(1, x, y) - 128.0, sample(2, x, y) - 128.0);
+                    (cmy[0], cmy[1], cmy[2])
+                } else {
+                    (ink(sample(0, x, y)), ink(sample(1, x, y)), ink(sample(2, x, y)))
+                };
+                cyan[y][x] = clamp_sample(c);
+                magenta[y][x] = clamp_sample(m);
+                yellow[y][x] = clamp_sample(ye);
+                black[y][x] = clamp_sample(ink(sample(3, x, y)));
+            }
+        }
+        create_cmyk_image(self.height as u16, self.width as u16, cyan, magenta, yellow, black)
+    }
+
+    /// Dequantize and inverse DCT the blocks of a component into a plane of samples.
+    fn component_to_plane(&self, component: &Component) -> Result<Vec<Vec<u8>>, String> {
+        let q_table = &self.q_tables[component.q_table];
+        if q_table.is_empty() {
+            return Err("Missing quantization table in JPEG data!".to_string());
+        }
+        let height = component.coefficients.len() / component.blocks_per_line * 8;
+        let mut plane = vec![vec![0u8; component.blocks_per_line * 8]; height];
+        for (index, coefficients) in component.coefficients.iter().enumerate() {
+            let mut dequantized = [0f32; 64];
+            for (k, value) in dequantized.iter_mut().enumerate() {
+                *value = (coefficients[k] * q_table[k] as i32) as f32;
+            }
+            let block: SMatrix<f32, 8, 8> = matrix_inverse_dct(&unsample_zigzag(&dequantized));
+            let x = (index % component.blocks_per_line) * 8;
+            let y = (index / component.blocks_per_line) * 8;
+            for i in 0..8 {
+                for j in 0..8 {
+                    plane[y + i][x + j] = (block[(i, j)] + 128.0).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+        Ok(plane)
+    }
+}
+
+/// Find the end of the entropy coded data starting at `start`, which is the first marker
+/// that is neither a stuffed zero byte nor a restart marker.
+fn find_scan_end(data: &[u8], start: usize) -> usize {
+    let mut position = start;
+    while position + 1 < data.len() {
+        if data[position] == 0xff && data[position + 1] != 0x00 && !(0xd0..=0xd7).contains(&data[position + 1]) {
+            return position;
+        }
+        position += 1;
+    }
+    data.len()
+}
+
+/// Round a sample and clamp it to 0..=255.
+fn clamp_sample(value: f32) -> i16 {
+    value.round().clamp(0.0, 255.0) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use scoped_threadpool::Pool;
+
+    use crate::dct::DCTMode;
+    use crate::encoder::{encode_dct_to_stream, encode_four_channel_dct_to_stream, EncoderSettings};
+    use crate::image::{ColorMatrix, Image};
+    use crate::jpg_writer::AdobeTransform;
+    use crate::image_metrics::compare_images;
+    use crate::parallel_dct;
+    use crate::ppm_parser::read_ppm_from_file;
+    use crate::quantization;
+    use crate::test_utils::prepare_image;
+
+    use super::{
+        decode_jpg, decode_jpg_with_color_matrix, extract_icc_profile, find_scan_end, read_segment, EntropyReader, HuffmanTable,
+    };
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    fn encode(filename: &str, settings: &EncoderSettings) -> Vec<u8> {
+        encode_with_color_matrix(filename, settings, ColorMatrix::Bt601)
+    }
+
+    fn encode_with_color_matrix(filename: &str, settings: &EncoderSettings, color_matrix: ColorMatrix) -> Vec<u8> {
+        let mut pool = get_pool();
+        let (image, (y_dct, cb_dct, cr_dct)) = prepare_image(filename, color_matrix, &mut pool);
+        encode_dct_to_stream(&image, y_dct, cb_dct, cr_dct, settings, &mut pool).data().clone()
+    }
+
+    #[test]
+    fn test_huffman_table_decode() {
+        // codes: 0 => 00, 1 => 01, 2 => 100, 3 => 101
+        let table = HuffmanTable::new(&[0, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], vec![0, 1, 2, 3]);
+        let data = [0b0001_1001, 0b0100_0000];
+        let mut reader = EntropyReader {
+            data: &data,
+            position: 0,
+            current_byte: 0,
+            bits_left: 0,
+        };
+        assert_eq!(Ok(0), reader.decode(&table));
+        assert_eq!(Ok(1), reader.decode(&table));
+        assert_eq!(Ok(2), reader.decode(&table));
+        assert_eq!(Ok(3), reader.decode(&table));
+    }
+
+    #[test]
+    fn test_read_value_and_byte_unstuffing() {
+        let data = [0xff, 0x00, 0b0110_0000];
+        let mut reader = EntropyReader {
+            data: &data,
+            position: 0,
+            current_byte: 0,
+            bits_left: 0,
+        };
+        // 1111 => 15, 1111 => 15, 011 => -4
+        assert_eq!(15, reader.read_value(4));
+        assert_eq!(15, reader.read_value(4));
+        assert_eq!(-4, reader.read_value(3));
+    }
+
+    #[test]
+    fn test_find_scan_end() {
+        let data = [0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56, 0xff, 0xd9];
+        assert_eq!(7, find_scan_end(&data, 0));
+    }
+
+    #[test]
+    fn test_decode_jpg_dimensions() {
+        let data = encode("test/test_73x117_color.ppm", &EncoderSettings::default());
+        let image = decode_jpg(&data).unwrap();
+        assert_eq!(73, image.width());
+        assert_eq!(117, image.height());
+        assert_eq!(117, image.channel1().len());
+        assert_eq!(73, image.channel1()[0].len());
+    }
+
+    #[test]
+    fn test_decode_jpg_is_close_to_original() {
+        let settings = EncoderSettings {
+            luminance_q_table: quantization::uniform_q_table(1.0),
+            chrominance_q_table: quantization::uniform_q_table(1.0),
+            ..Default::default()
+        };
+        let data = encode("test/test_80x128_color.ppm", &settings);
+        let original = read_ppm_from_file("test/test_80x128_color.ppm");
+        let metrics = compare_images(&original, &decode_jpg(&data).unwrap());
+        // chroma subsampling is the main loss with these tables
+        assert!(metrics.overall.psnr > 30.0);
+        assert!(metrics.overall.ms_ssim > 0.95);
+    }
+
+    #[test]
+    fn test_decode_jpg_with_matching_color_matrix() {
+        let original = read_ppm_from_file("test/test_80x128_color.ppm");
+        let data = encode_with_color_matrix("test/test_80x128_color.ppm", &EncoderSettings::default(), ColorMatrix::Bt709);
+        let matching = compare_images(&original, &decode_jpg_with_color_matrix(&data, ColorMatrix::Bt709).unwrap());
+        let mismatching = compare_images(&original, &decode_jpg(&data).unwrap());
+        assert!(matching.overall.psnr > mismatching.overall.psnr);
+    }
+
+    /// Encode the image as CMYK or YCCK with fine quantization, decode it again and
+    /// return the mean absolute difference of each channel's amounts of ink.
+    fn four_channel_round_trip_error(transform: AdobeTransform) -> Vec<f64> {
+        let mut pool = get_pool();
+        let mut image = read_ppm_from_file("test/test_73x117_color.ppm");
+        image.rgb_to_cmyk();
+        let original = image.clone();
+        match transform {
+            AdobeTransform::Ycck => image.cmyk_to_ycck(),
+            _ => image.cmyk_to_adobe(),
+        }
+        let [c, m, y, k] = parallel_dct::dct_four_channels(&mut image, &DCTMode::Arai, &mut pool);
+        let settings = EncoderSettings {
+            luminance_q_table: quantization::uniform_q_table(1.0),
+            chrominance_q_table: quantization::uniform_q_table(1.0),
+            ..Default::default()
+        };
+        let stream = encode_four_channel_dct_to_stream(&image, c, m, y, k, transform, &settings, &mut pool);
+        let decoded = decode_jpg(stream.data()).unwrap();
+        assert_eq!(4, decoded.channel_count());
+        let channels = |image: &Image| [image.channel1().clone(), image.channel2().clone(), image.channel3().clone(), image.channel4().clone()];
+        channels(&original)
+            .iter()
+            .zip(channels(&decoded).iter())
+            .map(|(expected, actual)| {
+                let difference: i64 = expected
+                    .iter()
+                    .flatten()
+                    .zip(actual.iter().flatten())
+                    .map(|(a, b)| (a - b).abs() as i64)
+                    .sum();
+                difference as f64 / (73 * 117) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_jpg_cmyk() {
+        for error in four_channel_round_trip_error(AdobeTransform::Unknown) {
+            assert!(error < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_decode_jpg_ycck() {
+        for error in four_channel_round_trip_error(AdobeTransform::Ycck) {
+            assert!(error < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_extract_icc_profile() {
+        let profile: Vec<u8> = (0..100_000).map(|index| (index * 7 % 256) as u8).collect();
+        let settings = EncoderSettings {
+            icc_profile: Some(profile.clone()),
+            ..Default::default()
+        };
+        let data = encode("test/test_16x16_color.ppm", &settings);
+        assert_eq!(Ok(Some(profile)), extract_icc_profile(&data));
+        // the profile doesn't get in the way of decoding
+        assert_eq!(16, decode_jpg(&data).unwrap().width());
+    }
+
+    #[test]
+    fn test_extract_icc_profile_none() {
+        let data = encode("test/test_16x16_color.ppm", &EncoderSettings::default());
+        assert_eq!(Ok(None), extract_icc_profile(&data));
+    }
+
+    #[test]
+    fn test_extract_icc_profile_out_of_order() {
+        let mut data = vec![0xff, 0xd8];
+        for (sequence_number, content) in [(2u8, [3u8, 4]), (1, [1, 2])] {
+            data.extend_from_slice(&[0xff, 0xe2, 0, 18]);
+            data.extend_from_slice(b"ICC_PROFILE\0");
+            data.extend_from_slice(&[sequence_number, 2]);
+            data.extend_from_slice(&content);
+        }
+        data.extend_from_slice(&[0xff, 0xd9]);
+        assert_eq!(Ok(Some(vec![1, 2, 3, 4])), extract_icc_profile(&data));
+    }
+
+    #[test]
+    fn test_extract_icc_profile_missing_chunk() {
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe2, 0, 18];
+        data.extend_from_slice(b"ICC_PROFILE\0");
+        data.extend_from_slice(&[1, 2, 1, 2, 0xff, 0xd9]);
+        assert_eq!("Invalid ICC profile chunks in JPEG data!", extract_icc_profile(&data).unwrap_err());
+        assert_eq!("Not a JPEG file!", extract_icc_profile(b"P3\n1 1").unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_jpg_not_a_jpeg() {
+        assert_eq!("Not a JPEG file!", decode_jpg(b"P3\n1 1\n255\n0 0 0\n").unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_jpg_truncated() {
+        let data = encode("test/test_16x16_color.ppm", &EncoderSettings::default());
+        assert_eq!("Unexpected end of JPEG data!", decode_jpg(&data[..100]).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_jpg_undefined_huffman_table() {
+        let data = encode("test/test_16x16_color.ppm", &EncoderSettings::default());
+        // copy the file without its DHT segments
+        let mut stripped = data[..2].to_vec();
+        let mut position = 2;
+        loop {
+            let (marker, _, next) = read_segment(&data, position).unwrap();
+            if marker != 0xc4 {
+                stripped.extend_from_slice(&data[position..next]);
+            }
+            position = next;
+            if marker == 0xda {
+                break;
+            }
+        }
+        let scan_start = stripped.len();
+        stripped.extend_from_slice(&data[position..]);
+        // a leading 0 bit would match a code of length 1 in a table without codes
+        stripped[scan_start] = 0;
+        assert_eq!("Invalid huffman code in JPEG data!", decode_jpg(&stripped).unwrap_err());
+    }
+}
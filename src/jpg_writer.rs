@@ -1,15 +1,26 @@
-use modinverse::egcd;
 use nalgebra::SMatrix;
 
 use crate::bit_stream::BitStream;
+use crate::exif::Exif;
 use crate::huffman::{HuffmanCode, HuffmanCodeMap};
 use crate::image::Image;
-use crate::quantization;
+use crate::{quantization, xmp};
+
+/// The maximum amount of bytes in a segment, excluding the marker and the length field.
+pub const MAX_SEGMENT_CONTENT_LENGTH: usize = 65533;
+/// The identifier at the start of the APP2 segments holding an ICC profile.
+pub const ICC_PROFILE_IDENTIFIER: &[u8; 12] = b"ICC_PROFILE\0";
+/// The amount of profile bytes fitting into one APP2 segment,
+/// after the identifier and the chunk's sequence number and the total amount of chunks.
+const ICC_CHUNK_LENGTH: usize = MAX_SEGMENT_CONTENT_LENGTH - ICC_PROFILE_IDENTIFIER.len() - 2;
 
 /// Enum describing the different types of segments in a JPG file.
 pub enum SegmentType {
     SOI,
     APP0,
+    APP1,
+    APP2,
+    APP14,
     DQT,
     SOF0,
     DHT,
@@ -32,10 +43,10 @@ pub fn write_segment_to_stream(stream: &mut BitStream, image: &Image, segment_ty
     write_marker_for_segment(stream, &segment_type);
     match segment_type {
         SegmentType::SOI => (),
-        SegmentType::APP0 => write_app0_segment(stream, image),
+        SegmentType::APP0 => write_app0_segment(stream, &JfifHeader::default()),
         SegmentType::SOF0 => write_sof0_segment(stream, image),
-        SegmentType::COM => write_com_segment(stream),
-        SegmentType::SOS => write_sos_segment(stream),
+        SegmentType::COM => write_com_segment(stream, DEFAULT_COMMENT.as_bytes()),
+        SegmentType::SOS => write_sos_segment(stream, image),
         SegmentType::EOI => (),
         _ => panic!("Not implemented yet!"),
     };
@@ -45,6 +56,9 @@ fn write_marker_for_segment(stream: &mut BitStream, segment_type: &SegmentType)
     stream.append::<u16>(match segment_type {
         SegmentType::SOI => 0xffd8,
         SegmentType::APP0 => 0xffe0,
+        SegmentType::APP1 => 0xffe1,
+        SegmentType::APP2 => 0xffe2,
+        SegmentType::APP14 => 0xffee,
         SegmentType::SOF0 => 0xffc0,
         SegmentType::EOI => 0xffd9,
         SegmentType::DHT => 0xffc4,
@@ -54,31 +68,329 @@ fn write_marker_for_segment(stream: &mut BitStream, segment_type: &SegmentType)
     });
 }
 
+/// The pixel density stored in the JFIF header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Density {
+    /// No unit, only the aspect ratio of a pixel (horizontal to vertical). 1:1 for square pixels.
+    AspectRatio(u16, u16),
+    /// Horizontal and vertical dots per inch.
+    DotsPerInch(u16, u16),
+    /// Horizontal and vertical dots per centimetre.
+    DotsPerCm(u16, u16),
+}
+
+impl Default for Density {
+    /// Square pixels without a unit.
+    fn default() -> Density {
+        Density::AspectRatio(1, 1)
+    }
+}
+
+impl Density {
+    /// Get the unit as stored in the JFIF header: 0 for none (aspect ratio only), 1 for dots per inch
+    /// and 2 for dots per centimetre.
+    pub fn unit(&self) -> u8 {
+        match self {
+            Density::AspectRatio(_, _) => 0,
+            Density::DotsPerInch(_, _) => 1,
+            Density::DotsPerCm(_, _) => 2,
+        }
+    }
+
+    /// Get the horizontal and vertical density.
+    pub fn values(&self) -> (u16, u16) {
+        match *self {
+            Density::AspectRatio(x, y) | Density::DotsPerInch(x, y) | Density::DotsPerCm(x, y) => (x, y),
+        }
+    }
+}
+
+impl std::str::FromStr for Density {
+    type Err = String;
+
+    /// Parse a density given as e.g. "300dpi", "300x150dpi", "118dpcm" or a pixel aspect ratio like "4:3".
+    fn from_str(value: &str) -> Result<Density, String> {
+        let (x, y, unit) = if let Some((x, y)) = value.split_once(':') {
+            (x, y, Density::AspectRatio as fn(u16, u16) -> Density)
+        } else if let Some(numbers) = value.strip_suffix("dpi") {
+            let (x, y) = numbers.split_once('x').unwrap_or((numbers, numbers));
+            (x, y, Density::DotsPerInch as fn(u16, u16) -> Density)
+        } else if let Some(numbers) = value.strip_suffix("dpcm") {
+            let (x, y) = numbers.split_once('x').unwrap_or((numbers, numbers));
+            (x, y, Density::DotsPerCm as fn(u16, u16) -> Density)
+        } else {
+            return Err(format!("Invalid density {}, expected a unit of dpi or dpcm or an aspect ratio!", value));
+        };
+        match (x.parse::<u16>(), y.parse::<u16>()) {
+            (Ok(x), Ok(y)) if x > 0 && y > 0 => Ok(unit(x, y)),
+            _ => Err(format!("Invalid density {}!", value)),
+        }
+    }
+}
+
+/// A thumbnail to embed in the JFIF header.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Thumbnail {
+    /// An uncompressed thumbnail stored in the APP0 segment itself, as 8 bit RGB triples row by row.
+    Rgb { width: u8, height: u8, data: Vec<u8> },
+    /// A complete JPEG file (SOI to EOI) stored in a JFXX extension segment.
+    Jpeg(Vec<u8>),
+}
+
+/// The JFXX extension code of thumbnails coded with JPEG.
+const JFXX_JPEG_THUMBNAIL: u8 = 0x10;
+
+/// The content of the JFIF header, validated on creation so that it can always be written.
+///
+/// # Attributes
+///
+/// * `version`: The major and minor version of the JFIF specification, 1.01 or 1.02.
+///   JFXX extension segments (used for JPEG thumbnails) were introduced with 1.02.
+/// * `density`: The pixel density, including its unit.
+/// * `thumbnail`: The thumbnail to embed, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JfifHeader {
+    version: (u8, u8),
+    density: Density,
+    thumbnail: Option<Thumbnail>,
+}
+
+impl Default for JfifHeader {
+    /// JFIF 1.01 with square pixels and without a thumbnail.
+    fn default() -> JfifHeader {
+        JfifHeader {
+            version: (1, 1),
+            density: Density::default(),
+            thumbnail: None,
+        }
+    }
+}
+
+impl JfifHeader {
+    /// Create a JFIF header with the lowest version supporting the thumbnail,
+    /// i.e. 1.02 for JPEG thumbnails and 1.01 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `density`: The pixel density to store.
+    /// * `thumbnail`: The thumbnail to embed, if any.
+    ///
+    /// # Examples
+    /// ```
+    /// let header = JfifHeader::new(Density::DotsPerInch(300, 300), None).unwrap();
+    /// write_jfif_segments(&mut stream, &header);
+    /// ```
+    pub fn new(density: Density, thumbnail: Option<Thumbnail>) -> Result<JfifHeader, String> {
+        let version = match thumbnail {
+            Some(Thumbnail::Jpeg(_)) => (1, 2),
+            _ => (1, 1),
+        };
+        JfifHeader::with_version(version, density, thumbnail)
+    }
+
+    /// Create a JFIF header with the given version.
+    ///
+    /// # Arguments
+    ///
+    /// * `version`: The major and minor version, e.g. (1, 2) for 1.02.
+    /// * `density`: The pixel density to store.
+    /// * `thumbnail`: The thumbnail to embed, if any.
+    ///
+    /// # Errors
+    /// * If the version isn't 1.00 to 1.02, or a JPEG thumbnail is combined with a version before 1.02.
+    /// * If a density value is 0.
+    /// * If the RGB thumbnail's data doesn't match its size, or a thumbnail doesn't fit into its segment.
+    pub fn with_version(version: (u8, u8), density: Density, thumbnail: Option<Thumbnail>) -> Result<JfifHeader, String> {
+        if version.0 != 1 || version.1 > 2 {
+            return Err(format!("Unsupported JFIF version {}.{:02}!", version.0, version.1));
+        }
+        let (x_density, y_density) = density.values();
+        if x_density == 0 || y_density == 0 {
+            return Err(format!("Invalid density {}x{}, it must not be 0!", x_density, y_density));
+        }
+        match &thumbnail {
+            Some(Thumbnail::Rgb { width, height, data }) => {
+                if data.len() != 3 * *width as usize * *height as usize {
+                    return Err(format!("RGB thumbnail has {} bytes instead of 3x{}x{}!", data.len(), width, height));
+                }
+                if 14 + data.len() > MAX_SEGMENT_CONTENT_LENGTH {
+                    return Err(format!("RGB thumbnail of {}x{} pixels doesn't fit into the APP0 segment!", width, height));
+                }
+            }
+            Some(Thumbnail::Jpeg(data)) => {
+                if version.1 < 2 {
+                    return Err("JPEG thumbnails require JFIF 1.02!".to_string());
+                }
+                if 6 + data.len() > MAX_SEGMENT_CONTENT_LENGTH {
+                    return Err(format!("JPEG thumbnail of {} bytes doesn't fit into the JFXX segment!", data.len()));
+                }
+            }
+            None => (),
+        }
+        Ok(JfifHeader {
+            version,
+            density,
+            thumbnail,
+        })
+    }
+
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+    pub fn density(&self) -> Density {
+        self.density
+    }
+    pub fn thumbnail(&self) -> Option<&Thumbnail> {
+        self.thumbnail.as_ref()
+    }
+}
+
+/// Write the JFIF header: the APP0 segment and, if the thumbnail is a JPEG,
+/// the JFXX extension segment holding it. See `write_app0_segment`.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segments to.
+/// * `header`: The content of the header.
+pub fn write_jfif_segments(stream: &mut BitStream, header: &JfifHeader) {
+    write_marker_for_segment(stream, &SegmentType::APP0);
+    write_app0_segment(stream, header);
+    if let Some(Thumbnail::Jpeg(data)) = header.thumbnail() {
+        write_marker_for_segment(stream, &SegmentType::APP0);
+        stream.append((2 + 6 + data.len()) as u16);
+        // string "JFXX": 0x4a 0x46 0x58 0x58 0x00
+        stream.append_many(&[0x4au8, 0x46, 0x58, 0x58, 0x00]);
+        stream.append(JFXX_JPEG_THUMBNAIL);
+        stream.append_many(data);
+    }
+}
+
 /// Write the APP0 segment of the JPG file.
-/// This includes metadata regarding the version of the JFIF spec (in our case 1.1)
-/// as well as data about the image and a potential preview image.
+/// This includes the version of the JFIF spec, the pixel density and an uncompressed thumbnail, if any.
 ///
 /// # Arguments
 ///
 /// * `stream`: The BitStream to append the segment to.
-/// * `image`: The image to take the data from.
-fn write_app0_segment(stream: &mut BitStream, image: &Image) {
-    // length of segment: 16
-    stream.append::<u16>(16);
+/// * `header`: The content of the header.
+fn write_app0_segment(stream: &mut BitStream, header: &JfifHeader) {
+    let (width, height, data): (u8, u8, &[u8]) = match header.thumbnail() {
+        Some(Thumbnail::Rgb { width, height, data }) => (*width, *height, data),
+        _ => (0, 0, &[]),
+    };
+    // length of segment: 16 + thumbnail
+    stream.append((16 + data.len()) as u16);
     // string "JFIF": 0x4a 0x46 0x49 0x46 0x00
-    stream.append::<Vec<u8>>(vec![0x4a, 0x46, 0x49, 0x46, 0x00]); // TODO: use array rather than vec
-                                                                  // revision number 1.1: 0x01 0x01
-    stream.append::<u16>(0x0101);
-    // of pixel size (0 => no unit, aspect ratio instead)
-    stream.append::<u8>(0);
-    // aspect ratio
-    let (gcd, _1, _2) = egcd(image.width() as i32, image.height() as i32);
-    let aspect_width = image.width() / gcd as u16;
-    let aspect_height = image.height() / gcd as u16;
-    stream.append(aspect_width);
-    stream.append(aspect_height);
-    // no thumbnail: 0x00 0x00
-    stream.append::<u16>(0)
+    stream.append_many(&[0x4au8, 0x46, 0x49, 0x46, 0x00]);
+    stream.append(header.version.0);
+    stream.append(header.version.1);
+    // unit of the density (0 => no unit, pixel aspect ratio instead)
+    stream.append(header.density.unit());
+    let (x_density, y_density) = header.density.values();
+    stream.append(x_density);
+    stream.append(y_density);
+    // thumbnail size, 0x00 0x00 for none
+    stream.append(width);
+    stream.append(height);
+    stream.append_many(data);
+}
+
+/// Write the APP1 segment holding Exif metadata, see `Exif::to_bytes`.
+/// It has to follow SOI and APP0 directly.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segment to.
+/// * `exif`: The metadata to write.
+///
+/// # Panics
+/// * If the metadata doesn't fit into one segment.
+/// * See `Exif::to_bytes`.
+pub fn write_exif_segment(stream: &mut BitStream, exif: &Exif) {
+    let data = exif.to_bytes();
+    if data.len() > MAX_SEGMENT_CONTENT_LENGTH {
+        panic!("Exif metadata is too large!");
+    }
+    write_marker_for_segment(stream, &SegmentType::APP1);
+    stream.append((data.len() + 2) as u16);
+    stream.append_many(&data);
+}
+
+/// Write an XMP packet as APP1 segments, see `xmp::xmp_segments`.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segments to.
+/// * `packet`: The serialized XMP packet.
+pub fn write_xmp_segments(stream: &mut BitStream, packet: &str) {
+    for segment in xmp::xmp_segments(packet) {
+        write_marker_for_segment(stream, &SegmentType::APP1);
+        stream.append((segment.len() + 2) as u16);
+        stream.append_many(&segment);
+    }
+}
+
+/// Write an ICC profile as APP2 segments.
+/// A profile is split into as many chunks as needed to fit into segments, each of which starts with
+/// the identifier "ICC_PROFILE", the (1-based) sequence number of the chunk and the total amount of chunks.
+/// Nothing is written for an empty profile.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segments to.
+/// * `profile`: The ICC profile, as read from an .icc/.icm file.
+///
+/// # Panics
+/// * If the profile needs more than 255 chunks (about 16 MB).
+pub fn write_icc_profile_segments(stream: &mut BitStream, profile: &[u8]) {
+    let chunks = profile.chunks(ICC_CHUNK_LENGTH);
+    if chunks.len() > 255 {
+        panic!("ICC profile is too large to embed!");
+    }
+    let chunk_count = chunks.len() as u8;
+    for (index, chunk) in chunks.enumerate() {
+        write_marker_for_segment(stream, &SegmentType::APP2);
+        // length: the length field itself, identifier, sequence number, chunk count and the chunk
+        stream.append((2 + ICC_PROFILE_IDENTIFIER.len() + 2 + chunk.len()) as u16);
+        stream.append_many(ICC_PROFILE_IDENTIFIER);
+        stream.append(index as u8 + 1);
+        stream.append(chunk_count);
+        stream.append_many(chunk);
+    }
+}
+
+/// The colour transform stored in the Adobe APP14 segment.
+/// It tells decoders how to interpret the components, as there's no JFIF header for
+/// images other than grayscale and YCbCr.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdobeTransform {
+    /// No transform, i.e. RGB or CMYK.
+    Unknown = 0,
+    /// YCbCr.
+    YCbCr = 1,
+    /// YCCK.
+    Ycck = 2,
+}
+
+/// Write the Adobe APP14 segment, which marks a file as written by an Adobe application
+/// and (more importantly) stores the colour transform used for the components.
+/// Decoders expect this for CMYK and YCCK files, which they also expect to be stored inverted.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segment to.
+/// * `transform`: The colour transform used for the components.
+pub fn write_app14_segment(stream: &mut BitStream, transform: AdobeTransform) {
+    write_marker_for_segment(stream, &SegmentType::APP14);
+    // length of segment: 14
+    stream.append::<u16>(14);
+    // string "Adobe": 0x41 0x64 0x6f 0x62 0x65
+    stream.append_many(&[0x41u8, 0x64, 0x6f, 0x62, 0x65]);
+    // DCTEncode version 100
+    stream.append::<u16>(100);
+    // flags0 and flags1 - no special handling of any components
+    stream.append::<u16>(0);
+    stream.append::<u16>(0);
+    stream.append(transform as u8);
 }
 
 /// Write the SOF0 segment of the JPG file.
@@ -89,7 +401,12 @@ fn write_app0_segment(stream: &mut BitStream, image: &Image) {
 /// * `stream`: The BitStream to append the segment to.
 /// * `image`: The image to take the data from.
 fn write_sof0_segment(stream: &mut BitStream, image: &Image) {
-    // length, we always do coloured so 8 + 3*3
+    match image.channel_count() {
+        1 => return write_grayscale_sof0_segment(stream, image),
+        4 => return write_four_component_sof0_segment(stream, image),
+        _ => (),
+    }
+    // length, 8 + 3*3 for the three components
     stream.append::<u16>(17);
     // accuracy - we default to 8 as 12 and 16 aren't commonly supported
     stream.append::<u8>(8);
@@ -129,6 +446,42 @@ fn write_sof0_segment(stream: &mut BitStream, image: &Image) {
     );
 }
 
+/// Write the SOF0 segment of a grayscale image, with its single component using quantization table 0.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segment to.
+/// * `image`: The image to take the data from.
+fn write_grayscale_sof0_segment(stream: &mut BitStream, image: &Image) {
+    // length: 8 + 1*3
+    stream.append::<u16>(11);
+    stream.append::<u8>(8);
+    stream.append(image.height());
+    stream.append(image.width());
+    stream.append::<u8>(1);
+    write_sof0_segment_component(stream, 1, 1, false, 0, 1);
+}
+
+/// Write the SOF0 segment of a CMYK or YCCK image.
+/// Four channel images are never downsampled. The first and fourth component (C and K or Y and K)
+/// use quantization table 0, the others quantization table 1.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segment to.
+/// * `image`: The image to take the data from.
+fn write_four_component_sof0_segment(stream: &mut BitStream, image: &Image) {
+    // length: 8 + 4*3
+    stream.append::<u16>(20);
+    stream.append::<u8>(8);
+    stream.append(image.height());
+    stream.append(image.width());
+    stream.append::<u8>(4);
+    for (id, quantise_table) in [(1, 0), (2, 1), (3, 1), (4, 0)] {
+        write_sof0_segment_component(stream, id, 1, false, quantise_table, 1);
+    }
+}
+
 /// Write a component in the SOF0 segment.
 ///
 /// # Arguments
@@ -159,16 +512,39 @@ fn write_sof0_segment_component(
     stream.append(quantise_table);
 }
 
+/// The comment written by `write_segment_to_stream` for COM segments.
+pub const DEFAULT_COMMENT: &str = "Dieser Text wird wahrscheinlich als ASCII interpretiert. Scheiß Encoding!";
+
+/// Write a comment as COM segments. Comments longer than one segment can hold are split
+/// into several segments, between characters so none of them is cut in half.
+///
+/// # Arguments
+///
+/// * `stream`: The BitStream to append the segments to.
+/// * `comment`: The comment. It's written as UTF-8, so decoders may show anything
+///   but ASCII incorrectly.
+pub fn write_comment_segments(stream: &mut BitStream, comment: &str) {
+    if comment.is_empty() {
+        write_marker_for_segment(stream, &SegmentType::COM);
+        write_com_segment(stream, &[]);
+    }
+    let mut rest = comment;
+    while !rest.is_empty() {
+        let mut length = rest.len().min(MAX_SEGMENT_CONTENT_LENGTH);
+        while !rest.is_char_boundary(length) {
+            length -= 1;
+        }
+        let (chunk, remaining) = rest.split_at(length);
+        write_marker_for_segment(stream, &SegmentType::COM);
+        write_com_segment(stream, chunk.as_bytes());
+        rest = remaining;
+    }
+}
+
 /// Write the COM segment.
-fn write_com_segment(stream: &mut BitStream) {
-    stream.append::<u16>(76);
-    stream.append::<Vec<u8>>(vec![
-        0x44, 0x69, 0x65, 0x73, 0x65, 0x72, 0x20, 0x54, 0x65, 0x78, 0x74, 0x20, 0x77, 0x69, 0x72,
-        0x64, 0x20, 0x77, 0x61, 0x68, 0x72, 0x73, 0x63, 0x68, 0x65, 0x69, 0x6e, 0x6c, 0x69, 0x63,
-        0x68, 0x20, 0x61, 0x6c, 0x73, 0x20, 0x41, 0x53, 0x43, 0x49, 0x49, 0x20, 0x69, 0x6e, 0x74,
-        0x65, 0x72, 0x70, 0x72, 0x65, 0x74, 0x69, 0x65, 0x72, 0x74, 0x2e, 0x20, 0x53, 0x63, 0x68,
-        0x65, 0x69, 0xc3, 0x9f, 0x20, 0x45, 0x6e, 0x63, 0x6f, 0x64, 0x69, 0x6e, 0x67, 0x21,
-    ]);
+fn write_com_segment(stream: &mut BitStream, comment: &[u8]) {
+    stream.append((comment.len() + 2) as u16);
+    stream.append_many(comment);
 }
 
 /// Write the SOS segment of the JPG file.
@@ -178,20 +554,28 @@ fn write_com_segment(stream: &mut BitStream) {
 ///
 /// * `stream`: The BitStream to append the segment to.
 /// * `image`: The image to take the data from.
-fn write_sos_segment(stream: &mut BitStream) {
-    // length, we always do coloured so 6 + 2*3
-    stream.append::<u16>(12);
-    // number of components, we always do coloured so 3
-    stream.append::<u8>(3);
+fn write_sos_segment(stream: &mut BitStream, image: &Image) {
+    let components = image.channel_count() as u8;
+    // length: 6 + 2 per component
+    stream.append::<u16>(6 + 2 * components as u16);
+    // number of components, 1 for grayscale, 3 for YCbCr and 4 for CMYK/YCCK
+    stream.append::<u8>(components);
     // Y component - we use DC DHT 0, AC DHT 2
     stream.append::<u8>(1);
     stream.append::<u8>(0b0000_0010);
-    // Cb component - we use DC DHT 1, AC DHT 3
-    stream.append::<u8>(2);
-    stream.append::<u8>(0b0001_0011);
-    // Cr component - we use DC DHT 1, AC DHT 3
-    stream.append::<u8>(3);
-    stream.append::<u8>(0b0001_0011);
+    if components >= 3 {
+        // Cb component - we use DC DHT 1, AC DHT 3
+        stream.append::<u8>(2);
+        stream.append::<u8>(0b0001_0011);
+        // Cr component - we use DC DHT 1, AC DHT 3
+        stream.append::<u8>(3);
+        stream.append::<u8>(0b0001_0011);
+    }
+    if components == 4 {
+        // K component - like Y, we use DC DHT 0, AC DHT 2
+        stream.append::<u8>(4);
+        stream.append::<u8>(0b0000_0010);
+    }
     // unused info for spectral/predictor selection
     // irrelevant for us because we don't do lossless, just write defaults
     stream.append::<u8>(0x00);
@@ -242,11 +626,15 @@ pub fn write_dqt_segment(stream: &mut BitStream, q_table: &SMatrix<f32, 8, 8>, n
 #[cfg(test)]
 mod tests {
     use crate::bit_stream::BitStream;
+    use crate::exif::Exif;
     use crate::huffman::encode;
     use crate::jpg_writer::{
-        write_app0_segment, write_dht_segment, write_marker_for_segment, write_segment_to_stream,
-        write_sof0_segment, write_sof0_segment_component, SegmentType,
+        write_app0_segment, write_app14_segment, write_comment_segments, write_jfif_segments, JfifHeader, Thumbnail,
+        write_dht_segment, write_exif_segment, write_marker_for_segment, Density, DEFAULT_COMMENT,
+        write_icc_profile_segments, write_segment_to_stream, write_sof0_segment, write_sof0_segment_component,
+        AdobeTransform, SegmentType, MAX_SEGMENT_CONTENT_LENGTH,
     };
+    use crate::image::create_grayscale_image;
     use crate::ppm_parser::read_ppm_from_file;
     use crate::quantization;
 
@@ -273,8 +661,7 @@ mod tests {
     #[test]
     fn test_write_app0_segment_successful() {
         let mut stream = BitStream::open();
-        let image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
-        write_app0_segment(&mut stream, &image);
+        write_app0_segment(&mut stream, &JfifHeader::default());
         let data: Vec<u8> = vec![
             0, 16, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0, 0, 1, 0, 1, 0, 0,
         ];
@@ -282,6 +669,133 @@ mod tests {
         assert_eq!(8, stream.bits_in_last_byte());
     }
 
+    #[test]
+    fn test_write_jfif_segments_with_density() {
+        let mut stream = BitStream::open();
+        let header = JfifHeader::new(Density::DotsPerInch(300, 600), None).unwrap();
+        write_jfif_segments(&mut stream, &header);
+        let data: Vec<u8> = vec![
+            0xff, 0xe0, 0, 16, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 1, 0x01, 0x2c, 0x02, 0x58, 0, 0,
+        ];
+        assert_eq!(data, *stream.data());
+
+        let mut stream = BitStream::open();
+        write_jfif_segments(&mut stream, &JfifHeader::new(Density::DotsPerCm(118, 118), None).unwrap());
+        assert_eq!(&[2, 0, 118, 0, 118], &stream.data()[11..16]);
+    }
+
+    #[test]
+    fn test_write_jfif_segments_density_independent_of_image_size() {
+        // the density describes the pixels, not the image, so prime and extreme sizes get square pixels, too
+        for (width, height) in [(1, 1), (4093, 2000), (65535, 1), (65535, 65535)] {
+            let image = crate::image::create_image(height, width, vec![], vec![], vec![]);
+            let mut stream = BitStream::open();
+            write_segment_to_stream(&mut stream, &image, SegmentType::APP0);
+            assert_eq!(&[0, 0, 1, 0, 1, 0, 0], &stream.data()[11..18]);
+        }
+    }
+
+    #[test]
+    fn test_write_jfif_segments_rgb_thumbnail() {
+        let mut stream = BitStream::open();
+        let thumbnail = Thumbnail::Rgb { width: 2, height: 1, data: vec![1, 2, 3, 4, 5, 6] };
+        write_jfif_segments(&mut stream, &JfifHeader::new(Density::default(), Some(thumbnail)).unwrap());
+        let data: Vec<u8> = vec![
+            0xff, 0xe0, 0, 22, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0, 0, 1, 0, 1, 2, 1, 1, 2, 3, 4, 5, 6,
+        ];
+        assert_eq!(data, *stream.data());
+    }
+
+    #[test]
+    fn test_write_jfif_segments_jpeg_thumbnail() {
+        let mut stream = BitStream::open();
+        let thumbnail = Thumbnail::Jpeg(vec![0xff, 0xd8, 0xff, 0xd9]);
+        write_jfif_segments(&mut stream, &JfifHeader::new(Density::default(), Some(thumbnail)).unwrap());
+        let data = stream.data();
+        // JFIF 1.02 APP0 without thumbnail, directly followed by the JFXX extension
+        assert_eq!(&[0x01, 0x02], &data[9..11]);
+        assert_eq!(&[0, 0], &data[16..18]);
+        let jfxx: Vec<u8> = vec![0xff, 0xe0, 0, 12, 0x4a, 0x46, 0x58, 0x58, 0x00, 0x10, 0xff, 0xd8, 0xff, 0xd9];
+        assert_eq!(&jfxx, &data[18..]);
+    }
+
+    #[test]
+    fn test_jfif_header_validation() {
+        assert!(JfifHeader::with_version((1, 2), Density::DotsPerInch(72, 72), None).is_ok());
+        assert!(JfifHeader::with_version((2, 0), Density::default(), None).is_err());
+        assert!(JfifHeader::with_version((1, 3), Density::default(), None).is_err());
+        assert!(JfifHeader::new(Density::DotsPerInch(0, 72), None).is_err());
+        assert!(JfifHeader::new(Density::AspectRatio(1, 0), None).is_err());
+
+        let jpeg = Some(Thumbnail::Jpeg(vec![0xff, 0xd8, 0xff, 0xd9]));
+        assert!(JfifHeader::with_version((1, 1), Density::default(), jpeg).is_err());
+        let too_large = Some(Thumbnail::Jpeg(vec![0; MAX_SEGMENT_CONTENT_LENGTH]));
+        assert!(JfifHeader::new(Density::default(), too_large).is_err());
+
+        let wrong_size = Some(Thumbnail::Rgb { width: 2, height: 2, data: vec![0; 6] });
+        assert!(JfifHeader::new(Density::default(), wrong_size).is_err());
+        let largest = Some(Thumbnail::Rgb { width: 255, height: 85, data: vec![0; 3 * 255 * 85] });
+        assert!(JfifHeader::new(Density::default(), largest).is_ok());
+        let too_large = Some(Thumbnail::Rgb { width: 255, height: 86, data: vec![0; 3 * 255 * 86] });
+        assert!(JfifHeader::new(Density::default(), too_large).is_err());
+    }
+
+    #[test]
+    fn test_density_from_str() {
+        assert_eq!(Ok(Density::DotsPerInch(300, 300)), "300dpi".parse());
+        assert_eq!(Ok(Density::DotsPerInch(300, 150)), "300x150dpi".parse());
+        assert_eq!(Ok(Density::DotsPerCm(118, 118)), "118dpcm".parse());
+        assert_eq!(Ok(Density::AspectRatio(4, 3)), "4:3".parse());
+        assert!("300".parse::<Density>().is_err());
+        assert!("0dpi".parse::<Density>().is_err());
+        assert!("1:0".parse::<Density>().is_err());
+        assert!("axbdpi".parse::<Density>().is_err());
+    }
+
+    #[test]
+    fn test_write_comment_segments() {
+        let mut stream = BitStream::open();
+        write_comment_segments(&mut stream, "Hi");
+        write_comment_segments(&mut stream, "");
+        assert_eq!(&vec![0xff, 0xfe, 0, 4, b'H', b'i', 0xff, 0xfe, 0, 2], stream.data());
+    }
+
+    #[test]
+    fn test_write_comment_segments_long() {
+        let mut stream = BitStream::open();
+        let comment = "a".repeat(MAX_SEGMENT_CONTENT_LENGTH + 10);
+        write_comment_segments(&mut stream, &comment);
+        let data = stream.data();
+        assert_eq!(&[0xff, 0xfe, 0xff, 0xff], &data[0..4]);
+        let second = 4 + MAX_SEGMENT_CONTENT_LENGTH;
+        assert_eq!(&[0xff, 0xfe, 0, 12], &data[second..second + 4]);
+        assert_eq!(second + 4 + 10, data.len());
+    }
+
+    #[test]
+    fn test_write_comment_segments_splits_between_characters() {
+        let mut stream = BitStream::open();
+        // the two bytes of the last ß would be split by the segment boundary
+        let comment = format!("{}ßß", "a".repeat(MAX_SEGMENT_CONTENT_LENGTH - 3));
+        write_comment_segments(&mut stream, &comment);
+        let data = stream.data();
+        let first_length = u16::from_be_bytes([data[2], data[3]]) as usize - 2;
+        assert_eq!(MAX_SEGMENT_CONTENT_LENGTH - 1, first_length);
+        let second = 4 + first_length;
+        assert_eq!(&[0xff, 0xfe, 0, 4], &data[second..second + 4]);
+        assert_eq!("ß".as_bytes(), &data[second + 4..]);
+        assert!(std::str::from_utf8(&data[4..second]).unwrap().ends_with('ß'));
+    }
+
+    #[test]
+    fn test_write_default_com_segment() {
+        let mut stream = BitStream::open();
+        let image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        write_segment_to_stream(&mut stream, &image, SegmentType::COM);
+        assert_eq!(&[0xff, 0xfe, 0, 76], &stream.data()[0..4]);
+        assert_eq!(DEFAULT_COMMENT.as_bytes(), &stream.data()[4..]);
+    }
+
     #[test]
     fn test_write_sof0_segment_component_downsampled_vertically_true_factor2() {
         let mut stream = BitStream::open();
@@ -342,7 +856,8 @@ mod tests {
     #[test]
     fn test_write_sos_segment() {
         let mut stream = BitStream::open();
-        write_sos_segment(&mut stream);
+        let image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        write_sos_segment(&mut stream, &image);
         let expected_data: Vec<u8> = vec![
             0x00,
             0x0c,
@@ -360,6 +875,100 @@ mod tests {
         assert_eq!(&expected_data, stream.data());
     }
 
+    #[test]
+    fn test_write_sof0_and_sos_segment_four_components() {
+        let mut stream = BitStream::open();
+        let mut image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        image.rgb_to_cmyk();
+        write_sof0_segment(&mut stream, &image);
+        write_sos_segment(&mut stream, &image);
+        let data: Vec<u8> = vec![
+            0, 20, 8, 0, 4, 0, 4, 4, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1, 4, 0x11, 0, // SOF0
+            0, 14, 4, 1, 0x02, 2, 0x13, 3, 0x13, 4, 0x02, 0x00, 0x3f, 0x00, // SOS
+        ];
+        assert_eq!(data, *stream.data());
+    }
+
+    #[test]
+    fn test_write_sof0_and_sos_segment_grayscale() {
+        let mut stream = BitStream::open();
+        let image = create_grayscale_image(4, 4, vec![vec![0; 4]; 4]);
+        write_sof0_segment(&mut stream, &image);
+        write_sos_segment(&mut stream, &image);
+        let data: Vec<u8> = vec![
+            0, 11, 8, 0, 4, 0, 4, 1, 1, 0x11, 0, // SOF0
+            0, 8, 1, 1, 0x02, 0x00, 0x3f, 0x00, // SOS
+        ];
+        assert_eq!(data, *stream.data());
+    }
+
+    #[test]
+    fn test_write_icc_profile_segments_single_chunk() {
+        let mut stream = BitStream::open();
+        write_icc_profile_segments(&mut stream, &[1, 2, 3]);
+        let mut expected: Vec<u8> = vec![0xff, 0xe2, 0, 19];
+        expected.extend_from_slice(b"ICC_PROFILE\0");
+        expected.extend_from_slice(&[1, 1, 1, 2, 3]);
+        assert_eq!(expected, *stream.data());
+    }
+
+    #[test]
+    fn test_write_icc_profile_segments_multiple_chunks() {
+        let mut stream = BitStream::open();
+        let profile: Vec<u8> = (0..150_000).map(|index| (index % 251) as u8).collect();
+        write_icc_profile_segments(&mut stream, &profile);
+        let data = stream.data();
+
+        // 65519 + 65519 + 18962 bytes
+        let mut position = 0;
+        let mut reassembled = vec![];
+        for (sequence_number, chunk_length) in [(1, 65519), (2, 65519), (3, 18962)] {
+            assert_eq!(&[0xff, 0xe2], &data[position..position + 2]);
+            let length = ((data[position + 2] as usize) << 8) | data[position + 3] as usize;
+            assert_eq!(2 + 14 + chunk_length, length);
+            assert!(length - 2 <= MAX_SEGMENT_CONTENT_LENGTH);
+            assert_eq!(b"ICC_PROFILE\0", &data[position + 4..position + 16]);
+            assert_eq!(&[sequence_number, 3], &data[position + 16..position + 18]);
+            reassembled.extend_from_slice(&data[position + 18..position + 2 + length]);
+            position += 2 + length;
+        }
+        assert_eq!(data.len(), position);
+        assert_eq!(profile, reassembled);
+    }
+
+    #[test]
+    fn test_write_icc_profile_segments_empty() {
+        let mut stream = BitStream::open();
+        write_icc_profile_segments(&mut stream, &[]);
+        assert!(stream.data().is_empty());
+    }
+
+    #[test]
+    fn test_write_exif_segment() {
+        let mut stream = BitStream::open();
+        let exif = Exif {
+            orientation: Some(3),
+            ..Default::default()
+        };
+        write_exif_segment(&mut stream, &exif);
+        let data = stream.data();
+        let content = exif.to_bytes();
+        assert_eq!(&[0xff, 0xe1], &data[0..2]);
+        assert_eq!((content.len() + 2) as u16, ((data[2] as u16) << 8) | data[3] as u16);
+        assert_eq!(&content, &data[4..]);
+    }
+
+    #[test]
+    fn test_write_app14_segment() {
+        let mut stream = BitStream::open();
+        write_app14_segment(&mut stream, AdobeTransform::Ycck);
+        let data: Vec<u8> = vec![
+            0xff, 0xee, 0, 14, 0x41, 0x64, 0x6f, 0x62, 0x65, 0, 100, 0, 0, 0, 0, 2,
+        ];
+        assert_eq!(data, *stream.data());
+        assert_eq!(8, stream.bits_in_last_byte());
+    }
+
     #[test]
     fn test_write_whole_image_with_downsampling() {
         let mut stream = BitStream::open();
@@ -535,7 +1144,7 @@ mod tests {
         let data: Vec<u8> = vec![
             0xff, 0xd8,
             //APP0: length 2 byte, JFIF0, major revision 1 byte, minor revision 1 byte, pixel ratio mode 1byte, x density 2 byte, y density 2 byte, thumbnail
-            0xff, 0xe0, 0, 16, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0, 0, 16, 0, 9, 0, 0,
+            0xff, 0xe0, 0, 16, 0x4a, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0, 0, 1, 0, 1, 0, 0,
             //SOF0
             0xff, 0xc0, 0, 17, 8, 8, 112, 15, 0, 3, 1, 0x22, 0, 2, 0x11, 0, 3, 0x11, 0,
             //EOI
@@ -6,94 +6,276 @@ use scoped_threadpool::Pool;
 use dct::DCTMode;
 
 use crate::bit_stream::BitStream;
+use crate::image::{ColorSpace, Image};
+use crate::image_reader::InputFormat;
+use crate::jpg_writer::{AdobeTransform, JfifHeader};
 use crate::utils::THREAD_COUNT;
 
 mod appendable_to_bit_stream;
 mod arai;
+mod batch;
 mod bit_stream;
+mod bmp_reader;
+mod cli;
 mod coefficient_encoder;
+mod color_conversion;
 mod dct;
 mod dct_constant_calculator;
 mod dct_constants;
 mod dct_to_ppm;
 mod downsample;
+mod encoder;
+mod exif;
 mod huffman;
 mod huffman_decoder;
 mod image;
 mod image_data_writer;
+mod image_metrics;
+mod image_reader;
+mod inflate;
+mod jpg_reader;
 mod jpg_writer;
 mod package_merge;
+mod png_reader;
+mod parallel_color_conversion;
 mod parallel_dct;
 mod parallel_downsample;
 mod parallel_idct;
 mod parallel_quantize;
 mod ppm_parser;
+mod ppm_writer;
+mod quality_control;
 mod quantization;
+mod rate_control;
+mod resize;
+#[cfg(test)]
+mod test_utils;
+mod thumbnail;
 mod utils;
+mod xmp;
+mod yuv_reader;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() == 1 {
-        panic!("No file name specified!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let arguments = cli::parse_arguments(&args);
+    let reader_settings = image_reader::ReaderSettings {
+        frame: arguments.frame,
+        yuv_size: arguments.yuv_size,
+        yuv_range: arguments.yuv_range,
+    };
+    if arguments.command == cli::Command::Batch {
+        let encode = |image, input_format, output: &str, pool: &mut Pool| encode_sizes(image, input_format, &arguments, output, pool);
+        let summary = match batch::encode_batch(&arguments.input, &arguments.output, &reader_settings, *THREAD_COUNT, encode) {
+            Ok(summary) => summary,
+            Err(error) => panic!("{}", error),
+        };
+        print!("{}", summary.table());
+        if summary.failed_count() > 0 {
+            std::process::exit(1);
+        }
+        return;
     }
-    let mut image = ppm_parser::read_ppm_from_file(&args[1]);
 
+    let read_result = if arguments.input == cli::STDIO_PATH {
+        image_reader::read_image(std::io::stdin().lock(), "stdin", &reader_settings)
+    } else {
+        image_reader::read_image_from_file(&arguments.input, &reader_settings)
+    };
+    let (image, input_format) = match read_result {
+        Ok(result) => result,
+        Err(error) => panic!("{}", error),
+    };
     let mut pool = Pool::new(*THREAD_COUNT as u32);
+    encode_sizes(image, input_format, &arguments, &arguments.output, &mut pool);
+}
 
-    image.rgb_to_ycbcr();
-    image.downsample(4, 2, 0);
-
-    let (mut y_dct, mut cb_dct, mut cr_dct) = parallel_dct::dct(&mut image, &DCTMode::Arai, &mut pool);
-
-    let luminance_q_table = quantization::box_q_table(1f32, 3, 1f32);
-    let chrominance_q_table = quantization::box_q_table(2f32, 3, 1f32);
+/// Encode an image at each width given with `--resize`, keeping the aspect ratio, or at its own size without.
+/// With several widths, the width is added to the name of the output, e.g. `output_640.jpg`. See `encode_image`.
+///
+/// Returns the size of all encoded files together in bytes.
+fn encode_sizes(image: Image, input_format: InputFormat, arguments: &cli::Arguments, output: &str, pool: &mut Pool) -> usize {
+    if arguments.resize_widths.is_empty() {
+        return encode_image(image, input_format.is_ycbcr(), arguments, output, pool);
+    }
+    // video frames have subsampled chroma, but all channels need to have the full size to be resized
+    let image = if input_format.is_ycbcr() { image.to_rgb(arguments.color_matrix) } else { image };
+    arguments
+        .resize_widths
+        .iter()
+        .map(|&width| {
+            let resized = image.resize(width, image.resized_height(width), arguments.resize_filter);
+            let output = match arguments.resize_widths.len() {
+                1 => output.to_string(),
+                _ => suffixed_path(output, &width.to_string()),
+            };
+            encode_image(resized, false, arguments, &output, pool)
+        })
+        .sum()
+}
 
-    let y_quant = parallel_quantize::quantize_zigzag(&mut y_dct, luminance_q_table, &mut pool);
-    let cb_quant = parallel_quantize::quantize_zigzag(&mut cb_dct, chrominance_q_table, &mut pool);
-    let cr_quant = parallel_quantize::quantize_zigzag(&mut cr_dct, chrominance_q_table, &mut pool);
+/// Encode an image as set by the arguments and write it to the output,
+/// or with `compare`, decode it again and print how much it differs from the image.
+///
+/// # Arguments
+/// * `image`: The image as read from the input file.
+/// * `ycbcr_input`: Whether the image is YCbCr with subsampled chroma, as read from video frames, instead of RGB.
+/// * `arguments`: The command line arguments.
+/// * `output`: The path to write the encoded file to, `-` for stdout.
+/// * `pool`: The thread pool to encode with.
+///
+/// Returns the size of the encoded file in bytes.
+///
+/// # Panics
+/// * If the arguments can't be applied to the image, e.g. CMYK for video frames.
+/// * If a file given in the arguments can't be read or the output can't be written.
+fn encode_image(mut image: Image, ycbcr_input: bool, arguments: &cli::Arguments, output: &str, pool: &mut Pool) -> usize {
+    // video frames are read as YCbCr with 4:2:0 subsampled chroma, so they are encoded as they are
+    if ycbcr_input && arguments.color_space != ColorSpace::YCbCr {
+        panic!("YUV input can only be encoded as YCbCr!");
+    }
+    let alpha_sidecar = image.apply_alpha_policy(arguments.alpha);
+    // the thumbnail and the comparison need the RGB image
+    let rgb_image = ycbcr_input.then(|| image.to_rgb(arguments.color_matrix));
+    let original = (arguments.command == cli::Command::Compare).then(|| rgb_image.as_ref().unwrap_or(&image).clone());
 
-    let mut y_dc = coefficient_encoder::dc_coefficients(&y_quant);
-    let cb_dc = coefficient_encoder::dc_coefficients(&cb_quant);
-    let cr_dc = coefficient_encoder::dc_coefficients(&cr_quant);
+    let mut settings = encoder::EncoderSettings {
+        rdo_lambda: arguments.rdo_lambda,
+        masking_strength: arguments.masking_strength,
+        exif: arguments.exif.clone(),
+        xmp: arguments
+            .xmp
+            .as_ref()
+            .map(|path| std::fs::read_to_string(path).expect("Error reading XMP packet!")),
+        icc_profile: arguments
+            .icc_profile
+            .as_ref()
+            .map(|path| std::fs::read(path).expect("Error reading ICC profile!")),
+        ..Default::default()
+    };
+    if let Some(comments) = &arguments.comments {
+        settings.comments = comments.clone();
+    }
+    let thumbnail = arguments
+        .thumbnail
+        .map(|format| thumbnail::create_thumbnail(rgb_image.as_ref().unwrap_or(&image), format, arguments.thumbnail_size, pool));
+    settings.jfif_header = match JfifHeader::new(arguments.density, thumbnail) {
+        Ok(header) => Some(header),
+        Err(error) => panic!("{}", error),
+    };
 
-    let mut y_ac = coefficient_encoder::ac_coefficients(&y_quant);
-    let cb_ac = coefficient_encoder::ac_coefficients(&cb_quant);
-    let cr_ac = coefficient_encoder::ac_coefficients(&cr_quant);
+    if let (Some(mut sidecar), cli::Command::Encode | cli::Command::Batch) = (alpha_sidecar, &arguments.command) {
+        let dct = parallel_dct::dct_grayscale(&mut sidecar, &DCTMode::Arai, pool);
+        let mut sidecar_stream = encoder::encode_grayscale_dct_to_stream(&sidecar, dct, &settings, pool);
+        write_output(&mut sidecar_stream, &alpha_sidecar_path(output));
+    }
 
-    coefficient_encoder::reorder_y_coefficients(&mut y_dc, image.padded_width(16));
-    coefficient_encoder::reorder_y_coefficients(&mut y_ac, image.padded_width(16));
+    if arguments.color_space != ColorSpace::YCbCr {
+        image.rgb_to_cmyk();
+        let transform = match arguments.color_space {
+            ColorSpace::Ycck => {
+                image.cmyk_to_ycck();
+                AdobeTransform::Ycck
+            }
+            _ => {
+                image.cmyk_to_adobe();
+                AdobeTransform::Unknown
+            }
+        };
+        let [c_dct, m_dct, y_dct, k_dct] = parallel_dct::dct_four_channels(&mut image, &DCTMode::Arai, pool);
+        let mut target_stream = encoder::encode_four_channel_dct_to_stream(&image, c_dct, m_dct, y_dct, k_dct, transform, &settings, pool);
+        let size = target_stream.data().len();
+        write_output(&mut target_stream, output);
+        return size;
+    }
 
-    let (y_dc_encoded, huffman_dc_y) = coefficient_encoder::encode_dc_coefficients(&y_dc);
-    let (cbcr_dc_encoded, huffman_dc_cbcr) = coefficient_encoder::encode_two_dc_coefficients(&cb_dc, &cr_dc);
-    let cb_dc_encoded = &cbcr_dc_encoded[0..cbcr_dc_encoded.len() / 2];
-    let cr_dc_encoded = &cbcr_dc_encoded[(cbcr_dc_encoded.len() / 2)..cbcr_dc_encoded.len()];
+    if !ycbcr_input {
+        image.rgb_to_ycbcr_parallel(arguments.color_matrix, pool);
+        image.downsample(4, 2, 0);
+    }
 
-    let (y_ac_encoded, huffman_ac_y) = coefficient_encoder::encode_ac_coefficients(&y_ac);
-    let (cbcr_ac_encoded, huffman_ac_cbcr) = coefficient_encoder::encode_two_ac_coefficients(&cb_ac, &cr_ac);
-    let cb_ac_encoded = &cbcr_ac_encoded[0..cbcr_ac_encoded.len() / 2];
-    let cr_ac_encoded = &cbcr_ac_encoded[(cbcr_ac_encoded.len() / 2)..cbcr_ac_encoded.len()];
+    let (y_dct, cb_dct, cr_dct) = parallel_dct::dct(&mut image, &DCTMode::Arai, pool);
 
-    let mut target_stream = BitStream::open();
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::SOI);
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::APP0);
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::COM);
-    jpg_writer::write_dqt_segment(&mut target_stream, &luminance_q_table, 0);
-    jpg_writer::write_dqt_segment(&mut target_stream, &chrominance_q_table, 1);
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::SOF0);
-    jpg_writer::write_dht_segment(&mut target_stream, 0, &huffman_dc_y, false);
-    jpg_writer::write_dht_segment(&mut target_stream, 1, &huffman_dc_cbcr, false);
+    let mut target_stream = match (&arguments.rate_target, &arguments.quality_target) {
+        (Some(target), _) => {
+            let result = rate_control::encode_to_target(&image, &y_dct, &cb_dct, &cr_dct, &settings, target, pool);
+            if !result.target_met {
+                eprintln!("Target size can't be met, encoded with lowest quality instead.");
+            }
+            print_status(&format!("Encoded with quality {} to {} bytes.", result.quality, result.stream.data().len()), output);
+            result.stream
+        }
+        (_, Some(target)) => {
+            let result = quality_control::encode_to_quality_target(&image, &y_dct, &cb_dct, &cr_dct, &settings, target, pool);
+            if !result.target_met {
+                eprintln!("Target quality can't be met, encoded with highest quality instead.");
+            }
+            let metric = match target {
+                quality_control::QualityTarget::Psnr(_) => "PSNR (dB)",
+                quality_control::QualityTarget::Ssim(_) => "SSIM",
+            };
+            print_status(
+                &format!("Encoded with quality {} to {} bytes, achieved {} of {:.4}.", result.quality, result.stream.data().len(), metric, result.achieved),
+                output,
+            );
+            result.stream
+        }
+        (None, None) => encoder::encode_dct_to_stream(&image, y_dct, cb_dct, cr_dct, &settings, pool),
+    };
 
-    jpg_writer::write_dht_segment(&mut target_stream, 2, &huffman_ac_y, true);
-    jpg_writer::write_dht_segment(&mut target_stream, 3, &huffman_ac_cbcr, true);
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::SOS);
+    let size = target_stream.data().len();
+    match original {
+        Some(original) => {
+            let decoded = match jpg_reader::decode_jpg_with_color_matrix(target_stream.data(), arguments.color_matrix) {
+                Ok(image) => image,
+                Err(error) => panic!("{}", error),
+            };
+            let metrics = image_metrics::compare_images(&original, &decoded);
+            if let Some(path) = &arguments.decoded_output {
+                if let Err(error) = ppm_writer::write_ppm_to_file(&decoded, ppm_writer::PpmEncoding::Binary, path) {
+                    panic!("Error writing {}: {}!", path, error);
+                }
+            }
+            println!("Encoded to {} bytes.", size);
+            print!("{}", metrics.table(["R", "G", "B"]));
+        }
+        None => write_output(&mut target_stream, output),
+    }
+    size
+}
 
-    target_stream.byte_stuffing(true);
-    image_data_writer::write_image_data_to_stream(&mut target_stream, &y_dc_encoded, cb_dc_encoded, cr_dc_encoded, &y_ac_encoded, cb_ac_encoded, cr_ac_encoded);
-    target_stream.byte_stuffing(false);
+/// Write the encoded file to the output path, or to stdout for `-`.
+fn write_output(stream: &mut BitStream, output: &str) {
+    if output == cli::STDIO_PATH {
+        stream.flush_to_writer(&mut std::io::stdout().lock());
+    } else {
+        stream.flush_to_file(output);
+    }
+}
 
-    target_stream.pad_last_byte(true);
+/// Print a status message, to stderr if the encoded file is written to stdout.
+fn print_status(message: &str, output: &str) {
+    if output == cli::STDIO_PATH {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
 
-    jpg_writer::write_segment_to_stream(&mut target_stream, &image, jpg_writer::SegmentType::EOI);
+/// The path of the alpha sidecar next to the output, e.g. `output_alpha.jpg` for `output.jpg`.
+///
+/// # Panics
+/// * If the output is stdout.
+fn alpha_sidecar_path(output: &str) -> String {
+    if output == cli::STDIO_PATH {
+        panic!("The alpha sidecar can't be written with the output to stdout!");
+    }
+    suffixed_path(output, "alpha")
+}
 
-    target_stream.flush_to_file("output.jpg");
+/// Add a suffix to the name of a file in front of its extension, e.g. `output_640.jpg` for `output.jpg` and `640`.
+fn suffixed_path(path: &str, suffix: &str) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains(['/', '\\']) => format!("{}_{}.{}", stem, suffix, extension),
+        _ => format!("{}_{}", path, suffix),
+    }
 }
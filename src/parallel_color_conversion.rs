@@ -0,0 +1,93 @@
+use scoped_threadpool::Pool;
+
+use crate::color_conversion::ColorConversionTables;
+use crate::image::ColorMatrix;
+use crate::utils::THREAD_COUNT;
+
+/// Convert the three channels of an RGB image to level shifted YCbCr in place, using the
+/// fixed point lookup tables of `ColorConversionTables`.
+/// The rows are split into as many chunks as the system has logical CPUs,
+/// each of which is converted on the pool.
+///
+/// # Arguments
+///
+/// * `channel1`: The R channel, replaced by the Y channel.
+/// * `channel2`: The G channel, replaced by the Cb channel.
+/// * `channel3`: The B channel, replaced by the Cr channel.
+/// * `matrix`: The colour matrix to convert with.
+/// * `pool`: The thread pool to use.
+///
+/// # Panics
+///
+/// * If the channels don't have the same amount of rows.
+pub fn rgb_to_ycbcr(
+    channel1: &mut [Vec<i16>],
+    channel2: &mut [Vec<i16>],
+    channel3: &mut [Vec<i16>],
+    matrix: ColorMatrix,
+    pool: &mut Pool,
+) {
+    if channel1.len() != channel2.len() || channel1.len() != channel3.len() {
+        panic!("Channels have different heights!");
+    }
+    let tables = ColorConversionTables::new(matrix);
+    let tables = &tables;
+    let chunk_size = channel1.len() / *THREAD_COUNT + 1;
+    pool.scoped(|s| {
+        let chunks = channel1
+            .chunks_mut(chunk_size)
+            .zip(channel2.chunks_mut(chunk_size))
+            .zip(channel3.chunks_mut(chunk_size));
+        for ((chunk1, chunk2), chunk3) in chunks {
+            s.execute(move || {
+                for ((row1, row2), row3) in chunk1.iter_mut().zip(chunk2.iter_mut()).zip(chunk3.iter_mut()) {
+                    tables.convert_row(row1, row2, row3);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use scoped_threadpool::Pool;
+
+    use crate::color_conversion::ColorConversionTables;
+    use crate::image::ColorMatrix;
+    use crate::ppm_parser::read_ppm_from_file;
+
+    use super::rgb_to_ycbcr;
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    #[test]
+    fn test_rgb_to_ycbcr_matches_sequential() {
+        let mut pool = get_pool();
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        let mut channel1 = image.channel1().clone();
+        let mut channel2 = image.channel2().clone();
+        let mut channel3 = image.channel3().clone();
+
+        rgb_to_ycbcr(&mut channel1, &mut channel2, &mut channel3, ColorMatrix::Bt709, &mut pool);
+
+        let tables = ColorConversionTables::new(ColorMatrix::Bt709);
+        for row in 0..image.height() as usize {
+            for col in 0..image.width() as usize {
+                let expected = tables.convert(image.channel1()[row][col], image.channel2()[row][col], image.channel3()[row][col]);
+                assert_eq!(expected, (channel1[row][col], channel2[row][col], channel3[row][col]));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rgb_to_ycbcr_different_heights() {
+        let mut pool = get_pool();
+        rgb_to_ycbcr(&mut [vec![0]], &mut [vec![0]], &mut [], ColorMatrix::Bt601, &mut pool);
+    }
+}
@@ -36,6 +36,43 @@ pub fn dct(
     (y_matrices, cb_matrices, cr_matrices)
 }
 
+/// Perform the DCT on a four channel (CMYK or YCCK) image.
+/// The DCT is performed for each channel in sequence, see `dct`.
+///
+/// # Arguments
+/// * `image`: The image to calculate the DCT for.
+///
+/// # Panics
+/// * If the image doesn't have four channels.
+pub fn dct_four_channels(
+    image: &mut Image,
+    mode: &DCTMode,
+    pool: &mut Pool,
+) -> [Vec<SMatrix<f32, 8, 8>>; 4] {
+    if image.channel_count() != 4 {
+        panic!("Image doesn't have four channels!");
+    }
+    let (first, second, third) = dct(image, mode, pool);
+    let mut fourth = image.single_channel_to_matrices::<4>();
+    dct_matrix_vector(&mut fourth, mode, pool);
+    [first, second, third, fourth]
+}
+
+/// Perform the DCT on a grayscale image, padding it to whole blocks first.
+///
+/// # Arguments
+/// * `image`: The image to calculate the DCT for.
+///
+/// # Panics
+/// * If the image isn't grayscale.
+pub fn dct_grayscale(image: &mut Image, mode: &DCTMode, pool: &mut Pool) -> Vec<SMatrix<f32, 8, 8>> {
+    if image.channel_count() != 1 {
+        panic!("Image isn't grayscale!");
+    }
+    image.pad_image_if_necessary();
+    dct_single_channel(image, mode, pool)
+}
+
 /// Perform the DCT on only the image's 'Y' channel.
 /// The DCT on a channel is parallelised with as many threads as the system has logical CPUs.
 ///
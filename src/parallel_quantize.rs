@@ -3,6 +3,7 @@ use std::slice::ChunksMut;
 use nalgebra::SMatrix;
 use scoped_threadpool::Pool;
 
+use crate::coefficient_encoder::{self, AcCostModel};
 use crate::{quantization, utils::THREAD_COUNT};
 
 /// Quantize the given vector of value matrices, then return a zigzag sampled
@@ -33,6 +34,79 @@ pub fn quantize_zigzag(
         .collect()
 }
 
+/// Apply activity masking to the given vector of value matrices, zeroing the coefficients of
+/// each block that fall into its deadzone (see `quantization::masking_deadzone`).
+/// The mean activity is the geometric mean over all blocks, so a few very busy blocks don't
+/// shift it much.
+///
+/// # Arguments
+/// * `values`: The DCT output to mask. Modified in place.
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+/// * `strength`: How much the deadzone grows with each doubling of a block's activity.
+/// * `pool`: The thread pool to use.
+pub fn apply_activity_masking(
+    values: &mut [SMatrix<f32, 8, 8>],
+    q_table: SMatrix<f32, 8, 8>,
+    strength: f32,
+    pool: &mut Pool,
+) {
+    if values.is_empty() {
+        return;
+    }
+    let log_sum: f32 = values
+        .iter()
+        .map(|matrix| (quantization::block_activity(matrix) + 1.0).ln())
+        .sum();
+    let mean_activity = (log_sum / values.len() as f32).exp() - 1.0;
+
+    let chunk_size = (values.len() / *THREAD_COUNT) + 1;
+    pool.scoped(|s| {
+        for chunk in values.chunks_mut(chunk_size) {
+            s.execute(move || {
+                for matrix in chunk {
+                    let activity = quantization::block_activity(matrix);
+                    let deadzone = quantization::masking_deadzone(activity, mean_activity, strength);
+                    quantization::apply_deadzone(matrix, &q_table, deadzone);
+                }
+            });
+        }
+    });
+}
+
+/// Quantize the given vector of value matrices with rate-distortion optimization,
+/// then return a zigzag sampled array of the results.
+/// The bit costs are estimated from the huffman code that plain rounding would result in,
+/// see `quantization::trellis_quantize`.
+///
+/// # Arguments
+/// * `values`: The DCT output to quantize.
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+/// * `lambda`: The weight of one bit against the squared error.
+/// * `pool`: The thread pool to quantize with.
+pub fn quantize_zigzag_trellis(
+    values: &[SMatrix<f32, 8, 8>],
+    q_table: SMatrix<f32, 8, 8>,
+    lambda: f32,
+    pool: &mut Pool,
+) -> Vec<[i32; 64]> {
+    let rounded = quantize_zigzag(&mut values.to_vec(), q_table, pool);
+    let cost_model = AcCostModel::from_ac_coefficients(&coefficient_encoder::ac_coefficients(&rounded));
+    let cost_model = &cost_model;
+
+    let mut result = vec![[0i32; 64]; values.len()];
+    let chunk_size = (values.len() / *THREAD_COUNT) + 1;
+    pool.scoped(|s| {
+        for (input, output) in values.chunks(chunk_size).zip(result.chunks_mut(chunk_size)) {
+            s.execute(move || {
+                for (matrix, quantized) in input.iter().zip(output) {
+                    *quantized = quantization::trellis_quantize(matrix, &q_table, cost_model, lambda);
+                }
+            });
+        }
+    });
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use std::thread::available_parallelism;
@@ -40,7 +114,7 @@ mod tests {
     use nalgebra::SMatrix;
     use scoped_threadpool::Pool;
 
-    use crate::parallel_quantize::quantize_zigzag;
+    use crate::parallel_quantize::{apply_activity_masking, quantize_zigzag, quantize_zigzag_trellis};
 
     fn get_pool() -> Pool {
         let thread_count = available_parallelism().unwrap().get();
@@ -71,4 +145,36 @@ mod tests {
         assert_eq!(1, result.len());
         assert_eq!(expected, result[0]);
     }
+
+    #[test]
+    fn test_quantize_trellis_lambda_zero_matches_rounding() {
+        let mut pool = get_pool();
+        let input: Vec<SMatrix<f32, 8, 8>> = (0..20)
+            .map(|block| SMatrix::from_fn(|i, j| ((block * 37 + i * 11 + j * 7) % 41) as f32 - 20.0))
+            .collect();
+        let q_table = crate::quantization::uniform_q_table(3.0);
+
+        let rounded = quantize_zigzag(&mut input.clone(), q_table, &mut pool);
+        let trellis = quantize_zigzag_trellis(&input, q_table, 0.0, &mut pool);
+        assert_eq!(rounded, trellis);
+
+        let coarse = quantize_zigzag_trellis(&input, q_table, 100.0, &mut pool);
+        let non_zero = |blocks: &Vec<[i32; 64]>| blocks.iter().flatten().filter(|value| **value != 0).count();
+        assert!(non_zero(&coarse) < non_zero(&rounded));
+    }
+
+    #[test]
+    fn test_activity_masking_only_affects_busy_blocks() {
+        let mut pool = get_pool();
+        let flat: SMatrix<f32, 8, 8> = SMatrix::from_fn(|i, j| if i + j < 2 { 40.0 } else { 2.0 });
+        let busy: SMatrix<f32, 8, 8> = SMatrix::from_fn(|i, j| if i + j < 2 { 400.0 } else { 2.0 });
+        let mut input = vec![flat, flat, flat, busy];
+        let q_table = crate::quantization::uniform_q_table(3.0);
+
+        apply_activity_masking(&mut input, q_table, 0.5, &mut pool);
+
+        assert_eq!(flat, input[0]);
+        assert_eq!(400.0, input[3][(0, 1)]);
+        assert_eq!(0.0, input[3][(7, 7)]);
+    }
 }
@@ -0,0 +1,538 @@
+use std::fs;
+
+use crate::image::{create_image, create_rgba_image, Image};
+use crate::inflate::zlib_decompress;
+
+/// The eight bytes every PNG file starts with.
+pub const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// The starting column and row and the column and row spacing of the seven Adam7 passes.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The colour types of PNG images.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorType {
+    Grayscale,
+    Rgb,
+    Palette,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    /// The amount of samples per pixel.
+    fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Palette => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+}
+
+/// The content of the IHDR chunk, plus the chunks needed to interpret the samples.
+///
+/// # Attributes
+///
+/// * `width`: Image width in pixels.
+/// * `height`: Image height in pixels.
+/// * `bit_depth`: The bits per sample (or per palette index), 1, 2, 4, 8 or 16.
+/// * `color_type`: How the samples are interpreted.
+/// * `interlaced`: Whether the image data is stored in seven Adam7 passes.
+/// * `palette`: The RGB entries of the PLTE chunk.
+/// * `transparency`: The content of the tRNS chunk, if any: the alpha of palette entries,
+///   or the gray or RGB samples of the colour that is fully transparent.
+#[derive(Debug)]
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlaced: bool,
+    palette: Vec<[u8; 3]>,
+    transparency: Option<Vec<u8>>,
+}
+
+impl Header {
+    /// The amount of bits of one pixel.
+    fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// The amount of bytes a filter looks back to find the corresponding byte of the previous pixel, at least 1.
+    fn filter_distance(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+
+    /// The amount of bytes of a scanline of the given width, without its filter type byte.
+    fn scanline_length(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// The amount of bytes of the decompressed image data: the scanlines of the image, or of all Adam7 passes,
+    /// each with its filter type byte.
+    fn raw_length(&self) -> usize {
+        if !self.interlaced {
+            return (self.scanline_length(self.width) + 1) * self.height;
+        }
+        ADAM7_PASSES
+            .iter()
+            .map(|(x_start, y_start, x_step, y_step)| {
+                let pass_width = self.width.saturating_sub(*x_start).div_ceil(*x_step);
+                let pass_height = self.height.saturating_sub(*y_start).div_ceil(*y_step);
+                // empty passes don't have any scanlines, not even filter type bytes
+                if pass_width == 0 { 0 } else { (self.scanline_length(pass_width) + 1) * pass_height }
+            })
+            .sum()
+    }
+
+    fn has_alpha(&self) -> bool {
+        matches!(self.color_type, ColorType::GrayscaleAlpha | ColorType::Rgba) || self.transparency.is_some()
+    }
+}
+
+/// Read a PNG file and decode it into an RGB image.
+///
+/// # Arguments
+/// * `filename`: The path to the file to read.
+///
+/// # Examples
+/// ```
+/// let image = read_png_from_file("image.png");
+/// ```
+///
+/// # Panics
+/// * If the file can't be read.
+/// * If the file can't be decoded, see `decode_png`.
+pub fn read_png_from_file(filename: &str) -> Image {
+    let data = fs::read(filename).expect("Error reading PNG file!");
+    match decode_png(&data) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Decode a PNG file into an RGB image.
+/// All colour types, bit depths and both interlace methods are supported. Grayscale images are decoded into
+/// three equal channels, palette images into the palette's colours. 16 bit samples are scaled to 8 bit.
+/// Images with an alpha channel or a tRNS chunk keep their alpha (see `Image::apply_alpha_policy`).
+/// Ancillary chunks apart from tRNS (e.g. gamma or colour profiles) are ignored.
+///
+/// # Arguments
+/// * `data`: The content of the PNG file.
+///
+/// # Errors
+/// * If the data isn't a PNG file, a chunk's CRC doesn't match or a critical chunk is missing.
+/// * If the IHDR chunk holds an invalid combination of bit depth and colour type, or the image is larger than
+///   65535 pixels in either direction.
+/// * If the image data is corrupted, see `inflate::zlib_decompress`.
+/// * If an unknown critical chunk or filter type is found.
+pub fn decode_png(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err("Not a PNG file!".to_string());
+    }
+    let mut header: Option<Header> = None;
+    let mut compressed: Vec<u8> = Vec::new();
+    let mut position = PNG_SIGNATURE.len();
+    loop {
+        let (chunk_type, chunk, next) = read_chunk(data, position)?;
+        position = next;
+        match &chunk_type {
+            b"IHDR" => header = Some(read_header(chunk)?),
+            b"PLTE" => {
+                let header = header.as_mut().ok_or("PLTE chunk before IHDR!")?;
+                header.palette = chunk.chunks_exact(3).map(|entry| [entry[0], entry[1], entry[2]]).collect();
+            }
+            b"tRNS" => header.as_mut().ok_or("tRNS chunk before IHDR!")?.transparency = Some(chunk.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // bit 5 of the first byte is set for ancillary chunks, which may be ignored
+            _ if chunk_type[0] & 0x20 == 0 => {
+                return Err(format!("Unsupported critical PNG chunk {}!", String::from_utf8_lossy(&chunk_type)))
+            }
+            _ => (),
+        }
+    }
+    let header = header.ok_or("PNG file is missing its IHDR chunk!")?;
+    if header.color_type == ColorType::Palette && header.palette.is_empty() {
+        return Err("PNG palette image is missing its PLTE chunk!".to_string());
+    }
+    if compressed.is_empty() {
+        return Err("PNG file is missing its image data!".to_string());
+    }
+    let raw = zlib_decompress(&compressed, header.raw_length())?;
+    to_image(&header, &unfilter_passes(&header, &raw)?)
+}
+
+/// Read the chunk at the position.
+/// Returns the chunk type, the chunk data and the position of the next chunk.
+///
+/// # Errors
+/// * If the data ends within the chunk or its CRC doesn't match.
+fn read_chunk(data: &[u8], position: usize) -> Result<([u8; 4], &[u8], usize), String> {
+    let header = data.get(position..position + 8).ok_or("PNG file ends unexpectedly!")?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let chunk_type = [header[4], header[5], header[6], header[7]];
+    let end = position + 8 + length;
+    let crc = data.get(end..end + 4).ok_or("PNG file ends unexpectedly!")?;
+    if u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) != crc32(&data[position + 4..end]) {
+        return Err(format!("CRC of PNG chunk {} doesn't match!", String::from_utf8_lossy(&chunk_type)));
+    }
+    Ok((chunk_type, &data[position + 8..end], end + 4))
+}
+
+/// Parse and validate the IHDR chunk.
+///
+/// # Errors
+/// * If the chunk is too short, or holds invalid or unsupported values.
+fn read_header(chunk: &[u8]) -> Result<Header, String> {
+    if chunk.len() != 13 {
+        return Err("Invalid PNG IHDR chunk!".to_string());
+    }
+    let width = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+    let height = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("Unsupported PNG size {}x{}!", width, height));
+    }
+    let bit_depth = chunk[8];
+    let (color_type, allowed_depths): (ColorType, &[u8]) = match chunk[9] {
+        0 => (ColorType::Grayscale, &[1, 2, 4, 8, 16]),
+        2 => (ColorType::Rgb, &[8, 16]),
+        3 => (ColorType::Palette, &[1, 2, 4, 8]),
+        4 => (ColorType::GrayscaleAlpha, &[8, 16]),
+        6 => (ColorType::Rgba, &[8, 16]),
+        color_type => return Err(format!("Invalid PNG colour type {}!", color_type)),
+    };
+    if !allowed_depths.contains(&bit_depth) {
+        return Err(format!("Invalid PNG bit depth {} for colour type {:?}!", bit_depth, color_type));
+    }
+    if chunk[10] != 0 || chunk[11] != 0 || chunk[12] > 1 {
+        return Err("Unsupported PNG compression, filter or interlace method!".to_string());
+    }
+    Ok(Header {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced: chunk[12] == 1,
+        palette: vec![],
+        transparency: None,
+    })
+}
+
+/// Reverse the filters of all scanlines and put the pixels of interlaced images back in place.
+/// Returns the unfiltered scanlines of the full image, without filter type bytes.
+///
+/// # Errors
+/// * If the decompressed data is too short or a scanline has an invalid filter type.
+fn unfilter_passes(header: &Header, raw: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    if !header.interlaced {
+        return unfilter(header, raw, header.width, header.height);
+    }
+    let bits_per_pixel = header.bits_per_pixel();
+    let mut rows = vec![vec![0u8; header.scanline_length(header.width)]; header.height];
+    let mut position = 0;
+    for (x_start, y_start, x_step, y_step) in ADAM7_PASSES {
+        let pass_width = header.width.saturating_sub(x_start).div_ceil(x_step);
+        let pass_height = header.height.saturating_sub(y_start).div_ceil(y_step);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let length = (header.scanline_length(pass_width) + 1) * pass_height;
+        let pass_data = raw.get(position..position + length).ok_or("PNG image data is too short!")?;
+        position += length;
+        for (pass_y, pass_row) in unfilter(header, pass_data, pass_width, pass_height)?.iter().enumerate() {
+            let row = &mut rows[y_start + pass_y * y_step];
+            for pass_x in 0..pass_width {
+                copy_pixel(pass_row, pass_x, row, x_start + pass_x * x_step, bits_per_pixel);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// Copy the bits of one pixel between two scanlines.
+fn copy_pixel(source: &[u8], source_x: usize, target: &mut [u8], target_x: usize, bits_per_pixel: usize) {
+    if bits_per_pixel >= 8 {
+        let bytes = bits_per_pixel / 8;
+        target[target_x * bytes..(target_x + 1) * bytes].copy_from_slice(&source[source_x * bytes..(source_x + 1) * bytes]);
+        return;
+    }
+    // pixels of less than 8 bits are packed into the bytes starting with the most significant bits
+    let mask = (1u16 << bits_per_pixel) as u8 - 1;
+    let source_shift = 8 - bits_per_pixel - (source_x * bits_per_pixel) % 8;
+    let target_shift = 8 - bits_per_pixel - (target_x * bits_per_pixel) % 8;
+    let value = (source[source_x * bits_per_pixel / 8] >> source_shift) & mask;
+    let target_byte = &mut target[target_x * bits_per_pixel / 8];
+    *target_byte = (*target_byte & !(mask << target_shift)) | (value << target_shift);
+}
+
+/// Reverse the filters of the scanlines of an image (or an Adam7 pass) of the given size.
+///
+/// # Errors
+/// * If the data is too short or a scanline has an invalid filter type.
+fn unfilter(header: &Header, data: &[u8], width: usize, height: usize) -> Result<Vec<Vec<u8>>, String> {
+    let length = header.scanline_length(width);
+    let distance = header.filter_distance();
+    if data.len() < (length + 1) * height {
+        return Err("PNG image data is too short!".to_string());
+    }
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    let mut previous = vec![0u8; length];
+    for line in data.chunks_exact(length + 1).take(height) {
+        let (filter_type, filtered) = (line[0], &line[1..]);
+        let mut row = filtered.to_vec();
+        for index in 0..length {
+            let left = if index >= distance { row[index - distance] } else { 0 };
+            let up = previous[index];
+            let up_left = if index >= distance { previous[index - distance] } else { 0 };
+            let predictor = match filter_type {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth_predictor(left, up, up_left),
+                _ => return Err(format!("Invalid PNG filter type {}!", filter_type)),
+            };
+            row[index] = row[index].wrapping_add(predictor);
+        }
+        previous.clone_from(&row);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// The Paeth predictor: whichever of the left, upper and upper left byte is closest to left + up - up_left.
+fn paeth_predictor(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (left_distance, up_distance, up_left_distance) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if left_distance <= up_distance && left_distance <= up_left_distance {
+        left
+    } else if up_distance <= up_left_distance {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Convert the unfiltered scanlines into an image, resolving palette indices, grayscale and transparency.
+///
+/// # Errors
+/// * If a palette index is out of range.
+fn to_image(header: &Header, rows: &[Vec<u8>]) -> Result<Image, String> {
+    let (width, height) = (header.width, header.height);
+    let mut red = vec![vec![0i16; width]; height];
+    let mut green = vec![vec![0i16; width]; height];
+    let mut blue = vec![vec![0i16; width]; height];
+    let mut alpha = vec![vec![255i16; width]; height];
+    let channels = header.color_type.channels();
+    let depth = header.bit_depth as usize;
+    let max_value = ((1u32 << depth) - 1) as f32;
+    let scale = |sample: u16| (sample as f32 * u8::MAX as f32 / max_value) as i16;
+
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..width {
+            let samples: Vec<u16> = (0..channels).map(|channel| read_sample(row, x * channels + channel, depth)).collect();
+            let (r, g, b, a) = match header.color_type {
+                ColorType::Grayscale => {
+                    let transparent = header.transparency.as_ref().is_some_and(|key| key.len() >= 2 && samples[0] == u16::from_be_bytes([key[0], key[1]]));
+                    let gray = scale(samples[0]);
+                    (gray, gray, gray, if transparent { 0 } else { 255 })
+                }
+                ColorType::Rgb => {
+                    let transparent = header.transparency.as_ref().is_some_and(|key| {
+                        key.len() >= 6 && (0..3).all(|channel| samples[channel] == u16::from_be_bytes([key[2 * channel], key[2 * channel + 1]]))
+                    });
+                    (scale(samples[0]), scale(samples[1]), scale(samples[2]), if transparent { 0 } else { 255 })
+                }
+                ColorType::Palette => {
+                    let index = samples[0] as usize;
+                    let [r, g, b] = *header.palette.get(index).ok_or("PNG palette index out of range!")?;
+                    let a = header.transparency.as_ref().and_then(|alphas| alphas.get(index)).copied().unwrap_or(255);
+                    (r as i16, g as i16, b as i16, a as i16)
+                }
+                ColorType::GrayscaleAlpha => {
+                    let gray = scale(samples[0]);
+                    (gray, gray, gray, scale(samples[1]))
+                }
+                ColorType::Rgba => (scale(samples[0]), scale(samples[1]), scale(samples[2]), scale(samples[3])),
+            };
+            red[y][x] = r;
+            green[y][x] = g;
+            blue[y][x] = b;
+            alpha[y][x] = a;
+        }
+    }
+
+    if header.has_alpha() {
+        Ok(create_rgba_image(height as u16, width as u16, red, green, blue, alpha))
+    } else {
+        Ok(create_image(height as u16, width as u16, red, green, blue))
+    }
+}
+
+/// Read the sample with the given index from an unfiltered scanline.
+/// Samples of 16 bit are big endian, samples of less than 8 bit are packed starting with the most significant bits.
+fn read_sample(row: &[u8], index: usize, depth: usize) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * depth;
+            ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+        }
+    }
+}
+
+/// Calculate the CRC-32 (as used by PNG and zlib) of the data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, decode_png, paeth_predictor, read_png_from_file, ColorType, Header};
+
+    fn rgb(x: usize, y: usize) -> (i16, i16, i16) {
+        (((x * 37 + y * 11) % 256) as i16, ((x * 5 + y * 53) % 256) as i16, ((x * x + 3 * y) % 256) as i16)
+    }
+
+    fn alpha(x: usize, y: usize) -> i16 {
+        ((x * 29 + y * 71) % 256) as i16
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xcbf43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(10, paeth_predictor(10, 20, 20));
+        assert_eq!(20, paeth_predictor(10, 20, 10));
+        assert_eq!(15, paeth_predictor(10, 20, 15));
+    }
+
+    #[test]
+    fn test_raw_length() {
+        let mut header = Header {
+            width: 3,
+            height: 2,
+            bit_depth: 1,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+            palette: vec![],
+            transparency: None,
+        };
+        assert_eq!(4, header.raw_length());
+        // passes 1, 4 and 6 hold one pixel of the first row, pass 7 the second row, passes 2, 3 and 5 are empty
+        header.interlaced = true;
+        assert_eq!(8, header.raw_length());
+    }
+
+    #[test]
+    fn test_decode_rgb_8_bit_all_filters() {
+        // every row uses another filter type
+        let image = read_png_from_file("test/valid_test_rgb_8bit_filters.png");
+        assert_eq!((13, 7), (image.width(), image.height()));
+        assert!(!image.has_alpha());
+        for y in 0..7 {
+            for x in 0..13 {
+                assert_eq!(rgb(x, y), image.pixel_at(x as u16, y as u16));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rgba_16_bit_adam7() {
+        let image = read_png_from_file("test/valid_test_rgba_16bit_adam7.png");
+        assert_eq!((11, 9), (image.width(), image.height()));
+        // the samples are the 8 bit values in the high byte plus (x + y) in the low byte
+        let scale = |value: i16, x: usize, y: usize| ((value as usize * 256 + (x + y) % 256) as f32 * 255.0 / 65535.0) as i16;
+        for y in 0..9 {
+            for x in 0..11 {
+                let (r, g, b) = rgb(x, y);
+                assert_eq!((scale(r, x, y), scale(g, x, y), scale(b, x, y)), image.pixel_at(x as u16, y as u16));
+                assert_eq!(scale(alpha(x, y), x, y), image.alpha()[y][x]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_palette_4_bit_adam7_with_transparency() {
+        let image = read_png_from_file("test/valid_test_palette_4bit_adam7.png");
+        assert_eq!((10, 6), (image.width(), image.height()));
+        for y in 0..6 {
+            for x in 0..10 {
+                let index = (x + 2 * y) % 16;
+                let expected = ((index * 16) as i16, (255 - index * 16) as i16, ((index * 40) % 256) as i16);
+                assert_eq!(expected, image.pixel_at(x as u16, y as u16));
+                let expected_alpha = [0, 64, 128, 192].get(index).copied().unwrap_or(255);
+                assert_eq!(expected_alpha, image.alpha()[y][x]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_gray_2_bit() {
+        let image = read_png_from_file("test/valid_test_gray_2bit.png");
+        assert!(!image.has_alpha());
+        for y in 0..5 {
+            for x in 0..9 {
+                let gray = ((x + y) % 4 * 85) as i16;
+                assert_eq!((gray, gray, gray), image.pixel_at(x as u16, y as u16));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_gray_alpha_8_bit() {
+        let image = read_png_from_file("test/valid_test_gray_alpha_8bit.png");
+        for y in 0..4 {
+            for x in 0..6 {
+                let gray = ((x * 23 + y * 7) % 256) as i16;
+                assert_eq!((gray, gray, gray), image.pixel_at(x as u16, y as u16));
+                assert_eq!(alpha(x, y), image.alpha()[y][x]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_png_from_other_encoder() {
+        let image = read_png_from_file("test/20231113_huge_bitstream_encoding_test.png");
+        assert_eq!((637, 554), (image.width(), image.height()));
+        assert!(image.has_alpha());
+    }
+
+    #[test]
+    fn test_decode_png_wrong_crc() {
+        let mut data = std::fs::read("test/valid_test_gray_2bit.png").unwrap();
+        data[20] ^= 1;
+        assert_eq!("CRC of PNG chunk IHDR doesn't match!", decode_png(&data).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_png_no_png() {
+        assert_eq!("Not a PNG file!", decode_png(b"P3\n1 1\n255\n0 0 0").unwrap_err());
+    }
+}
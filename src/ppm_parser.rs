@@ -1,17 +1,110 @@
-use std::fs::read_to_string;
+use std::fs::{read, File};
+use std::io::{BufRead, BufReader};
 
-use lazy_static::lazy_static;
-use regex::Regex;
+use crate::image::{create_image, create_rgba_image, Image};
 
-use crate::image::{create_image, Image};
+const SUPPORTED_FORMAT: &[u8; 2] = b"P3";
 
-lazy_static! {
-    static ref WHITESPACE_REGEX: Regex = Regex::new(r"\s+").unwrap();
+/// A tokenizer for the header and the plain (ASCII) raster of Netpbm files.
+/// It reads from a buffered reader byte by byte, so files are never held in memory as a whole.
+/// Whitespace and comments are skipped. As per the Netpbm specification, comments start with `#` anywhere
+/// outside a token and end at the end of the line.
+struct NetpbmTokenizer<R: BufRead> {
+    reader: R,
 }
 
-const SUPPORTED_FORMAT: &str = "P3";
+impl<R: BufRead> NetpbmTokenizer<R> {
+    fn new(reader: R) -> NetpbmTokenizer<R> {
+        NetpbmTokenizer { reader }
+    }
+
+    /// Get the next byte without consuming it, `None` at the end of the data.
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        let buffer = self.reader.fill_buf().map_err(|error| format!("Error reading Netpbm file: {}!", error))?;
+        Ok(buffer.first().copied())
+    }
+
+    fn consume(&mut self) {
+        self.reader.consume(1);
+    }
+
+    /// Skip whitespace and comments up to the next token.
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), String> {
+        while let Some(byte) = self.peek()? {
+            if byte == b'#' {
+                while self.peek()?.is_some_and(|byte| byte != b'\n' && byte != b'\r') {
+                    self.consume();
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.consume();
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the magic number at the start of the file, e.g. `P3`.
+    fn magic_number(&mut self) -> Result<[u8; 2], String> {
+        let mut magic_number = [0; 2];
+        for byte in magic_number.iter_mut() {
+            *byte = self.peek()?.ok_or("Netpbm file is empty!")?;
+            self.consume();
+        }
+        Ok(magic_number)
+    }
+
+    /// Read the next unsigned decimal number.
+    /// The single whitespace character terminating it is consumed, so a binary raster can follow the header.
+    ///
+    /// # Errors
+    ///
+    /// * The data ends before the number.
+    /// * The token contains anything but digits or is larger than `u32::MAX`.
+    fn next_number(&mut self) -> Result<u32, String> {
+        self.skip_whitespace_and_comments()?;
+        let mut number: Option<u32> = None;
+        while let Some(byte) = self.peek()? {
+            if byte.is_ascii_whitespace() {
+                self.consume();
+                break;
+            }
+            if byte == b'#' {
+                break;
+            }
+            let digit = (byte as char).to_digit(10).ok_or(format!("Invalid Netpbm value with {}!", byte as char))?;
+            number = number
+                .unwrap_or(0)
+                .checked_mul(10)
+                .and_then(|number| number.checked_add(digit))
+                .map(Some)
+                .ok_or("Netpbm value is too large!")?;
+            self.consume();
+        }
+        number.ok_or("Netpbm file ends unexpectedly!".to_string())
+    }
+
+    /// Read the next sample of a plain bitmap (P1), which don't need whitespace between them.
+    fn next_bit(&mut self) -> Result<u8, String> {
+        self.skip_whitespace_and_comments()?;
+        match self.peek()? {
+            Some(byte @ (b'0' | b'1')) => {
+                self.consume();
+                Ok(byte - b'0')
+            }
+            Some(byte) => Err(format!("Invalid PBM value {}!", byte as char)),
+            None => Err("Netpbm file ends unexpectedly!".to_string()),
+        }
+    }
+
+    /// The reader, positioned after the last token read.
+    fn into_inner(self) -> R {
+        self.reader
+    }
+}
 
 /// Reads an P3 PPM image file to image data structure.
+/// The file is read in a streaming fashion, filling the channels row by row.
 /// If the width or height specified by the file is smaller than the actual width/height,
 /// part of the data will be discarded.
 ///
@@ -27,120 +120,460 @@ const SUPPORTED_FORMAT: &str = "P3";
 ///
 /// # Panics
 ///
+/// * The file can't be opened.
+/// * The file isn't a valid P3 file, see `parse_ppm`.
+pub fn read_ppm_from_file(filename: &str) -> Image {
+    let file = File::open(filename).unwrap();
+    match read_ppm(BufReader::new(file)) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Reads an P3 PPM image from a buffered reader, e.g. stdin, to image data structure.
+/// See `read_ppm_from_file`.
+///
+/// # Arguments
+///
+/// * `reader`: The reader positioned at the start of the file.
+///
+/// # Examples
+///
+/// ```
+/// let image = read_ppm(std::io::stdin().lock());
+/// ```
+///
+/// # Errors
+///
+/// * The reader fails or the file isn't a valid P3 file, see `parse_ppm`.
+pub fn read_ppm<R: BufRead>(reader: R) -> Result<Image, String> {
+    parse_ppm(reader)
+}
+
+/// Parse a P3 PPM file from a reader, see `read_ppm_from_file`.
+///
+/// # Arguments
+///
+/// * `reader`: The reader positioned at the start of the file.
+///
+/// # Errors
+///
 /// * PPM image file is not P3 format
 /// * The PPM file is malformed so that image values contain non-numeric values.
 /// * The width or height specified in the file is greater than the data's width/height.
-pub fn read_ppm_from_file(filename: &str) -> Image {
-    let result = parse_file_to_split_vec(filename);
+fn parse_ppm<R: BufRead>(reader: R) -> Result<Image, String> {
+    let mut tokenizer = NetpbmTokenizer::new(reader);
+    tokenizer.skip_whitespace_and_comments()?;
+    if &tokenizer.magic_number()? != SUPPORTED_FORMAT {
+        return Err("Unsupported PPM format!".to_string());
+    }
 
-    if result[0] != SUPPORTED_FORMAT {
-        panic!("Unsupported PPM format");
+    let width = tokenizer.next_number()? as usize;
+    let height = tokenizer.next_number()? as usize;
+    let max_value_in_ppm = tokenizer.next_number()?;
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("Unsupported PPM size {}x{}!", width, height));
     }
+    if max_value_in_ppm == 0 {
+        return Err("Invalid PPM maximum value 0!".to_string());
+    }
+    let scaling_factor = u8::MAX as f32 / max_value_in_ppm as f32;
 
-    let width: usize = result[1].parse().unwrap();
-    let height: usize = result[2].parse().unwrap();
+    let mut image_values1: Vec<Vec<i16>> = Vec::with_capacity(height);
+    let mut image_values2: Vec<Vec<i16>> = Vec::with_capacity(height);
+    let mut image_values3: Vec<Vec<i16>> = Vec::with_capacity(height);
+    for _ in 0..height {
+        let mut row1 = Vec::with_capacity(width);
+        let mut row2 = Vec::with_capacity(width);
+        let mut row3 = Vec::with_capacity(width);
+        for _ in 0..width {
+            row1.push(scale(tokenizer.next_number()?, scaling_factor));
+            row2.push(scale(tokenizer.next_number()?, scaling_factor));
+            row3.push(scale(tokenizer.next_number()?, scaling_factor));
+        }
+        image_values1.push(row1);
+        image_values2.push(row2);
+        image_values3.push(row3);
+    }
 
-    let max_value_in_ppm: i32 = result[3].parse().unwrap();
-    let scaling_factor = u8::MAX as f32 / max_value_in_ppm as f32;
+    Ok(create_image(height as u16, width as u16, image_values1, image_values2, image_values3))
+}
 
-    let (image_values1, image_values2, image_values3) = extract_pixel_values(&result, height, width, scaling_factor);
+/// Apply the scaling factor to a sample, truncating the result.
+///
+/// # Arguments
+///
+/// * `value`: The value to multiply with.
+/// * `scaling_factor`: The factor to scale it by.
+fn scale(value: u32, scaling_factor: f32) -> i16 {
+    (value as f32 * scaling_factor) as i16
+}
 
-    create_image(height as u16, width as u16, image_values1, image_values2, image_values3)
+/// The tuple types of PAM files that can be read, see `read_pam_from_file`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TupleType {
+    Grayscale,
+    Rgb,
+    RgbAlpha,
 }
 
+impl TupleType {
+    /// The amount of samples per pixel (the DEPTH) of this tuple type.
+    fn depth(&self) -> usize {
+        match self {
+            TupleType::Grayscale => 1,
+            TupleType::Rgb => 3,
+            TupleType::RgbAlpha => 4,
+        }
+    }
+}
 
-/// Creates two-dimensional vectors with corresponding width and height for the three image data channels
+/// Reads a PAM (P7) image file to image data structure.
+/// GRAYSCALE images are stored with the same value in all three channels, RGB_ALPHA images keep their
+/// alpha channel (see `Image::apply_alpha_policy`). Without a TUPLTYPE, the tuple type is deduced from the depth.
 ///
 /// # Arguments
 ///
-/// * `raw_data`: The raw image data as row strings
-/// * `height`: The image height given in the PPM file
-/// * `width`: The image width given in the PPM file
-/// * `scaling_factor`: The factor used to scale the image data to 255
+/// * `filename`: Path to the image file
 ///
 /// # Examples
 ///
 /// ```
-/// let (image_values1, image_values2, image_values3) = extract_pixel_values(&data, 1920, 1080, 3.14);
+/// let image = read_pam_from_file("../path/to/image.pam");
 /// ```
-fn extract_pixel_values(raw_data: &[String], height: usize, width: usize, scaling_factor: f32) -> (Vec<Vec<i16>>, Vec<Vec<i16>>, Vec<Vec<i16>>) {
-    let mut image_values1: Vec<Vec<i16>> = vec![vec![0; width]; height];
-    let mut image_values2: Vec<Vec<i16>> = vec![vec![0; width]; height];
-    let mut image_values3: Vec<Vec<i16>> = vec![vec![0; width]; height];
-
-    for i in 0..height {
-        for j in 0..width {
-            // index is 4 (because data starts at index 4)
-            // plus width * 3 * i (to get to the row we're currently reading)
-            // plus 3 * j (for the value in the row)
-            let index = 4 + width * 3 * i + 3 * j;
-            image_values1[i][j] = unwrap_and_scale(&raw_data[index], scaling_factor);
-            image_values2[i][j] = unwrap_and_scale(&raw_data[index + 1], scaling_factor);
-            image_values3[i][j] = unwrap_and_scale(&raw_data[index + 2], scaling_factor);
+///
+/// # Panics
+///
+/// * The file can't be read.
+/// * The file isn't a valid PAM file, see `parse_pam`.
+pub fn read_pam_from_file(filename: &str) -> Image {
+    let data = read(filename).unwrap();
+    match parse_pam(&data) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Parse the content of a PAM (P7) file, see `parse_pam_image`.
+pub fn parse_pam(data: &[u8]) -> Result<Image, String> {
+    parse_pam_image(data).map(|(image, _)| image)
+}
+
+/// Parse the content of a PAM (P7) file, see `read_pam_from_file`.
+///
+/// # Arguments
+///
+/// * `data`: The content of the file.
+///
+/// # Errors
+///
+/// * The data doesn't start with P7 or the header is malformed or incomplete.
+/// * The tuple type is neither GRAYSCALE, RGB nor RGB_ALPHA, or its depth doesn't match DEPTH.
+/// * The width, height or maximum value are out of range.
+/// * The data ends before all samples are read.
+///
+/// Returns the image and the amount of bytes it takes up, so another image may follow.
+fn parse_pam_image(data: &[u8]) -> Result<(Image, usize), String> {
+    if !data.starts_with(b"P7\n") {
+        return Err("Not a PAM file!".to_string());
+    }
+    let (mut width, mut height, mut depth, mut max_value) = (None, None, None, None);
+    let mut tuple_type: Option<TupleType> = None;
+    let mut position = 3;
+    loop {
+        let line_end = data[position..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or("PAM header is missing ENDHDR!")?;
+        let line = String::from_utf8_lossy(&data[position..position + line_end]);
+        position += line_end + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        let parse_number = |value: &str| value.parse::<usize>().map_err(|_| format!("Invalid PAM header line {}!", line));
+        match key {
+            "ENDHDR" => break,
+            "WIDTH" => width = Some(parse_number(value)?),
+            "HEIGHT" => height = Some(parse_number(value)?),
+            "DEPTH" => depth = Some(parse_number(value)?),
+            "MAXVAL" => max_value = Some(parse_number(value)?),
+            "TUPLTYPE" => {
+                tuple_type = Some(match value {
+                    "GRAYSCALE" => TupleType::Grayscale,
+                    "RGB" => TupleType::Rgb,
+                    "RGB_ALPHA" => TupleType::RgbAlpha,
+                    _ => return Err(format!("Unsupported PAM tuple type {}!", value)),
+                })
+            }
+            _ => return Err(format!("Unknown PAM header line {}!", line)),
         }
     }
-    (image_values1, image_values2, image_values3)
+
+    let (width, height, depth, max_value) = match (width, height, depth, max_value) {
+        (Some(width), Some(height), Some(depth), Some(max_value)) => (width, height, depth, max_value),
+        _ => return Err("PAM header is missing WIDTH, HEIGHT, DEPTH or MAXVAL!".to_string()),
+    };
+    let tuple_type = match (tuple_type, depth) {
+        (Some(tuple_type), depth) if tuple_type.depth() == depth => tuple_type,
+        (Some(tuple_type), depth) => return Err(format!("Unsupported PAM depth {} for tuple type {:?}!", depth, tuple_type)),
+        (None, 1) => TupleType::Grayscale,
+        (None, 3) => TupleType::Rgb,
+        (None, 4) => TupleType::RgbAlpha,
+        (None, depth) => return Err(format!("Unsupported PAM depth {}!", depth)),
+    };
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("Unsupported PAM size {}x{}!", width, height));
+    }
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(format!("Invalid PAM maximum value {}!", max_value));
+    }
+
+    let bytes_per_sample = if max_value > u8::MAX as usize { 2 } else { 1 };
+    let raster = &data[position..];
+    let raster_length = width * height * depth * bytes_per_sample;
+    if raster.len() < raster_length {
+        return Err("PAM file ends before all samples are read!".to_string());
+    }
+    let scaling_factor = u8::MAX as f32 / max_value as f32;
+    let sample = |index: usize| -> i16 {
+        let value = match bytes_per_sample {
+            2 => u16::from_be_bytes([raster[2 * index], raster[2 * index + 1]]),
+            _ => raster[index] as u16,
+        };
+        (value as f32 * scaling_factor) as i16
+    };
+
+    let mut channels = vec![vec![vec![0i16; width]; height]; depth];
+    for row in 0..height {
+        for col in 0..width {
+            // samples are interleaved, pixel after pixel, row after row
+            let index = (row * width + col) * depth;
+            for (channel_index, channel) in channels.iter_mut().enumerate() {
+                channel[row][col] = sample(index + channel_index);
+            }
+        }
+    }
+
+    let (width, height) = (width as u16, height as u16);
+    let image = match tuple_type {
+        TupleType::Grayscale => {
+            let gray = channels.pop().unwrap();
+            create_image(height, width, gray.clone(), gray.clone(), gray)
+        }
+        TupleType::Rgb => {
+            let [red, green, blue]: [Vec<Vec<i16>>; 3] = channels.try_into().unwrap();
+            create_image(height, width, red, green, blue)
+        }
+        TupleType::RgbAlpha => {
+            let [red, green, blue, alpha]: [Vec<Vec<i16>>; 4] = channels.try_into().unwrap();
+            create_rgba_image(height, width, red, green, blue, alpha)
+        }
+    };
+    Ok((image, position + raster_length))
 }
 
-/// Parse the file and split it by white spaces/newlines.
-/// Lines starting with '#' (comments) are discarded.
+/// Reads a PBM (P1, P4), PGM (P2, P5) or binary PPM (P6) image file to image data structure.
+/// Bitmaps and graymaps are stored with the same value in all three channels. For P3 files, see `read_ppm_from_file`.
 ///
 /// # Arguments
 ///
-/// * `filename`: The file name.
+/// * `filename`: Path to the image file
 ///
-/// # Example
+/// # Examples
 ///
 /// ```
-/// let my_vec = parse_file_to_split_vec("/path/to/file");
+/// let image = read_pnm_from_file("../path/to/image.pgm");
 /// ```
-fn parse_file_to_split_vec(filename: &str) -> Vec<String> {
-    let string = parse_file_to_string(filename);
-    WHITESPACE_REGEX.split(&string).map(|str_value| str_value.to_string()).collect()
+///
+/// # Panics
+///
+/// * The file can't be read.
+/// * The file isn't a valid PBM, PGM or binary PPM file, see `parse_pnm`.
+pub fn read_pnm_from_file(filename: &str) -> Image {
+    let data = read(filename).unwrap();
+    match parse_pnm(&data) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Parse the content of a PBM, PGM or binary PPM file, see `parse_pnm_image`.
+pub fn parse_pnm(data: &[u8]) -> Result<Image, String> {
+    parse_pnm_image(data).map(|(image, _)| image)
 }
 
-/// Parse a file as a string.
-/// Lines are connected with a blank space.
-/// Lines starting with '#' (comments) are discarded.
+/// Parse the content of a PBM (P1, P4), PGM (P2, P5) or binary PPM (P6) file, see `read_pnm_from_file`.
+/// Binary samples with a maximum value above 255 take two bytes, most significant first.
 ///
 /// # Arguments
 ///
-/// * `filename`: The file name.
+/// * `data`: The content of the file.
 ///
-/// # Example
+/// # Errors
+///
+/// * The data doesn't start with P1, P2, P4, P5 or P6 or the header is malformed.
+/// * The width, height or maximum value are out of range.
+/// * The data ends before all samples are read or a plain sample can't be parsed.
+///
+/// Returns the image and the amount of bytes it takes up, so another image may follow.
+fn parse_pnm_image(data: &[u8]) -> Result<(Image, usize), String> {
+    let magic_number = match data.get(..2) {
+        Some([b'P', number @ (b'1' | b'2' | b'4' | b'5' | b'6')]) => number - b'0',
+        _ => return Err("Not a PBM, PGM or binary PPM file!".to_string()),
+    };
+    let is_bitmap = magic_number == 1 || magic_number == 4;
+    let depth = if magic_number == 6 { 3 } else { 1 };
+
+    let mut tokenizer = NetpbmTokenizer::new(&data[2..]);
+    let mut header_value = |name: &str| tokenizer.next_number().map_err(|_| format!("Invalid PNM {}!", name));
+    let width = header_value("width")? as usize;
+    let height = header_value("height")? as usize;
+    let max_value = if is_bitmap { 1 } else { header_value("maximum value")? as usize };
+    if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(format!("Unsupported PNM size {}x{}!", width, height));
+    }
+    if max_value == 0 || max_value > u16::MAX as usize {
+        return Err(format!("Invalid PNM maximum value {}!", max_value));
+    }
+
+    let sample_count = width * height * depth;
+    let (samples, length): (Vec<u16>, usize) = match magic_number {
+        1 | 2 => {
+            let samples = if magic_number == 1 {
+                (0..sample_count).map_while(|_| tokenizer.next_bit().ok()).map(u16::from).collect()
+            } else {
+                (0..sample_count)
+                    .map_while(|_| tokenizer.next_number().ok().and_then(|value| u16::try_from(value).ok()))
+                    .collect()
+            };
+            (samples, data.len() - tokenizer.into_inner().len())
+        }
+        _ => {
+            // the tokenizer consumed the single whitespace character separating the header from the raster
+            let raster = tokenizer.into_inner();
+            let header_length = data.len() - raster.len();
+            if magic_number == 4 {
+                // rows of bits, the most significant first, padded to full bytes
+                let row_length = width.div_ceil(8);
+                let samples = raster
+                    .chunks_exact(row_length)
+                    .take(height)
+                    .flat_map(|row| (0..width).map(move |x| ((row[x / 8] >> (7 - x % 8)) & 1) as u16))
+                    .collect();
+                (samples, header_length + row_length * height)
+            } else if max_value > u8::MAX as usize {
+                let samples = raster.chunks_exact(2).take(sample_count).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                (samples, header_length + 2 * sample_count)
+            } else {
+                (raster.iter().take(sample_count).map(|&byte| byte as u16).collect(), header_length + sample_count)
+            }
+        }
+    };
+    if samples.len() < sample_count {
+        return Err("PNM file ends before all samples are read!".to_string());
+    }
+
+    let scaling_factor = u8::MAX as f32 / max_value as f32;
+    let mut channels = vec![vec![vec![0i16; width]; height]; depth];
+    for row in 0..height {
+        for col in 0..width {
+            let index = (row * width + col) * depth;
+            for (channel_index, channel) in channels.iter_mut().enumerate() {
+                let value = samples[index + channel_index];
+                // bitmaps store black as 1
+                let value = if is_bitmap { 1 - value } else { value };
+                channel[row][col] = (value as f32 * scaling_factor) as i16;
+            }
+        }
+    }
+
+    let (width, height) = (width as u16, height as u16);
+    let image = if depth == 1 {
+        let gray = channels.pop().unwrap();
+        create_image(height, width, gray.clone(), gray.clone(), gray)
+    } else {
+        let [red, green, blue]: [Vec<Vec<i16>>; 3] = channels.try_into().unwrap();
+        create_image(height, width, red, green, blue)
+    };
+    Ok((image, length))
+}
+
+/// Reads all images of a Netpbm file, which may hold several images one after another, e.g. the frames of a video.
+/// The images may be of any format read by `read_ppm`, `parse_pnm` and `parse_pam`, even mixed in one file.
+///
+/// # Arguments
+///
+/// * `filename`: Path to the image file
+///
+/// # Examples
 ///
 /// ```
-/// let my_string = parse_file_to_string("/path/to/file");
+/// let images = read_netpbm_images_from_file("../path/to/frames.ppm");
 /// ```
-fn parse_file_to_string(filename: &str) -> String {
-    let string = read_to_string(filename)
-        .unwrap();
-    let vec: Vec<_> = string
-        .lines()
-        .filter(|line| !line.starts_with('#'))
-        .collect();
-    vec.join(" ")
+///
+/// Returns the result of each image in the order of the file, see `parse_netpbm_images`.
+pub fn read_netpbm_images_from_file(filename: &str) -> Vec<Result<Image, String>> {
+    match read(filename) {
+        Ok(data) => parse_netpbm_images(&data),
+        Err(error) => vec![Err(format!("Error reading {}: {}!", filename, error))],
+    }
 }
 
-/// Apply the scaling factor. This is only extracted for readability purposes.
+/// Parse all images of a Netpbm file, see `read_netpbm_images_from_file`.
+/// Whitespace and comments between the images are skipped.
 ///
 /// # Arguments
 ///
-/// * `value`: The value to multiply with.
-/// * `scaling_factor`: The factor to scale it by.
-///
-/// # Panics
+/// * `data`: The content of the file.
 ///
-/// * If the value cannot be parsed into a float.
-fn unwrap_and_scale(value: &str, scaling_factor: f32) -> i16 {
-    (value.parse::<f32>().unwrap() * scaling_factor) as i16
+/// Returns the result of each image. If an image can't be parsed, its error is the last result,
+/// as the start of the next image can't be found.
+fn parse_netpbm_images(data: &[u8]) -> Vec<Result<Image, String>> {
+    let mut results = vec![];
+    let mut rest = data;
+    loop {
+        let mut tokenizer = NetpbmTokenizer::new(rest);
+        if let Err(error) = tokenizer.skip_whitespace_and_comments() {
+            results.push(Err(error));
+            break;
+        }
+        rest = tokenizer.into_inner();
+        if rest.is_empty() {
+            break;
+        }
+        let result = match &rest[..2.min(rest.len())] {
+            b"P3" => {
+                let mut reader = rest;
+                parse_ppm(&mut reader).map(|image| (image, rest.len() - reader.len()))
+            }
+            b"P7" => parse_pam_image(rest),
+            _ => parse_pnm_image(rest),
+        };
+        match result {
+            Ok((image, length)) => {
+                results.push(Ok(image));
+                rest = &rest[length..];
+            }
+            Err(error) => {
+                results.push(Err(error));
+                break;
+            }
+        }
+    }
+    results
 }
 
 #[cfg(test)]
 mod tests {
     use crate::image::create_image;
 
-    use super::{extract_pixel_values, parse_file_to_split_vec, read_ppm_from_file, unwrap_and_scale};
+    use super::{
+        parse_netpbm_images, parse_pam, parse_pnm, parse_ppm, read_netpbm_images_from_file, read_pam_from_file, read_pnm_from_file,
+        read_ppm_from_file, scale, NetpbmTokenizer,
+    };
 
     #[test]
     fn test_ppm_from_file_successful() {
@@ -196,70 +629,170 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_file_to_split_vec_with_whitespace() {
-        let data = parse_file_to_split_vec("test/string_test_file_with_whitespace.txt");
-        assert_eq!(vec!["Hello", "this", "is", "a", "test"], data);
+    fn test_ppm_from_file_varied_whitespace() {
+        let read_image = read_ppm_from_file("test/valid_test_varied_whitespace.ppm");
+        assert_eq!(read_ppm_from_file("test/valid_test_maxVal_15.ppm"), read_image);
     }
 
     #[test]
-    fn test_parse_file_to_split_vec_without_whitespace() {
-        let data = parse_file_to_split_vec("test/string_test_file_without_whitespace.txt");
-        assert_eq!(vec!["Hellothisisatest"], data);
+    fn test_tokenizer_comments() {
+        let mut tokenizer = NetpbmTokenizer::new(&b"# leading comment\nP3 2#inline comment\n3\t# another one\r\n15#\n\n  7"[..]);
+        tokenizer.skip_whitespace_and_comments().unwrap();
+        assert_eq!(*b"P3", tokenizer.magic_number().unwrap());
+        assert_eq!(Ok(2), tokenizer.next_number());
+        assert_eq!(Ok(3), tokenizer.next_number());
+        assert_eq!(Ok(15), tokenizer.next_number());
+        assert_eq!(Ok(7), tokenizer.next_number());
+        assert!(tokenizer.next_number().is_err());
     }
 
     #[test]
-    fn test_extract_pixel_values_successful() {
-        let mut data = Vec::with_capacity(16);
-        data.push(String::from("P3"));
-        data.push(String::from("2"));
-        data.push(String::from("2"));
-        data.push(String::from("15"));
-        for i in 1..13 {
-            data.push(i.to_string());
-        }
-        let (image_values1, image_values2, image_values3) = extract_pixel_values(&data, 2, 2, 3.14);
-        assert_eq!(vec![vec![3, 12], vec![21, 31]], image_values1);
-        assert_eq!(vec![vec![6, 15], vec![25, 34]], image_values2);
-        assert_eq!(vec![vec![9, 18], vec![28, 37]], image_values3);
+    fn test_tokenizer_consumes_one_whitespace() {
+        let mut tokenizer = NetpbmTokenizer::new(&b"255\n\n\x01"[..]);
+        assert_eq!(Ok(255), tokenizer.next_number());
+        assert_eq!(&b"\n\x01"[..], tokenizer.into_inner());
     }
 
     #[test]
-    #[should_panic]
-    fn test_extract_pixel_values_invalid_height() {
-        let mut data = Vec::with_capacity(16);
-        data.push(String::from("P3"));
-        data.push(String::from("2"));
-        data.push(String::from("2"));
-        data.push(String::from("15"));
-        for i in 1..13 {
-            data.push(i.to_string());
-        }
-        extract_pixel_values(&data, 3, 2, 3.14);
+    fn test_tokenizer_invalid_number() {
+        assert_eq!(Err("Invalid Netpbm value with A!".to_string()), NetpbmTokenizer::new(&b"12A"[..]).next_number());
+        assert_eq!(Err("Netpbm value is too large!".to_string()), NetpbmTokenizer::new(&b"4294967296"[..]).next_number());
     }
 
     #[test]
-    #[should_panic]
-    fn test_extract_pixel_values_invalid_width() {
-        let mut data = Vec::with_capacity(16);
-        data.push(String::from("P3"));
-        data.push(String::from("2"));
-        data.push(String::from("2"));
-        data.push(String::from("15"));
-        for i in 1..13 {
-            data.push(i.to_string());
-        }
-        extract_pixel_values(&data, 2, 3, 3.14);
+    fn test_tokenizer_bits() {
+        let mut tokenizer = NetpbmTokenizer::new(&b"10 # comment\n1"[..]);
+        assert_eq!(Ok(1), tokenizer.next_bit());
+        assert_eq!(Ok(0), tokenizer.next_bit());
+        assert_eq!(Ok(1), tokenizer.next_bit());
+        assert!(tokenizer.next_bit().is_err());
     }
 
     #[test]
-    fn test_unwrap_and_scale_successful() {
-        let calculated_value = unwrap_and_scale(&String::from("4"), 3.14);
-        assert_eq!(12, calculated_value);
+    fn test_parse_ppm_successful() {
+        let data = b"P3 2 2 15 1 2 3 4 5 6 7 8 9 10 11 12";
+        let image = parse_ppm(&data[..]).unwrap();
+        assert_eq!(&vec![vec![17, 68], vec![119, 170]], image.channel1());
+        assert_eq!(&vec![vec![34, 85], vec![136, 187]], image.channel2());
+        assert_eq!(&vec![vec![51, 102], vec![153, 204]], image.channel3());
     }
 
     #[test]
-    #[should_panic]
-    fn test_unwrap_and_scale_invalid_string() {
-        _ = unwrap_and_scale(&String::from("A"), 3.14);
+    fn test_parse_ppm_invalid() {
+        assert_eq!(Err("Unsupported PPM format!".to_string()), parse_ppm(&b"P6 1 1 255 0 0 0"[..]));
+        assert_eq!(Err("Netpbm file ends unexpectedly!".to_string()), parse_ppm(&b"P3 2 1 255 0 0 0"[..]));
+        assert_eq!(Err("Unsupported PPM size 0x1!".to_string()), parse_ppm(&b"P3 0 1 255"[..]));
+    }
+
+    #[test]
+    fn test_scale() {
+        assert_eq!(12, scale(4, 3.1));
+        assert_eq!(255, scale(15, 17.0));
+    }
+
+    #[test]
+    fn test_pam_rgb_alpha() {
+        let image = read_pam_from_file("test/valid_test_rgb_alpha.pam");
+        assert_eq!((3, 2), (image.width(), image.height()));
+        assert_eq!(&vec![vec![255, 0, 0], vec![255, 0, 128]], image.channel1());
+        assert_eq!(&vec![vec![0, 255, 0], vec![255, 0, 128]], image.channel2());
+        assert_eq!(&vec![vec![0, 0, 255], vec![255, 0, 128]], image.channel3());
+        assert_eq!(&vec![vec![255, 128, 0], vec![255, 255, 64]], image.alpha());
+    }
+
+    #[test]
+    fn test_pam_grayscale() {
+        let image = read_pam_from_file("test/valid_test_grayscale.pam");
+        let expected = create_image(2, 2, vec![vec![0, 64], vec![128, 255]], vec![vec![0, 64], vec![128, 255]], vec![vec![0, 64], vec![128, 255]]);
+        assert_eq!(expected, image);
+        assert!(!image.has_alpha());
+    }
+
+    #[test]
+    fn test_pam_rgb_16_bit() {
+        let image = read_pam_from_file("test/valid_test_rgb_16bit.pam");
+        assert_eq!((255, 0, 0), image.pixel_at(0, 0));
+        assert_eq!((0, 127, 255), image.pixel_at(1, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported PAM tuple type GRAYSCALE_ALPHA!")]
+    fn test_pam_unsupported_tuple_type() {
+        read_pam_from_file("test/invalid_test_pam_depth.pam");
+    }
+
+    #[test]
+    fn test_parse_pam_invalid() {
+        let header = |depth: &str, tuple_type: &str| format!("P7\nWIDTH 1\nHEIGHT 1\n{}MAXVAL 255\n{}ENDHDR\n", depth, tuple_type);
+        let error = |data: String| parse_pam(&[data.as_bytes(), &[0; 4]].concat()).unwrap_err();
+
+        assert_eq!("Unsupported PAM depth 2!", error(header("DEPTH 2\n", "")));
+        assert_eq!("Unsupported PAM depth 3 for tuple type RgbAlpha!", error(header("DEPTH 3\n", "TUPLTYPE RGB_ALPHA\n")));
+        assert_eq!("PAM header is missing WIDTH, HEIGHT, DEPTH or MAXVAL!", error(header("", "")));
+        assert!(parse_pam(b"P6\n1 1\n255\n").is_err());
+        assert!(parse_pam(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\n").is_err());
+        assert!(parse_pam(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\nENDHDR\n\x01\x02").is_err());
+        // the tuple type is deduced from the depth
+        assert!(parse_pam(&[header("DEPTH 4\n", "").as_bytes(), &[0; 4]].concat()).unwrap().has_alpha());
+    }
+
+    #[test]
+    fn test_pnm_plain_and_binary_bitmap() {
+        let expected = create_image(2, 3, vec![vec![0, 255, 0], vec![255, 255, 0]], vec![vec![0, 255, 0], vec![255, 255, 0]], vec![vec![0, 255, 0], vec![255, 255, 0]]);
+        assert_eq!(expected, parse_pnm(b"P1\n# a comment\n3 2\n101\n0 0 1\n").unwrap());
+        assert_eq!(expected, parse_pnm(b"P4\n3 2\n\xa0\x20").unwrap());
+    }
+
+    #[test]
+    fn test_pnm_plain_and_binary_graymap() {
+        let expected = create_image(1, 3, vec![vec![0, 127, 255]], vec![vec![0, 127, 255]], vec![vec![0, 127, 255]]);
+        assert_eq!(expected, parse_pnm(b"P2 3 1 # inline comment\n4\n0 2 4\n").unwrap());
+        assert_eq!(expected, parse_pnm(b"P5\n3 1\n4\n\x00\x02\x04").unwrap());
+        assert_eq!(expected, parse_pnm(b"P5 3 1 1023\n\x00\x00\x01\xff\x03\xff").unwrap());
+    }
+
+    #[test]
+    fn test_pnm_binary_pixmap() {
+        let image = read_pnm_from_file("test/valid_test_binary.ppm");
+        assert_eq!(image, read_ppm_from_file("test/valid_test_maxVal_15.ppm"));
+    }
+
+    #[test]
+    fn test_parse_pnm_invalid() {
+        assert_eq!("Not a PBM, PGM or binary PPM file!", parse_pnm(b"P3\n1 1\n255\n0 0 0").unwrap_err());
+        assert_eq!("Invalid PNM maximum value!", parse_pnm(b"P5\n1 1\nmax\n\x00").unwrap_err());
+        assert_eq!("Unsupported PNM size 0x1!", parse_pnm(b"P5\n0 1\n255\n").unwrap_err());
+        assert_eq!("PNM file ends before all samples are read!", parse_pnm(b"P6\n1 1\n255\n\x00\x00").unwrap_err());
+        assert_eq!("PNM file ends before all samples are read!", parse_pnm(b"P2\n2 1\n255\n0 x\n").unwrap_err());
+    }
+
+    #[test]
+    fn test_parse_netpbm_images() {
+        let data = b"P3 1 1 255 1 2 3\nP6\n1 1\n255\n\x04\x05\x06# comment\nP5 2 1 255\n\x07\x08P1 1 1 1\nP7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nENDHDR\n\x09\n";
+        let images: Vec<_> = parse_netpbm_images(data).into_iter().map(Result::unwrap).collect();
+        assert_eq!(5, images.len());
+        assert_eq!((1, 2, 3), images[0].pixel_at(0, 0));
+        assert_eq!((4, 5, 6), images[1].pixel_at(0, 0));
+        assert_eq!((8, 8, 8), images[2].pixel_at(1, 0));
+        assert_eq!((0, 0, 0), images[3].pixel_at(0, 0));
+        assert_eq!((9, 9, 9), images[4].pixel_at(0, 0));
+    }
+
+    #[test]
+    fn test_parse_netpbm_images_stops_at_invalid_image() {
+        let results = parse_netpbm_images(b"P5 1 1 255 \x01P5 1 1 0 \x00P5 1 1 255 \x02");
+        assert_eq!(2, results.len());
+        assert!(results[0].is_ok());
+        assert_eq!(Err("Invalid PNM maximum value 0!".to_string()), results[1].clone().map(|_| ()));
+        assert!(parse_netpbm_images(b"").is_empty());
+    }
+
+    #[test]
+    fn test_read_netpbm_images_from_file() {
+        let results = read_netpbm_images_from_file("test/valid_test_multi.ppm");
+        assert_eq!(3, results.len());
+        assert_eq!(&read_ppm_from_file("test/valid_test_maxVal_15.ppm"), results[0].as_ref().unwrap());
+        assert_eq!(&read_pnm_from_file("test/valid_test_binary.ppm"), results[1].as_ref().unwrap());
+        assert!(read_netpbm_images_from_file("test/missing.ppm")[0].is_err());
     }
 }
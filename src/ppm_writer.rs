@@ -0,0 +1,194 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+
+use crate::image::Image;
+
+/// The offset between level shifted samples (as stored after `rgb_to_ycbcr`) and unsigned 8 bit samples.
+pub const LEVEL_SHIFT: i16 = 128;
+
+/// How the samples of a PPM or PGM file are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PpmEncoding {
+    /// ASCII decimal numbers (P3 for PPM, P2 for PGM), easy to read and diff.
+    Plain,
+    /// One byte per sample (P6 for PPM, P5 for PGM).
+    #[default]
+    Binary,
+}
+
+/// Write an image to a PPM or PGM file, see `write_ppm`.
+///
+/// # Arguments
+/// * `image`: The image to write.
+/// * `encoding`: How to store the samples.
+/// * `path`: The path of the file to write to.
+///
+/// # Examples
+/// ```
+/// let image = read_jpg_from_file("output.jpg");
+/// write_ppm_to_file(&image, PpmEncoding::Binary, "decoded.ppm")?;
+/// ```
+///
+/// # Errors
+/// * If the file can't be written.
+/// * See `write_ppm`.
+pub fn write_ppm_to_file(image: &Image, encoding: PpmEncoding, path: &str) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_ppm(image, encoding, &mut writer)?;
+    writer.flush()
+}
+
+/// Write an image as PPM, or as PGM if it is grayscale, with a maximum value of 255.
+/// RGB images are written as they are, grayscale images (which are level shifted, see `create_grayscale_image`)
+/// are shifted back to 0..=255. Samples outside of 0..=255 are clamped.
+/// Images with downsampled channels are written at full size, repeating their samples.
+/// YCbCr images should be converted with `Image::to_rgb` first, or written channel by channel with `write_pgm`.
+///
+/// # Arguments
+/// * `image`: The image to write.
+/// * `encoding`: How to store the samples.
+/// * `writer`: The writer to write to.
+///
+/// # Errors
+/// * If the image has four channels.
+/// * If the writer fails.
+pub fn write_ppm<W: Write>(image: &Image, encoding: PpmEncoding, writer: &mut W) -> Result<(), Error> {
+    let (width, height) = (image.width(), image.height());
+    match image.channel_count() {
+        1 => write_pgm(image.channel1(), width, height, LEVEL_SHIFT, encoding, writer),
+        3 => {
+            let magic_number = match encoding {
+                PpmEncoding::Plain => "P3",
+                PpmEncoding::Binary => "P6",
+            };
+            write!(writer, "{}\n{} {}\n255\n", magic_number, width, height)?;
+            for y in 0..height {
+                let row: Vec<u8> = (0..width)
+                    .flat_map(|x| {
+                        let (r, g, b) = image.pixel_at(x, y);
+                        [r, g, b].map(|value| value.clamp(0, 255) as u8)
+                    })
+                    .collect();
+                write_row(&row, encoding, writer)?;
+            }
+            Ok(())
+        }
+        _ => Err(Error::new(ErrorKind::InvalidInput, "Images with four channels can't be written as PPM!")),
+    }
+}
+
+/// Write a single channel as PGM, e.g. the Y, Cb or Cr channel to inspect a step of the encoding.
+///
+/// # Arguments
+/// * `channel`: The rows of the channel.
+/// * `width`: The amount of samples to write per row, e.g. the image width to leave out padding.
+/// * `height`: The amount of rows to write.
+/// * `offset`: The value added to each sample before clamping it to 0..=255,
+///   `LEVEL_SHIFT` for level shifted channels, 0 for all others.
+/// * `encoding`: How to store the samples.
+/// * `writer`: The writer to write to.
+///
+/// # Examples
+/// ```
+/// image.rgb_to_ycbcr();
+/// let mut file = File::create("luma.pgm")?;
+/// write_pgm(image.channel1(), image.width(), image.height(), LEVEL_SHIFT, PpmEncoding::Plain, &mut file)?;
+/// ```
+///
+/// # Errors
+/// * If the channel is smaller than `width` times `height`.
+/// * If the writer fails.
+pub fn write_pgm<W: Write>(channel: &[Vec<i16>], width: u16, height: u16, offset: i16, encoding: PpmEncoding, writer: &mut W) -> Result<(), Error> {
+    let (width, height) = (width as usize, height as usize);
+    if channel.len() < height || channel.iter().take(height).any(|row| row.len() < width) {
+        return Err(Error::new(ErrorKind::InvalidInput, "Channel is smaller than the given size!"));
+    }
+    let magic_number = match encoding {
+        PpmEncoding::Plain => "P2",
+        PpmEncoding::Binary => "P5",
+    };
+    write!(writer, "{}\n{} {}\n255\n", magic_number, width, height)?;
+    for row in channel.iter().take(height) {
+        let row: Vec<u8> = row[..width]
+            .iter()
+            .map(|value| value.saturating_add(offset).clamp(0, 255) as u8)
+            .collect();
+        write_row(&row, encoding, writer)?;
+    }
+    Ok(())
+}
+
+/// Write the samples of one row, as bytes or as decimal numbers on their own line.
+fn write_row<W: Write>(row: &[u8], encoding: PpmEncoding, writer: &mut W) -> Result<(), Error> {
+    match encoding {
+        PpmEncoding::Binary => writer.write_all(row),
+        PpmEncoding::Plain => {
+            // plain files should not have lines longer than 70 characters
+            for samples in row.chunks(17) {
+                let line: Vec<String> = samples.iter().map(|sample| sample.to_string()).collect();
+                writeln!(writer, "{}", line.join(" "))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::{create_grayscale_image, create_image, create_ycbcr_420_image};
+    use crate::ppm_parser::{parse_pnm, read_ppm, read_ppm_from_file};
+
+    use super::{write_pgm, write_ppm, PpmEncoding, LEVEL_SHIFT};
+
+    #[test]
+    fn test_write_ppm_plain_round_trip() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        let mut data: Vec<u8> = vec![];
+        write_ppm(&image, PpmEncoding::Plain, &mut data).unwrap();
+        assert!(data.starts_with(b"P3\n73 117\n255\n"));
+        assert!(data.split(|byte| *byte == b'\n').all(|line| line.len() <= 70));
+        assert_eq!(Ok(image), read_ppm(data.as_slice()));
+    }
+
+    #[test]
+    fn test_write_ppm_binary_round_trip() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        let mut data: Vec<u8> = vec![];
+        write_ppm(&image, PpmEncoding::Binary, &mut data).unwrap();
+        assert_eq!(14 + 73 * 117 * 3, data.len());
+        assert_eq!(Ok(image), parse_pnm(&data));
+    }
+
+    #[test]
+    fn test_write_ppm_clamps() {
+        let image = create_image(1, 2, vec![vec![-20, 300]], vec![vec![0, 255]], vec![vec![128, 1000]]);
+        let mut data: Vec<u8> = vec![];
+        write_ppm(&image, PpmEncoding::Plain, &mut data).unwrap();
+        assert_eq!(b"P3\n2 1\n255\n0 0 128 255 255 255\n".to_vec(), data);
+    }
+
+    #[test]
+    fn test_write_ppm_grayscale() {
+        let image = create_grayscale_image(2, 1, vec![vec![-128], vec![127]]);
+        let mut data: Vec<u8> = vec![];
+        write_ppm(&image, PpmEncoding::Binary, &mut data).unwrap();
+        assert_eq!(b"P5\n1 2\n255\n\x00\xff".to_vec(), data);
+    }
+
+    #[test]
+    fn test_write_ppm_downsampled() {
+        let image = create_ycbcr_420_image(1, 3, vec![vec![1, 2, 3]], vec![vec![4, 5]], vec![vec![6, 7]]);
+        let mut data: Vec<u8> = vec![];
+        write_ppm(&image, PpmEncoding::Plain, &mut data).unwrap();
+        assert_eq!(b"P3\n3 1\n255\n1 4 6 2 4 6 3 5 7\n".to_vec(), data);
+    }
+
+    #[test]
+    fn test_write_pgm_crops_padding() {
+        let channel = vec![vec![-128, 0, 127, 5], vec![1, 2, 3, 4], vec![0; 4]];
+        let mut data: Vec<u8> = vec![];
+        write_pgm(&channel, 3, 2, LEVEL_SHIFT, PpmEncoding::Plain, &mut data).unwrap();
+        assert_eq!(b"P2\n3 2\n255\n0 128 255\n129 130 131\n".to_vec(), data);
+        assert!(write_pgm(&channel, 5, 2, 0, PpmEncoding::Plain, &mut data).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use nalgebra::SMatrix;
+use scoped_threadpool::Pool;
+
+use crate::bit_stream::BitStream;
+use crate::dct::matrix_inverse_dct;
+use crate::encoder::{encode_dct_to_stream, quantize_channel, EncoderSettings};
+use crate::image::Image;
+use crate::image_metrics::{self, Plane};
+use crate::quantization::{dequantize, unsample_zigzag, MAX_QUALITY, MIN_QUALITY};
+use crate::utils::THREAD_COUNT;
+
+/// Offset to get from the level shifted samples used for the DCT back to 0..=255.
+const LEVEL_SHIFT: f64 = 128.0;
+
+/// The perceptual quality an encoded image should at least have.
+pub enum QualityTarget {
+    /// The minimum PSNR in dB, measured over the Y, Cb and Cr channels as they are encoded.
+    Psnr(f64),
+    /// The minimum SSIM of the Y channel, between 0 and 1.
+    Ssim(f64),
+}
+
+impl QualityTarget {
+    fn value(&self) -> f64 {
+        match self {
+            QualityTarget::Psnr(value) => *value,
+            QualityTarget::Ssim(value) => *value,
+        }
+    }
+}
+
+/// The result of a quality controlled encoding.
+///
+/// # Attributes
+///
+/// * `stream`: The encoded JPEG file.
+/// * `quality`: The quality (see `quantization::scale_q_table`) the file was encoded with.
+/// * `achieved`: The PSNR or SSIM (depending on the target) the file achieves.
+/// * `target_met`: False if even the highest quality doesn't meet the target. In that case,
+///   `stream` contains the file encoded with the highest quality.
+pub struct QualityControlResult {
+    pub stream: BitStream,
+    pub quality: u8,
+    pub achieved: f64,
+    pub target_met: bool,
+}
+
+/// Encode the DCT output of an image with the lowest quality (and thus the smallest output)
+/// whose decoded result still meets the quality target.
+/// The quality is searched by bisection. Candidates are evaluated with an internal decode
+/// (quantize, dequantize and inverse DCT) rather than a full encode, and compared to the
+/// image data the DCT was calculated from. Only the chosen quality is entropy coded.
+///
+/// # Arguments
+/// * `image`: The image the DCT output was calculated from. This has to be the (padded) image
+///   that was passed to `parallel_dct::dct`.
+/// * `y_dct`: The DCT output of the Y channel, as returned by `parallel_dct::dct`.
+/// * `cb_dct`: The DCT output of the Cb channel.
+/// * `cr_dct`: The DCT output of the Cr channel.
+/// * `settings`: The settings to encode with. Its quantization tables are scaled by the quality.
+/// * `target`: The quality the decoded image should at least have.
+/// * `pool`: The thread pool to quantize and decode with.
+///
+/// # Example
+/// ```
+/// let (y_dct, cb_dct, cr_dct) = parallel_dct::dct(&mut image, &DCTMode::Arai, &mut pool);
+/// let result = encode_to_quality_target(&image, &y_dct, &cb_dct, &cr_dct, &EncoderSettings::default(), &QualityTarget::Psnr(40.0), &mut pool);
+/// println!("PSNR: {} dB", result.achieved);
+/// ```
+pub fn encode_to_quality_target(
+    image: &Image,
+    y_dct: &[SMatrix<f32, 8, 8>],
+    cb_dct: &[SMatrix<f32, 8, 8>],
+    cr_dct: &[SMatrix<f32, 8, 8>],
+    settings: &EncoderSettings,
+    target: &QualityTarget,
+    pool: &mut Pool,
+) -> QualityControlResult {
+    let mut lower = MIN_QUALITY;
+    let mut upper = MAX_QUALITY;
+    while lower < upper {
+        let quality = lower + (upper - lower) / 2;
+        let achieved = evaluate_quality(image, y_dct, cb_dct, cr_dct, &settings.with_quality(quality), target, pool);
+        if achieved >= target.value() {
+            upper = quality;
+        } else {
+            lower = quality + 1;
+        }
+    }
+
+    let settings = settings.with_quality(lower);
+    let achieved = evaluate_quality(image, y_dct, cb_dct, cr_dct, &settings, target, pool);
+    QualityControlResult {
+        stream: encode_dct_to_stream(image, y_dct.to_vec(), cb_dct.to_vec(), cr_dct.to_vec(), &settings, pool),
+        quality: lower,
+        achieved,
+        target_met: achieved >= target.value(),
+    }
+}
+
+/// Decode the DCT output as it would be after quantizing with the given settings,
+/// then measure the metric of the target against the image.
+fn evaluate_quality(
+    image: &Image,
+    y_dct: &[SMatrix<f32, 8, 8>],
+    cb_dct: &[SMatrix<f32, 8, 8>],
+    cr_dct: &[SMatrix<f32, 8, 8>],
+    settings: &EncoderSettings,
+    target: &QualityTarget,
+    pool: &mut Pool,
+) -> f64 {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+    let y_original = image_metrics::plane_from_channel(image.channel1(), width, height, LEVEL_SHIFT);
+    let y_reconstructed = blocks_to_plane(&reconstruct_channel(y_dct, &settings.luminance_q_table, settings, pool), image.channel1()[0].len(), width, height);
+
+    match target {
+        QualityTarget::Ssim(_) => image_metrics::ssim(&y_original, &y_reconstructed),
+        QualityTarget::Psnr(_) => {
+            let chroma_height = if image.downsampled_vertically() { height.div_ceil(2) } else { height };
+            let cb_width = width.div_ceil(image.cb_downsample_factor());
+            let cr_width = width.div_ceil(image.cr_downsample_factor());
+            let cb_original = image_metrics::plane_from_channel(image.channel2(), cb_width, chroma_height, LEVEL_SHIFT);
+            let cr_original = image_metrics::plane_from_channel(image.channel3(), cr_width, chroma_height, LEVEL_SHIFT);
+            let cb_reconstructed = blocks_to_plane(&reconstruct_channel(cb_dct, &settings.chrominance_q_table, settings, pool), image.channel2()[0].len(), cb_width, chroma_height);
+            let cr_reconstructed = blocks_to_plane(&reconstruct_channel(cr_dct, &settings.chrominance_q_table, settings, pool), image.channel3()[0].len(), cr_width, chroma_height);
+
+            let y_samples = (width * height) as f64;
+            let cb_samples = (cb_width * chroma_height) as f64;
+            let cr_samples = (cr_width * chroma_height) as f64;
+            let error = image_metrics::mean_squared_error(&y_original, &y_reconstructed) * y_samples
+                + image_metrics::mean_squared_error(&cb_original, &cb_reconstructed) * cb_samples
+                + image_metrics::mean_squared_error(&cr_original, &cr_reconstructed) * cr_samples;
+            image_metrics::psnr(error / (y_samples + cb_samples + cr_samples))
+        }
+    }
+}
+
+/// Quantize, dequantize and inverse DCT a channel's DCT output, like a decoder would see it.
+/// Quantization is done the same way as when encoding, see `encoder::quantize_channel`.
+/// The result is rounded and clamped to the range of level shifted 8 bit samples.
+/// This is parallelised with as many threads as the system has logical CPUs.
+///
+/// # Arguments
+/// * `dct`: The DCT output of the channel.
+/// * `q_table`: The quantization table to use, in format 1/x.
+/// * `settings`: The settings selecting the quantizer.
+/// * `pool`: The thread pool to use.
+fn reconstruct_channel(
+    dct: &[SMatrix<f32, 8, 8>],
+    q_table: &SMatrix<f32, 8, 8>,
+    settings: &EncoderSettings,
+    pool: &mut Pool,
+) -> Vec<SMatrix<f32, 8, 8>> {
+    let quantized = quantize_channel(dct.to_vec(), q_table, settings, pool);
+    let mut blocks = vec![SMatrix::<f32, 8, 8>::zeros(); quantized.len()];
+    let q_table = *q_table;
+    let chunk_size = (blocks.len() / *THREAD_COUNT) + 1;
+    pool.scoped(|s| {
+        for (input, output) in quantized.chunks(chunk_size).zip(blocks.chunks_mut(chunk_size)) {
+            s.execute(move || {
+                for (values, matrix) in input.iter().zip(output) {
+                    *matrix = unsample_zigzag(values).map(|value| value as f32);
+                    dequantize(matrix, &q_table);
+                    *matrix = matrix_inverse_dct(matrix);
+                    matrix.apply(|value| *value = value.round().clamp(-128.0, 127.0));
+                }
+            });
+        }
+    });
+    blocks
+}
+
+/// Put 8x8 blocks (ordered top to bottom, then left to right, as in `Image::to_matrices`)
+/// back together into rows of samples, reverting the level shift and cropping the padding.
+///
+/// # Arguments
+/// * `blocks`: The blocks to combine.
+/// * `padded_width`: The width of the padded channel the blocks cover. Has to be divisible by 8.
+/// * `width`: The width of the resulting plane.
+/// * `height`: The height of the resulting plane.
+fn blocks_to_plane(blocks: &[SMatrix<f32, 8, 8>], padded_width: usize, width: usize, height: usize) -> Plane {
+    let blocks_per_row = padded_width / 8;
+    let mut plane = vec![vec![0.0; width]; height];
+    for (index, block) in blocks.iter().enumerate() {
+        let x = (index % blocks_per_row) * 8;
+        let y = (index / blocks_per_row) * 8;
+        for i in 0..8 {
+            for j in 0..8 {
+                if y + i < height && x + j < width {
+                    plane[y + i][x + j] = block[(i, j)] as f64 + LEVEL_SHIFT;
+                }
+            }
+        }
+    }
+    plane
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use nalgebra::SMatrix;
+    use scoped_threadpool::Pool;
+
+    use crate::encoder::EncoderSettings;
+    use crate::image::ColorMatrix;
+    use crate::test_utils::prepare_image;
+
+    use super::{blocks_to_plane, encode_to_quality_target, QualityTarget};
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    #[test]
+    fn test_blocks_to_plane() {
+        let blocks = vec![SMatrix::from_element(1.0), SMatrix::from_element(2.0), SMatrix::from_element(3.0), SMatrix::from_element(4.0)];
+        let plane = blocks_to_plane(&blocks, 16, 15, 13);
+        assert_eq!(13, plane.len());
+        assert_eq!(15, plane[0].len());
+        assert_eq!(129.0, plane[0][7]);
+        assert_eq!(130.0, plane[7][8]);
+        assert_eq!(131.0, plane[8][0]);
+        assert_eq!(132.0, plane[12][14]);
+    }
+
+    #[test]
+    fn test_encode_to_quality_target_psnr() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_73x117_color.ppm", ColorMatrix::Bt601, &mut pool);
+        let settings = EncoderSettings::default();
+
+        let low = encode_to_quality_target(&image, &dct.0, &dct.1, &dct.2, &settings, &QualityTarget::Psnr(30.0), &mut pool);
+        let high = encode_to_quality_target(&image, &dct.0, &dct.1, &dct.2, &settings, &QualityTarget::Psnr(40.0), &mut pool);
+
+        assert!(low.target_met);
+        assert!(high.target_met);
+        assert!(low.achieved >= 30.0);
+        assert!(high.achieved >= 40.0);
+        assert!(high.quality > low.quality);
+        assert!(high.stream.data().len() > low.stream.data().len());
+    }
+
+    #[test]
+    fn test_encode_to_quality_target_ssim() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_73x117_color.ppm", ColorMatrix::Bt601, &mut pool);
+
+        let result = encode_to_quality_target(&image, &dct.0, &dct.1, &dct.2, &EncoderSettings::default(), &QualityTarget::Ssim(0.95), &mut pool);
+
+        assert!(result.target_met);
+        assert!(result.achieved >= 0.95);
+        assert!(result.achieved <= 1.0);
+    }
+
+    #[test]
+    fn test_encode_to_quality_target_unreachable() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_73x117_color.ppm", ColorMatrix::Bt601, &mut pool);
+
+        let result = encode_to_quality_target(&image, &dct.0, &dct.1, &dct.2, &EncoderSettings::default(), &QualityTarget::Psnr(1000.0), &mut pool);
+
+        assert!(!result.target_met);
+        assert_eq!(100, result.quality);
+    }
+}
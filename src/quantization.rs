@@ -1,6 +1,8 @@
 use core::f32;
 
-use nalgebra::SMatrix;
+use nalgebra::{SMatrix, Scalar};
+
+use crate::coefficient_encoder::AcCostModel;
 
 /// Create a uniform quantization matrix from factor x in format 1/x
 /// # Arguments
@@ -39,6 +41,32 @@ pub fn box_q_table(global_factor: f32, box_size: usize, box_growth: f32) -> SMat
     })
 }
 
+/// The lowest quality accepted by `scale_q_table`.
+pub const MIN_QUALITY: u8 = 1;
+/// The highest quality accepted by `scale_q_table`.
+pub const MAX_QUALITY: u8 = 100;
+
+/// Scale a quantization table (format 1/x) by a quality value between 1 and 100,
+/// following the IJG quality scaling: 50 keeps the table as is, lower values
+/// make the quantization factors larger and higher values make them smaller.
+/// The resulting factors are rounded and clamped to 1..=255, so the table
+/// used for quantization matches the one written to the DQT segment exactly.
+/// # Arguments
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+/// * `quality`: The quality to scale to. Values outside of 1..=100 are clamped.
+pub fn scale_q_table(q_table: &SMatrix<f32, 8, 8>, quality: u8) -> SMatrix<f32, 8, 8> {
+    let quality = quality.clamp(MIN_QUALITY, MAX_QUALITY) as f32;
+    let scale = if quality < 50.0 {
+        5000.0 / quality
+    } else {
+        200.0 - 2.0 * quality
+    };
+    q_table.map(|value| {
+        let factor = (1.0 / value * scale / 100.0).round().clamp(1.0, 255.0);
+        1.0 / factor
+    })
+}
+
 /// Quantize the given matrix by multiplying it component-wise with
 /// the quantization table with format 1/x. The condition in the map only
 /// applies to exact 0.5 values, e.g. in test_quatization_from_slides, value 25.0 and
@@ -57,6 +85,163 @@ pub fn quantize(data: &mut SMatrix<f32, 8, 8>, q_table: &SMatrix<f32, 8, 8>) {
     });
 }
 
+/// Dequantize the given matrix by dividing it component-wise by
+/// the quantization table with format 1/x. This is what a decoder does,
+/// so it reverts `quantize` except for the rounding.
+/// # Arguments
+/// * `data`: The quantized matrix to perform the dequantization on
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+pub fn dequantize(data: &mut SMatrix<f32, 8, 8>, q_table: &SMatrix<f32, 8, 8>) {
+    data.component_div_assign(q_table);
+}
+
+/// The largest amount of doublings of the activity over the mean activity that still widens the deadzone.
+const MAX_MASKING_OCTAVES: f32 = 2.0;
+
+/// Get the activity of a block of DCT output, which is the variance of the block's samples.
+/// As the DCT is orthonormal, this is the energy of the AC coefficients divided by the amount of samples.
+/// # Arguments
+/// * `data`: The DCT output of the block.
+pub fn block_activity(data: &SMatrix<f32, 8, 8>) -> f32 {
+    (data.norm_squared() - data[(0, 0)] * data[(0, 0)]) / 64.0
+}
+
+/// Get the deadzone of a block for activity masking.
+/// Errors are less visible in busy blocks, so the more active a block is compared to the
+/// mean activity, the wider its deadzone gets. Blocks at or below the mean keep the deadzone of 0.5
+/// that plain rounding has.
+/// # Arguments
+/// * `activity`: The activity of the block, see `block_activity`.
+/// * `mean_activity`: The mean activity of all blocks of the channel.
+/// * `strength`: How much the deadzone grows with each doubling of the activity.
+pub fn masking_deadzone(activity: f32, mean_activity: f32, strength: f32) -> f32 {
+    let octaves = ((activity + 1.0) / (mean_activity + 1.0)).log2();
+    0.5 + strength * octaves.clamp(0.0, MAX_MASKING_OCTAVES)
+}
+
+/// Zero all AC coefficients of a block that would be quantized to a magnitude below the deadzone.
+/// The remaining coefficients are left as they are, so this can be done before any quantizer.
+/// # Arguments
+/// * `data`: The DCT output of the block.
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+/// * `deadzone`: The magnitude (in quantization steps) below which coefficients are zeroed.
+pub fn apply_deadzone(data: &mut SMatrix<f32, 8, 8>, q_table: &SMatrix<f32, 8, 8>, deadzone: f32) {
+    for i in 0..8 {
+        for j in 0..8 {
+            if (i, j) != (0, 0) && (data[(i, j)] * q_table[(i, j)]).abs() < deadzone {
+                data[(i, j)] = 0.0;
+            }
+        }
+    }
+}
+
+/// Rate-distortion optimized ("trellis") quantization of a single block.
+/// Starting from the rounded values `quantize` would produce, each AC coefficient may be kept,
+/// lowered by one towards zero or zeroed. The combination minimizing
+/// `distortion + lambda * bits` is found by dynamic programming over the zigzag positions,
+/// where distortion is the squared error of the dequantized DCT coefficients and bits are
+/// estimated with the run-length/huffman cost model.
+/// The DC coefficient is always rounded, as it is difference coded across blocks.
+/// # Arguments
+/// * `data`: The DCT output to quantize.
+/// * `q_table`: The quantization matrix with quantization factor x in format 1/x
+/// * `cost_model`: The estimated bit costs of the AC coefficients.
+/// * `lambda`: The weight of one bit against the squared error. With 0, the result is the same as
+///   with `quantize`, except that values of exactly -0.5 may be zeroed, as that is just as close.
+///
+/// # Returns
+/// The zigzag sampled quantized values, like `parallel_quantize::quantize_zigzag`.
+pub fn trellis_quantize(
+    data: &SMatrix<f32, 8, 8>,
+    q_table: &SMatrix<f32, 8, 8>,
+    cost_model: &AcCostModel,
+    lambda: f32,
+) -> [i32; 64] {
+    let mut rounded = *data;
+    quantize(&mut rounded, q_table);
+    let levels = sample_zigzag(&rounded.map(|value| value as i32));
+    let coefficients = sample_zigzag(data);
+    let factors = sample_zigzag(q_table);
+
+    // zero_distortion[k]: squared error of zeroing all AC coefficients up to position k
+    let mut zero_distortion = [0f32; 64];
+    for k in 1..64 {
+        zero_distortion[k] = zero_distortion[k - 1] + coefficients[k] * coefficients[k];
+    }
+
+    // best cost of all coefficients up to position j, with j being the last non-zero one
+    // (position 0 stands for "no non-zero AC coefficient yet")
+    let mut best_cost = [f32::INFINITY; 64];
+    let mut best_previous = [0usize; 64];
+    let mut best_value = [0i32; 64];
+    best_cost[0] = 0.0;
+
+    for j in 1..64 {
+        let level = levels[j];
+        if level == 0 {
+            continue;
+        }
+        // the rounded level and, unless that would zero it, the level one closer to zero
+        let candidates = [level, level - level.signum()];
+        let candidate_count = if level.abs() > 1 { 2 } else { 1 };
+        for &candidate in &candidates[..candidate_count] {
+            let error = coefficients[j] - candidate as f32 / factors[j];
+            let distortion = error * error;
+            for i in 0..j {
+                if best_cost[i].is_infinite() {
+                    continue;
+                }
+                let cost = best_cost[i]
+                    + zero_distortion[j - 1]
+                    - zero_distortion[i]
+                    + distortion
+                    + lambda * cost_model.coefficient_cost(j - i - 1, candidate) as f32;
+                if cost < best_cost[j] {
+                    best_cost[j] = cost;
+                    best_previous[j] = i;
+                    best_value[j] = candidate;
+                }
+            }
+        }
+    }
+
+    let mut last = 0;
+    let mut best_total = f32::INFINITY;
+    for (i, cost) in best_cost.iter().enumerate() {
+        if cost.is_infinite() {
+            continue;
+        }
+        let mut total = cost + zero_distortion[63] - zero_distortion[i];
+        if i < 63 {
+            total += lambda * cost_model.end_of_block_cost() as f32;
+        }
+        if total < best_total {
+            best_total = total;
+            last = i;
+        }
+    }
+
+    let mut result = [0i32; 64];
+    result[0] = levels[0];
+    while last > 0 {
+        result[last] = best_value[last];
+        last = best_previous[last];
+    }
+    result
+}
+
+/// Revert zigzag sampling, putting the values back into their matrix positions.
+/// # Arguments
+/// * `data`: The zigzag sampled values.
+pub fn unsample_zigzag<T: Scalar + Copy>(data: &[T; 64]) -> SMatrix<T, 8, 8> {
+    let positions = sample_zigzag(&SMatrix::<usize, 8, 8>::from_fn(|i, j| i * 8 + j));
+    let mut result = SMatrix::from_element(data[0]);
+    for (value, position) in data.iter().zip(positions) {
+        result[(position / 8, position % 8)] = *value;
+    }
+    result
+}
+
 /// Zigzag sample the given data.
 /// The sampling is hardcoded for simplicity reasons.
 /// # Arguments
@@ -134,7 +319,133 @@ pub fn sample_zigzag<T: Copy>(data: &SMatrix<T, 8, 8>) -> [T; 64] {
 mod test {
     use nalgebra::SMatrix;
 
-    use super::{quantize, sample_zigzag, uniform_q_table};
+    use crate::coefficient_encoder::AcCostModel;
+    use crate::huffman::HuffmanCodeMap;
+
+    use super::{apply_deadzone, block_activity, box_q_table, dequantize, masking_deadzone, quantize, sample_zigzag, scale_q_table, trellis_quantize, uniform_q_table, unsample_zigzag};
+
+    #[test]
+    fn test_block_activity() {
+        let flat: SMatrix<f32, 8, 8> = SMatrix::from_fn(|i, j| if (i, j) == (0, 0) { 800.0 } else { 0.0 });
+        assert_eq!(0.0, block_activity(&flat));
+        let mut busy = flat;
+        busy[(1, 2)] = 16.0;
+        busy[(5, 0)] = -8.0;
+        assert_eq!(5.0, block_activity(&busy));
+    }
+
+    #[test]
+    fn test_masking_deadzone() {
+        assert_eq!(0.5, masking_deadzone(0.0, 99.0, 0.25));
+        assert_eq!(0.5, masking_deadzone(99.0, 99.0, 0.25));
+        assert_eq!(0.75, masking_deadzone(199.0, 99.0, 0.25));
+        // capped at MAX_MASKING_OCTAVES
+        assert_eq!(1.0, masking_deadzone(100_000.0, 99.0, 0.25));
+        assert_eq!(0.5, masking_deadzone(100_000.0, 99.0, 0.0));
+    }
+
+    #[test]
+    fn test_apply_deadzone() {
+        let mut x = slides_matrix();
+        let q_table = uniform_q_table(10.0);
+        apply_deadzone(&mut x, &q_table, 1.0);
+        assert_eq!(581.0, x[(0, 0)]);
+        assert_eq!(-144.0, x[(0, 1)]);
+        assert_eq!(17.0, x[(0, 3)]);
+        assert_eq!(0.0, x[(0, 5)]);
+        assert_eq!(0.0, x[(7, 7)]);
+    }
+
+    fn slides_matrix() -> SMatrix<f32, 8, 8> {
+        let x_vec = vec![
+            581.0, -144.0, 56.0, 17.0, 15.0, -7.0, 25.0, -9.0, -242.0, 133.0, -48.0, 42.0, -2.0,
+            -7.0, 13.0, -4.0, 108.0, -18.0, -40.0, 71.0, -33.0, 12.0, 6.0, -10.0, -56.0, -93.0,
+            48.0, 19.0, -8.0, 7.0, 6.0, -2.0, -17.0, 9.0, 7.0, -23.0, -3.0, -10.0, 5.0, 3.0, 4.0,
+            9.0, -4.0, -5.0, 2.0, 2.0, -7.0, 3.0, -9.0, 7.0, 8.0, -6.0, 5.0, 12.0, 2.0, -5.0, -9.0,
+            -4.0, -2.0, -3.0, 6.0, 1.0, -1.0, -1.0,
+        ];
+        SMatrix::from_row_iterator(x_vec)
+    }
+
+    fn uniform_cost_model(length: u8) -> AcCostModel {
+        let mut code_map = HuffmanCodeMap::new();
+        for symbol in 0..=255u8 {
+            code_map.insert(symbol, (length, 0));
+        }
+        AcCostModel::from_code_map(&code_map)
+    }
+
+    #[test]
+    fn test_trellis_quantize_lambda_zero_is_rounding() {
+        let x = slides_matrix();
+        let q_table = uniform_q_table(7.0);
+        let mut rounded = x;
+        quantize(&mut rounded, &q_table);
+
+        let result = trellis_quantize(&x, &q_table, &uniform_cost_model(4), 0.0);
+        assert_eq!(sample_zigzag(&rounded.map(|value| value as i32)), result);
+    }
+
+    #[test]
+    fn test_trellis_quantize_huge_lambda_zeroes_ac() {
+        let x = slides_matrix();
+        let q_table = uniform_q_table(7.0);
+
+        let result = trellis_quantize(&x, &q_table, &uniform_cost_model(4), 1e9);
+        assert_eq!(83, result[0]);
+        assert!(result[1..].iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn test_trellis_quantize_never_increases_magnitude() {
+        let x = slides_matrix();
+        let q_table = uniform_q_table(7.0);
+        let mut rounded = x;
+        quantize(&mut rounded, &q_table);
+        let rounded = sample_zigzag(&rounded.map(|value| value as i32));
+
+        let result = trellis_quantize(&x, &q_table, &uniform_cost_model(4), 50.0);
+        let non_zero = |values: &[i32; 64]| values.iter().filter(|value| **value != 0).count();
+        assert!(non_zero(&result) < non_zero(&rounded));
+        for (value, original) in result.iter().zip(rounded) {
+            assert!(value.abs() <= original.abs());
+            assert!(*value == 0 || value.signum() == original.signum());
+        }
+    }
+
+    #[test]
+    fn test_unsample_zigzag_reverts_sample_zigzag() {
+        let x: SMatrix<i32, 8, 8> = SMatrix::from_fn(|i, j| (i * 8 + j) as i32);
+        assert_eq!(x, unsample_zigzag(&sample_zigzag(&x)));
+    }
+
+    #[test]
+    fn test_dequantize_reverts_quantize_up_to_rounding() {
+        let mut x: SMatrix<f32, 8, 8> = SMatrix::from_element(-103.0);
+        let q_table = uniform_q_table(10.0);
+        quantize(&mut x, &q_table);
+        dequantize(&mut x, &q_table);
+        assert_eq!(SMatrix::<f32, 8, 8>::from_element(-100.0), x);
+    }
+
+    #[test]
+    fn test_scale_q_table_quality_50_keeps_table() {
+        let q_table = box_q_table(2.0, 3, 1.0);
+        assert_eq!(q_table, scale_q_table(&q_table, 50));
+    }
+
+    #[test]
+    fn test_scale_q_table_quality_100_is_lossless_table() {
+        let q_table = box_q_table(2.0, 3, 1.0);
+        assert_eq!(uniform_q_table(1.0), scale_q_table(&q_table, 100));
+    }
+
+    #[test]
+    fn test_scale_q_table_low_quality_is_clamped() {
+        let q_table = uniform_q_table(16.0);
+        assert_eq!(uniform_q_table(255.0), scale_q_table(&q_table, 1));
+        assert_eq!(uniform_q_table(32.0), scale_q_table(&q_table, 25));
+    }
 
     #[test]
     fn test_quantization_from_slides() {
@@ -0,0 +1,179 @@
+use nalgebra::SMatrix;
+use scoped_threadpool::Pool;
+
+use crate::bit_stream::BitStream;
+use crate::encoder::{encode_dct_to_stream, EncoderSettings};
+use crate::image::Image;
+use crate::quantization::{MAX_QUALITY, MIN_QUALITY};
+
+/// The size an encoded image should fit into.
+pub enum RateTarget {
+    /// The maximum size of the whole JPEG file in bytes.
+    Bytes(usize),
+    /// The maximum amount of bits per image pixel, for the whole JPEG file.
+    BitsPerPixel(f32),
+}
+
+impl RateTarget {
+    /// Get the maximum size in bytes for an image with the given dimensions.
+    pub fn max_bytes(&self, width: u16, height: u16) -> usize {
+        match self {
+            RateTarget::Bytes(bytes) => *bytes,
+            RateTarget::BitsPerPixel(bpp) => (*bpp * width as f32 * height as f32 / 8.0) as usize,
+        }
+    }
+}
+
+/// The result of a rate controlled encoding.
+///
+/// # Attributes
+///
+/// * `stream`: The encoded JPEG file.
+/// * `quality`: The quality (see `quantization::scale_q_table`) the file was encoded with.
+/// * `target_met`: False if even the lowest quality doesn't fit the target. In that case,
+///   `stream` contains the file encoded with the lowest quality.
+pub struct RateControlResult {
+    pub stream: BitStream,
+    pub quality: u8,
+    pub target_met: bool,
+}
+
+/// Encode the DCT output of an image with the highest quality whose encoded size fits the target.
+/// The quality is searched by bisection, re-running only quantization and entropy coding on the
+/// given DCT output for each candidate. This assumes that the encoded size grows with the quality,
+/// which holds for all but very small images.
+///
+/// # Arguments
+/// * `image`: The image the DCT output was calculated from.
+/// * `y_dct`: The DCT output of the Y channel, as returned by `parallel_dct::dct`.
+/// * `cb_dct`: The DCT output of the Cb channel.
+/// * `cr_dct`: The DCT output of the Cr channel.
+/// * `settings`: The settings to encode with. Its quantization tables are scaled by the quality.
+/// * `target`: The size to fit the encoded file into.
+/// * `pool`: The thread pool to quantize with.
+///
+/// # Example
+/// ```
+/// let (y_dct, cb_dct, cr_dct) = parallel_dct::dct(&mut image, &DCTMode::Arai, &mut pool);
+/// let result = encode_to_target(&image, &y_dct, &cb_dct, &cr_dct, &EncoderSettings::default(), &RateTarget::Bytes(50_000), &mut pool);
+/// result.stream.flush_to_file("output.jpg");
+/// ```
+pub fn encode_to_target(
+    image: &Image,
+    y_dct: &[SMatrix<f32, 8, 8>],
+    cb_dct: &[SMatrix<f32, 8, 8>],
+    cr_dct: &[SMatrix<f32, 8, 8>],
+    settings: &EncoderSettings,
+    target: &RateTarget,
+    pool: &mut Pool,
+) -> RateControlResult {
+    let max_bytes = target.max_bytes(image.width(), image.height());
+    let mut encode_with_quality = |quality: u8| {
+        encode_dct_to_stream(image, y_dct.to_vec(), cb_dct.to_vec(), cr_dct.to_vec(), &settings.with_quality(quality), pool)
+    };
+
+    let mut best: Option<(BitStream, u8)> = None;
+    let mut lower = MIN_QUALITY;
+    let mut upper = MAX_QUALITY;
+    while lower <= upper {
+        let quality = lower + (upper - lower) / 2;
+        let stream = encode_with_quality(quality);
+        if stream.data().len() <= max_bytes {
+            best = Some((stream, quality));
+            lower = quality + 1;
+        } else if quality == MIN_QUALITY {
+            break;
+        } else {
+            upper = quality - 1;
+        }
+    }
+
+    match best {
+        Some((stream, quality)) => RateControlResult {
+            stream,
+            quality,
+            target_met: true,
+        },
+        None => RateControlResult {
+            stream: encode_with_quality(MIN_QUALITY),
+            quality: MIN_QUALITY,
+            target_met: false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use scoped_threadpool::Pool;
+
+    use crate::encoder::EncoderSettings;
+    use crate::image::ColorMatrix;
+    use crate::test_utils::prepare_image;
+
+    use super::{encode_to_target, RateTarget};
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    #[test]
+    fn test_max_bytes_bits_per_pixel() {
+        assert_eq!(1000, RateTarget::BitsPerPixel(0.5).max_bytes(100, 160));
+        assert_eq!(1234, RateTarget::Bytes(1234).max_bytes(100, 160));
+    }
+
+    #[test]
+    fn test_encode_to_target_fits_target() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_80x128_color.ppm", ColorMatrix::Bt601, &mut pool);
+        let target = RateTarget::Bytes(1000);
+
+        let result = encode_to_target(
+            &image,
+            &dct.0,
+            &dct.1,
+            &dct.2,
+            &EncoderSettings::default(),
+            &target,
+            &mut pool,
+        );
+
+        assert!(result.target_met);
+        assert!(result.stream.data().len() <= 1000);
+    }
+
+    #[test]
+    fn test_encode_to_target_larger_target_gives_higher_quality() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_80x128_color.ppm", ColorMatrix::Bt601, &mut pool);
+        let settings = EncoderSettings::default();
+
+        let small = encode_to_target(&image, &dct.0, &dct.1, &dct.2, &settings, &RateTarget::Bytes(900), &mut pool);
+        let large = encode_to_target(&image, &dct.0, &dct.1, &dct.2, &settings, &RateTarget::Bytes(1200), &mut pool);
+
+        assert!(large.quality > small.quality);
+        assert!(large.stream.data().len() > small.stream.data().len());
+    }
+
+    #[test]
+    fn test_encode_to_target_unreachable_target() {
+        let mut pool = get_pool();
+        let (image, dct) = prepare_image("test/test_80x128_color.ppm", ColorMatrix::Bt601, &mut pool);
+
+        let result = encode_to_target(
+            &image,
+            &dct.0,
+            &dct.1,
+            &dct.2,
+            &EncoderSettings::default(),
+            &RateTarget::Bytes(10),
+            &mut pool,
+        );
+
+        assert!(!result.target_met);
+        assert_eq!(1, result.quality);
+    }
+}
@@ -0,0 +1,269 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::thread;
+
+use crate::utils::THREAD_COUNT;
+
+/// The filters to resize images with, from the fastest and blurriest to the slowest and sharpest.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ResizeFilter {
+    /// Averages the covered samples when downscaling, repeats samples when upscaling (nearest neighbour).
+    Box,
+    /// Linear interpolation between the two nearest samples (the triangle filter).
+    Bilinear,
+    /// Cubic interpolation of the four nearest samples with the Catmull-Rom spline.
+    Bicubic,
+    /// A windowed sinc filter over the six nearest samples, which keeps the most detail.
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// The distance from its centre at which the kernel drops to zero, in samples of the smaller of both sizes.
+    fn support(&self) -> f32 {
+        match self {
+            ResizeFilter::Box => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Bicubic => 2.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    /// The weight of a sample at the given distance from the centre.
+    fn kernel(&self, x: f32) -> f32 {
+        match self {
+            // half open, so a sample exactly between two target samples only counts for one of them
+            ResizeFilter::Box => (-0.5..0.5).contains(&x) as u8 as f32,
+            ResizeFilter::Bilinear => (1.0 - x.abs()).max(0.0),
+            ResizeFilter::Bicubic => {
+                let x = x.abs();
+                if x < 1.0 {
+                    (1.5 * x - 2.5) * x * x + 1.0
+                } else if x < 2.0 {
+                    ((-0.5 * x + 2.5) * x - 4.0) * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            ResizeFilter::Lanczos3 => {
+                if x == 0.0 {
+                    1.0
+                } else if x.abs() < 3.0 {
+                    3.0 * (PI * x).sin() * (PI * x / 3.0).sin() / (PI * PI * x * x)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ResizeFilter, String> {
+        match value {
+            "box" => Ok(ResizeFilter::Box),
+            "bilinear" => Ok(ResizeFilter::Bilinear),
+            "bicubic" => Ok(ResizeFilter::Bicubic),
+            "lanczos3" => Ok(ResizeFilter::Lanczos3),
+            _ => Err(format!("Unknown resize filter {}!", value)),
+        }
+    }
+}
+
+/// The source samples a target sample is made of.
+///
+/// # Attributes
+///
+/// * `start`: The index of the first source sample.
+/// * `weights`: The weights of the source samples from `start` on, adding up to 1.
+#[derive(Debug, PartialEq)]
+struct Contribution {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Calculate which source samples make up each target sample along one axis, and their weights.
+/// When downscaling, the kernel is stretched to cover all source samples of a target sample.
+/// At the edges, the weights of the remaining samples are scaled up to make up for the missing ones.
+fn contributions(source_length: usize, target_length: usize, filter: ResizeFilter) -> Vec<Contribution> {
+    let scale = source_length as f32 / target_length as f32;
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+    (0..target_length)
+        .map(|index| {
+            let center = (index as f32 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(source_length);
+            let weights: Vec<f32> = (start..end)
+                .map(|source| filter.kernel((source as f32 + 0.5 - center) / filter_scale))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            if sum == 0.0 {
+                // can't happen with the kernels above, but take the nearest sample rather than dividing by zero
+                return Contribution {
+                    start: (center as usize).min(source_length - 1),
+                    weights: vec![1.0],
+                };
+            }
+            Contribution {
+                start,
+                weights: weights.iter().map(|weight| weight / sum).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Resize a channel with a separable filter: first each row to the new width, then each column to the new height.
+/// Both passes are split into chunks of rows calculated in parallel.
+/// The results are rounded and clamped to the range of the source samples,
+/// as the negative lobes of the bicubic and Lanczos filters overshoot at edges.
+///
+/// # Arguments
+///
+/// * `channel`: The channel to resize, which must not be empty.
+/// * `source_width`: The amount of samples per row to resize, e.g. the image width to leave out padding.
+/// * `source_height`: The amount of rows to resize, e.g. the image height to leave out padding.
+/// * `width`: The width to resize to.
+/// * `height`: The height to resize to.
+/// * `filter`: The filter to resize with.
+///
+/// # Examples
+///```
+/// let resized = resize_channel(image.channel1(), image.width() as usize, image.height() as usize, 640, 480, ResizeFilter::Lanczos3);
+/// ```
+pub fn resize_channel(channel: &[Vec<i16>], source_width: usize, source_height: usize, width: usize, height: usize, filter: ResizeFilter) -> Vec<Vec<i16>> {
+    let channel = &channel[..source_height];
+    let horizontal = contributions(source_width, width, filter);
+    let vertical = contributions(source_height, height, filter);
+    let min = channel.iter().flat_map(|row| &row[..source_width]).min().copied().unwrap_or(0) as f32;
+    let max = channel.iter().flat_map(|row| &row[..source_width]).max().copied().unwrap_or(0) as f32;
+
+    let resized_rows: Vec<Vec<f32>> = map_in_parallel(channel, |row| {
+        horizontal
+            .iter()
+            .map(|contribution| {
+                let samples = &row[contribution.start..];
+                contribution.weights.iter().zip(samples).map(|(weight, sample)| weight * *sample as f32).sum()
+            })
+            .collect()
+    });
+    let resized_rows = &resized_rows;
+    map_in_parallel(&vertical, |contribution| {
+        let mut row = vec![0.0f32; width];
+        for (weight, source_row) in contribution.weights.iter().zip(&resized_rows[contribution.start..]) {
+            for (value, source) in row.iter_mut().zip(source_row) {
+                *value += weight * source;
+            }
+        }
+        row.iter().map(|value| value.round().clamp(min, max) as i16).collect()
+    })
+}
+
+/// Apply the function to every item, in chunks spread over one thread each, and collect the results in order.
+fn map_in_parallel<T: Sync, R: Send>(items: &[T], function: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let chunk_size = items.len() / *THREAD_COUNT + 1;
+    let function = &function;
+    thread::scope(|s| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || chunk.iter().map(function).collect::<Vec<R>>()))
+            .collect();
+        let mut result = Vec::with_capacity(items.len());
+        for handle in handles {
+            result.extend(handle.join().unwrap());
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{contributions, resize_channel, ResizeFilter};
+
+    const FILTERS: [ResizeFilter; 4] = [ResizeFilter::Box, ResizeFilter::Bilinear, ResizeFilter::Bicubic, ResizeFilter::Lanczos3];
+
+    #[test]
+    fn test_kernels() {
+        for filter in FILTERS {
+            assert_eq!(1.0, filter.kernel(0.0), "{:?}", filter);
+            assert!(filter.kernel(filter.support()).abs() < 1e-6, "{:?}", filter);
+            // interpolating filters are zero at the other samples
+            assert!(filter.kernel(1.0).abs() < 1e-6, "{:?}", filter);
+        }
+        assert_eq!(0.5, ResizeFilter::Bilinear.kernel(-0.5));
+        assert_eq!(0.5625, ResizeFilter::Bicubic.kernel(0.5));
+        assert_eq!(-0.0625, ResizeFilter::Bicubic.kernel(1.5));
+        assert!(ResizeFilter::Lanczos3.kernel(1.5) < 0.0);
+    }
+
+    #[test]
+    fn test_contributions_add_up_to_one() {
+        for filter in FILTERS {
+            for (source, target) in [(10, 3), (3, 10), (7, 7), (1, 5), (5, 1)] {
+                for contribution in contributions(source, target, filter) {
+                    let sum: f32 = contribution.weights.iter().sum();
+                    assert!((sum - 1.0).abs() < 1e-5, "{:?} {}->{}", filter, source, target);
+                    assert!(contribution.start + contribution.weights.len() <= source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_contributions_box_downscale() {
+        let contributions = contributions(6, 2, ResizeFilter::Box);
+        assert_eq!(0, contributions[0].start);
+        assert_eq!(vec![1.0 / 3.0; 3], contributions[0].weights);
+        assert_eq!(3, contributions[1].start);
+    }
+
+    #[test]
+    fn test_resize_channel_same_size() {
+        let channel = vec![vec![1, 50, -3], vec![100, 7, 30]];
+        for filter in FILTERS {
+            assert_eq!(channel, resize_channel(&channel, 3, 2, 3, 2, filter), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_resize_channel_box() {
+        let channel = vec![vec![0, 2, 4, 6], vec![8, 10, 12, 14]];
+        assert_eq!(vec![vec![5, 9]], resize_channel(&channel, 4, 2, 2, 1, ResizeFilter::Box));
+        assert_eq!(vec![vec![0, 0, 2, 2], vec![0, 0, 2, 2]], resize_channel(&channel, 2, 1, 4, 2, ResizeFilter::Box));
+    }
+
+    #[test]
+    fn test_resize_channel_bilinear_upscale() {
+        let channel = vec![vec![0, 100]];
+        assert_eq!(vec![vec![0, 25, 75, 100]], resize_channel(&channel, 2, 1, 4, 1, ResizeFilter::Bilinear));
+    }
+
+    #[test]
+    fn test_resize_channel_clamps_overshoot() {
+        // a hard edge makes the negative lobes ring
+        let channel = vec![vec![0, 0, 0, 255, 255, 255]];
+        for filter in [ResizeFilter::Bicubic, ResizeFilter::Lanczos3] {
+            let resized = resize_channel(&channel, 6, 1, 15, 1, filter);
+            assert!(resized[0].iter().all(|value| (0..=255).contains(value)), "{:?}", filter);
+            assert_eq!((0, 255), (resized[0][0], resized[0][14]));
+        }
+    }
+
+    #[test]
+    fn test_resize_channel_ignores_padding() {
+        // a padding column and a padding row
+        let channel = vec![vec![10, 10, 99], vec![10, 10, 99], vec![99, 99, 99]];
+        assert_eq!(vec![vec![10]], resize_channel(&channel, 2, 2, 1, 1, ResizeFilter::Lanczos3));
+        assert_eq!(vec![vec![10, 10]; 4], resize_channel(&channel, 2, 2, 2, 4, ResizeFilter::Bicubic));
+    }
+
+    #[test]
+    fn test_resize_filter_from_str() {
+        assert_eq!(Ok(ResizeFilter::Bicubic), ResizeFilter::from_str("bicubic"));
+        assert_eq!(Err("Unknown resize filter sinc!".to_string()), ResizeFilter::from_str("sinc"));
+    }
+}
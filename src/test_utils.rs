@@ -0,0 +1,34 @@
+use nalgebra::SMatrix;
+use scoped_threadpool::Pool;
+
+use crate::dct::DCTMode;
+use crate::image::{ColorMatrix, Image};
+use crate::parallel_dct;
+use crate::ppm_parser::read_ppm_from_file;
+
+/// The DCT blocks of the Y, Cb and Cr channel.
+pub type Dct = (
+    Vec<SMatrix<f32, 8, 8>>,
+    Vec<SMatrix<f32, 8, 8>>,
+    Vec<SMatrix<f32, 8, 8>>,
+);
+
+/// Read a PPM test image and take it through the encoder up to the DCT:
+/// convert it to YCbCr with the given matrix, downsample it to 4:2:0 and transform it with the Arai DCT.
+///
+/// # Arguments
+/// * `filename`: The PPM file to read.
+/// * `color_matrix`: The matrix to convert to YCbCr with.
+/// * `pool`: The thread pool to calculate the DCT in.
+///
+/// # Examples
+/// ```
+/// let (image, (y_dct, cb_dct, cr_dct)) = prepare_image("test/test_73x117_color.ppm", ColorMatrix::Bt601, &mut pool);
+/// ```
+pub fn prepare_image(filename: &str, color_matrix: ColorMatrix, pool: &mut Pool) -> (Image, Dct) {
+    let mut image = read_ppm_from_file(filename);
+    image.rgb_to_ycbcr_with_matrix(color_matrix);
+    image.downsample(4, 2, 0);
+    let dct = parallel_dct::dct(&mut image, &DCTMode::Arai, pool);
+    (image, dct)
+}
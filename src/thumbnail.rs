@@ -0,0 +1,272 @@
+use scoped_threadpool::Pool;
+
+use crate::dct::DCTMode;
+use crate::encoder::{encode_dct_to_stream, EncoderSettings};
+use crate::image::{create_image, Image};
+use crate::jpg_writer::{Thumbnail, MAX_SEGMENT_CONTENT_LENGTH};
+use crate::parallel_dct;
+
+/// The default length of the longer side of thumbnails.
+pub const DEFAULT_THUMBNAIL_SIZE: u16 = 160;
+
+/// How a thumbnail is stored in the JFIF header.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ThumbnailFormat {
+    /// Uncompressed RGB in the APP0 segment, limited to 255x255 pixels and one segment.
+    Rgb,
+    /// A baseline JPEG in a JFXX APP0 extension segment.
+    #[default]
+    Jpeg,
+}
+
+impl std::str::FromStr for ThumbnailFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ThumbnailFormat, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "rgb" => Ok(ThumbnailFormat::Rgb),
+            "jpeg" | "jpg" => Ok(ThumbnailFormat::Jpeg),
+            _ => Err(format!("Unknown thumbnail format {}!", value)),
+        }
+    }
+}
+
+/// Create a thumbnail of an RGB image to embed in its JFIF header.
+/// The image is downscaled by averaging the source pixels covered by each thumbnail pixel, keeping its
+/// aspect ratio, so that the longer side is at most `max_size` pixels. Images are never upscaled.
+/// JPEG thumbnails are then encoded the same way as full images, with the default settings, without comments
+/// and without a JFIF header of their own, as JFIF 1.02 requires. They are made smaller until they fit into
+/// the JFXX segment.
+///
+/// # Arguments
+/// * `image`: The RGB image to create the thumbnail of, before it is converted to YCbCr.
+/// * `format`: How to store the thumbnail.
+/// * `max_size`: The maximum length of the longer side. Thumbnails are made smaller
+///   if necessary to fit into their segment.
+/// * `pool`: The thread pool to encode JPEG thumbnails with.
+///
+/// # Examples
+/// ```
+/// let mut image = read_ppm_from_file("image.ppm");
+/// let thumbnail = create_thumbnail(&image, ThumbnailFormat::Jpeg, DEFAULT_THUMBNAIL_SIZE, &mut pool);
+/// ```
+///
+/// # Panics
+/// * If `max_size` is 0.
+/// * If the image has four channels.
+pub fn create_thumbnail(image: &Image, format: ThumbnailFormat, max_size: u16, pool: &mut Pool) -> Thumbnail {
+    if max_size == 0 {
+        panic!("Thumbnail size must be at least 1!");
+    }
+    if image.channel_count() != 3 {
+        panic!("Thumbnails can only be created from RGB images!");
+    }
+    match format {
+        ThumbnailFormat::Rgb => {
+            let mut size = max_size.min(u8::MAX as u16);
+            let mut dimensions = thumbnail_dimensions(image, size);
+            // 16 bytes of the segment are taken by the length and the JFIF header
+            while 3 * dimensions.0 as usize * dimensions.1 as usize > MAX_SEGMENT_CONTENT_LENGTH + 2 - 16 {
+                size -= 1;
+                dimensions = thumbnail_dimensions(image, size);
+            }
+            let small = downscale(image, dimensions.0, dimensions.1);
+            let mut data = Vec::with_capacity(3 * dimensions.0 as usize * dimensions.1 as usize);
+            for y in 0..dimensions.1 {
+                for x in 0..dimensions.0 {
+                    let (r, g, b) = small.pixel_at(x, y);
+                    data.extend_from_slice(&[r as u8, g as u8, b as u8]);
+                }
+            }
+            Thumbnail::Rgb {
+                width: dimensions.0 as u8,
+                height: dimensions.1 as u8,
+                data,
+            }
+        }
+        ThumbnailFormat::Jpeg => {
+            let settings = EncoderSettings {
+                comments: vec![],
+                jfif_header: None,
+                ..Default::default()
+            };
+            let mut size = max_size;
+            loop {
+                let data = encode_jpeg_thumbnail(image, size, &settings, pool);
+                // 8 bytes of the segment are taken by the length and the JFXX header
+                if data.len() <= MAX_SEGMENT_CONTENT_LENGTH + 2 - 8 {
+                    return Thumbnail::Jpeg(data);
+                }
+                // the length grows about with the area, so estimate the size that fits, but shrink by at least a pixel
+                let scale = ((MAX_SEGMENT_CONTENT_LENGTH + 2 - 8) as f32 / data.len() as f32).sqrt();
+                let (width, height) = thumbnail_dimensions(image, size);
+                let longer = width.max(height);
+                size = ((longer as f32 * scale) as u16).min(longer - 1);
+                if size == 0 {
+                    panic!("JPEG thumbnail doesn't fit into the JFXX segment!");
+                }
+            }
+        }
+    }
+}
+
+/// Downscale the image so that its longer side is at most `max_size` pixels and encode it as JPEG.
+fn encode_jpeg_thumbnail(image: &Image, max_size: u16, settings: &EncoderSettings, pool: &mut Pool) -> Vec<u8> {
+    let (width, height) = thumbnail_dimensions(image, max_size);
+    let mut small = downscale(image, width, height);
+    small.rgb_to_ycbcr();
+    small.downsample(4, 2, 0);
+    let (y_dct, cb_dct, cr_dct) = parallel_dct::dct(&mut small, &DCTMode::Arai, pool);
+    encode_dct_to_stream(&small, y_dct, cb_dct, cr_dct, settings, pool).data().clone()
+}
+
+/// Calculate the width and height of a thumbnail whose longer side is at most `max_size` pixels,
+/// keeping the aspect ratio of the image. Each side is at least one pixel long.
+fn thumbnail_dimensions(image: &Image, max_size: u16) -> (u16, u16) {
+    let (width, height) = (image.width() as u32, image.height() as u32);
+    let longer = width.max(height);
+    if longer <= max_size as u32 {
+        return (width as u16, height as u16);
+    }
+    let scale = |side: u32| ((side * max_size as u32 + longer / 2) / longer).max(1) as u16;
+    (scale(width), scale(height))
+}
+
+/// Downscale an RGB image by averaging all source pixels each target pixel covers.
+fn downscale(image: &Image, width: u16, height: u16) -> Image {
+    let (source_width, source_height) = (image.width() as usize, image.height() as usize);
+    let mut channels = [
+        vec![vec![0i16; width as usize]; height as usize],
+        vec![vec![0i16; width as usize]; height as usize],
+        vec![vec![0i16; width as usize]; height as usize],
+    ];
+    for y in 0..height as usize {
+        let top = y * source_height / height as usize;
+        let bottom = ((y + 1) * source_height / height as usize).max(top + 1);
+        for x in 0..width as usize {
+            let left = x * source_width / width as usize;
+            let right = ((x + 1) * source_width / width as usize).max(left + 1);
+            let mut sums = [0i32; 3];
+            for row in top..bottom {
+                for col in left..right {
+                    let (r, g, b) = image.pixel_at(col as u16, row as u16);
+                    sums[0] += r as i32;
+                    sums[1] += g as i32;
+                    sums[2] += b as i32;
+                }
+            }
+            let count = ((bottom - top) * (right - left)) as i32;
+            for (channel, sum) in channels.iter_mut().zip(sums) {
+                channel[y][x] = ((sum + count / 2) / count) as i16;
+            }
+        }
+    }
+    let [channel1, channel2, channel3] = channels;
+    create_image(height, width, channel1, channel2, channel3)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::available_parallelism;
+
+    use scoped_threadpool::Pool;
+
+    use crate::jpg_reader::decode_jpg;
+    use crate::jpg_writer::Thumbnail;
+    use crate::ppm_parser::read_ppm_from_file;
+
+    use super::{create_thumbnail, downscale, thumbnail_dimensions, ThumbnailFormat};
+
+    fn get_pool() -> Pool {
+        let thread_count = available_parallelism().unwrap().get();
+        Pool::new(thread_count as u32)
+    }
+
+    #[test]
+    fn test_thumbnail_dimensions() {
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        assert_eq!((46, 73), thumbnail_dimensions(&image, 73));
+        assert_eq!((1, 1), thumbnail_dimensions(&image, 1));
+        // no upscaling
+        assert_eq!((73, 117), thumbnail_dimensions(&image, 160));
+    }
+
+    #[test]
+    fn test_downscale() {
+        let image = read_ppm_from_file("test/valid_test_maxVal_15.ppm");
+        let small = downscale(&image, 2, 2);
+        assert_eq!(2, small.width());
+        assert_eq!(2, small.height());
+        let mut sums = [0i32; 3];
+        for y in 0..2 {
+            for x in 0..2 {
+                let (r, g, b) = image.pixel_at(x, y);
+                sums[0] += r as i32;
+                sums[1] += g as i32;
+                sums[2] += b as i32;
+            }
+        }
+        let expected = ((sums[0] + 2) / 4, (sums[1] + 2) / 4, (sums[2] + 2) / 4);
+        let (r, g, b) = small.pixel_at(0, 0);
+        assert_eq!(expected, (r as i32, g as i32, b as i32));
+    }
+
+    #[test]
+    fn test_create_rgb_thumbnail() {
+        let mut pool = get_pool();
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        match create_thumbnail(&image, ThumbnailFormat::Rgb, 32, &mut pool) {
+            Thumbnail::Rgb { width, height, data } => {
+                assert_eq!((20, 32), (width, height));
+                assert_eq!(3 * 20 * 32, data.len());
+            }
+            thumbnail => panic!("Unexpected thumbnail {:?}", thumbnail),
+        }
+    }
+
+    #[test]
+    fn test_create_rgb_thumbnail_fits_into_segment() {
+        let mut pool = get_pool();
+        let image = read_ppm_from_file("test/dwsample-ppm-640.ppm");
+        match create_thumbnail(&image, ThumbnailFormat::Rgb, 1000, &mut pool) {
+            Thumbnail::Rgb { data, .. } => assert!(data.len() <= 65535 - 16),
+            thumbnail => panic!("Unexpected thumbnail {:?}", thumbnail),
+        }
+    }
+
+    #[test]
+    fn test_create_jpeg_thumbnail() {
+        let mut pool = get_pool();
+        let image = read_ppm_from_file("test/test_73x117_color.ppm");
+        match create_thumbnail(&image, ThumbnailFormat::Jpeg, 32, &mut pool) {
+            Thumbnail::Jpeg(data) => {
+                // no JFIF APP0 segment directly after SOI
+                assert_eq!(&[0xff, 0xd8, 0xff, 0xdb], &data[..4]);
+                let decoded = decode_jpg(&data).unwrap();
+                assert_eq!((20, 32), (decoded.width(), decoded.height()));
+            }
+            thumbnail => panic!("Unexpected thumbnail {:?}", thumbnail),
+        }
+    }
+
+    #[test]
+    fn test_create_jpeg_thumbnail_fits_into_segment() {
+        let mut pool = get_pool();
+        let image = read_ppm_from_file("test/dwsample-ppm-640.ppm");
+        match create_thumbnail(&image, ThumbnailFormat::Jpeg, 640, &mut pool) {
+            Thumbnail::Jpeg(data) => {
+                assert!(data.len() <= 65535 - 8);
+                let decoded = decode_jpg(&data).unwrap();
+                assert!(decoded.width() < 640);
+            }
+            thumbnail => panic!("Unexpected thumbnail {:?}", thumbnail),
+        }
+    }
+
+    #[test]
+    fn test_thumbnail_format_from_str() {
+        assert_eq!(Ok(ThumbnailFormat::Rgb), "rgb".parse());
+        assert_eq!(Ok(ThumbnailFormat::Jpeg), "JPEG".parse());
+        assert!("gif".parse::<ThumbnailFormat>().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::jpg_writer::MAX_SEGMENT_CONTENT_LENGTH;
+
+/// The namespace identifying the APP1 segment holding the standard XMP packet.
+pub const XMP_IDENTIFIER: &[u8; 29] = b"http://ns.adobe.com/xap/1.0/\0";
+/// The namespace identifying the APP1 segments holding chunks of the extended XMP.
+pub const EXTENDED_XMP_IDENTIFIER: &[u8; 35] = b"http://ns.adobe.com/xmp/extension/\0";
+/// The amount of extended XMP bytes fitting into one segment, after the identifier,
+/// the GUID, the full length and the offset of the chunk.
+const EXTENDED_XMP_CHUNK_LENGTH: usize = MAX_SEGMENT_CONTENT_LENGTH - EXTENDED_XMP_IDENTIFIER.len() - 32 - 4 - 4;
+
+/// Build the contents of the APP1 segments (without marker and length) holding an XMP packet.
+///
+/// If the packet fits into one segment, that's the only one. Otherwise the packet
+/// (without its `<?xpacket?>` wrapper) is written as extended XMP, split into as many chunks
+/// as needed. Each chunk is preceded by the GUID (the MD5 digest of the extended XMP as
+/// uppercase hex digits), the full length of the extended XMP and the chunk's offset, as described
+/// in part 3 of the XMP specification. The standard packet then only references the extended XMP
+/// with an `xmpNote:HasExtendedXMP` property.
+///
+/// # Arguments
+/// * `packet`: The serialized XMP packet, e.g. the content of an .xmp sidecar file.
+///
+/// # Examples
+/// ```
+/// for segment in xmp_segments(&fs::read_to_string("image.xmp").unwrap()) {
+///     // write APP1 marker, length and segment
+/// }
+/// ```
+pub fn xmp_segments(packet: &str) -> Vec<Vec<u8>> {
+    if XMP_IDENTIFIER.len() + packet.len() <= MAX_SEGMENT_CONTENT_LENGTH {
+        return vec![[XMP_IDENTIFIER.as_slice(), packet.as_bytes()].concat()];
+    }
+
+    let extended = strip_packet_wrapper(packet).as_bytes();
+    let guid: String = md5(extended).iter().map(|byte| format!("{:02X}", byte)).collect();
+    let mut segments = vec![[XMP_IDENTIFIER.as_slice(), standard_packet(&guid).as_bytes()].concat()];
+    for (index, chunk) in extended.chunks(EXTENDED_XMP_CHUNK_LENGTH).enumerate() {
+        let offset = (index * EXTENDED_XMP_CHUNK_LENGTH) as u32;
+        segments.push(
+            [
+                EXTENDED_XMP_IDENTIFIER.as_slice(),
+                guid.as_bytes(),
+                &(extended.len() as u32).to_be_bytes(),
+                &offset.to_be_bytes(),
+                chunk,
+            ]
+            .concat(),
+        );
+    }
+    segments
+}
+
+/// Build the standard XMP packet referencing the extended XMP with the given GUID.
+fn standard_packet(guid: &str) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+         <rdf:Description rdf:about=\"\" xmlns:xmpNote=\"http://ns.adobe.com/xmp/note/\" xmpNote:HasExtendedXMP=\"{}\"/>\
+         </rdf:RDF>\
+         </x:xmpmeta>\
+         <?xpacket end=\"w\"?>",
+        guid
+    )
+}
+
+/// Remove the `<?xpacket begin ...?>` and `<?xpacket end ...?>` processing instructions around a packet,
+/// as extended XMP is stored without them.
+fn strip_packet_wrapper(packet: &str) -> &str {
+    let mut content = packet.trim();
+    if content.starts_with("<?xpacket begin") {
+        if let Some(end) = content.find("?>") {
+            content = &content[end + 2..];
+        }
+    }
+    if let Some(start) = content.rfind("<?xpacket end") {
+        content = &content[..start];
+    }
+    content.trim()
+}
+
+/// The per-round shift amounts of MD5.
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, //
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, //
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, //
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Calculate the MD5 digest of the data (RFC 1321), which XMP uses for the GUID of extended XMP.
+fn md5(data: &[u8]) -> [u8; 16] {
+    let constants: Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32).collect();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks(64) {
+        let words: Vec<u32> = block
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0u8; 16];
+    for (index, word) in state.iter().enumerate() {
+        digest[4 * index..4 * index + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jpg_writer::MAX_SEGMENT_CONTENT_LENGTH;
+
+    use super::{md5, strip_packet_wrapper, xmp_segments, EXTENDED_XMP_IDENTIFIER, XMP_IDENTIFIER};
+
+    fn to_hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_md5() {
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", to_hex(&md5(b"")));
+        assert_eq!("9e107d9d372bb6826bd81d3542a419d6", to_hex(&md5(b"The quick brown fox jumps over the lazy dog")));
+        // more than one block
+        let data = "12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!("57edf4a22be3c955ac49da2e2107b67a", to_hex(&md5(data.as_bytes())));
+    }
+
+    #[test]
+    fn test_xmp_segments_small_packet() {
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+        let segments = xmp_segments(packet);
+        assert_eq!(1, segments.len());
+        assert_eq!(XMP_IDENTIFIER.as_slice(), &segments[0][..29]);
+        assert_eq!(packet.as_bytes(), &segments[0][29..]);
+    }
+
+    #[test]
+    fn test_xmp_segments_extended() {
+        let content = format!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><!--{}--></x:xmpmeta>",
+            "metadata ".repeat(20_000)
+        );
+        let packet = format!("<?xpacket begin=\"\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n{}\n<?xpacket end=\"w\"?>", content);
+        let segments = xmp_segments(&packet);
+        assert_eq!(4, segments.len());
+        assert!(segments.iter().all(|segment| segment.len() <= MAX_SEGMENT_CONTENT_LENGTH));
+
+        let guid = to_hex(&md5(content.as_bytes())).to_uppercase();
+        let standard = String::from_utf8(segments[0][29..].to_vec()).unwrap();
+        assert!(standard.contains(&format!("xmpNote:HasExtendedXMP=\"{}\"", guid)));
+
+        let mut extended = vec![0u8; content.len()];
+        for segment in &segments[1..] {
+            assert_eq!(EXTENDED_XMP_IDENTIFIER.as_slice(), &segment[..35]);
+            assert_eq!(guid.as_bytes(), &segment[35..67]);
+            let length = u32::from_be_bytes(segment[67..71].try_into().unwrap()) as usize;
+            let offset = u32::from_be_bytes(segment[71..75].try_into().unwrap()) as usize;
+            assert_eq!(content.len(), length);
+            extended[offset..offset + segment.len() - 75].copy_from_slice(&segment[75..]);
+        }
+        assert_eq!(content.as_bytes(), extended.as_slice());
+    }
+
+    #[test]
+    fn test_strip_packet_wrapper() {
+        assert_eq!("<x/>", strip_packet_wrapper("<?xpacket begin=\"\" id=\"a\"?>\n<x/>\n<?xpacket end=\"w\"?>\n"));
+        assert_eq!("<x/>", strip_packet_wrapper("<x/>"));
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
+
+use crate::image::{create_ycbcr_420_image, Image};
+
+/// The signature at the start of every Y4M file.
+const Y4M_SIGNATURE: &[u8] = b"YUV4MPEG2 ";
+/// The marker at the start of every Y4M frame.
+const Y4M_FRAME_MARKER: &[u8] = b"FRAME";
+
+/// The layout of the planes in a raw 8 bit 4:2:0 YUV frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawYuvFormat {
+    /// The Y plane, followed by the Cb (U) plane and the Cr (V) plane.
+    I420,
+    /// The Y plane, followed by one plane of interleaved Cb (U) and Cr (V) samples.
+    Nv12,
+}
+
+impl RawYuvFormat {
+    /// The format of a raw YUV file, determined by its extension (`.yuv` and `.i420` for I420, `.nv12` for NV12).
+    /// Returns `None` for other files.
+    pub fn from_filename(filename: &str) -> Option<RawYuvFormat> {
+        let extension = filename.rsplit_once('.')?.1.to_ascii_lowercase();
+        match extension.as_str() {
+            "yuv" | "i420" => Some(RawYuvFormat::I420),
+            "nv12" => Some(RawYuvFormat::Nv12),
+            _ => None,
+        }
+    }
+}
+
+/// The range of the samples of a YUV frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorRange {
+    /// Y from 16 to 235, Cb and Cr from 16 to 240, as used by most video.
+    #[default]
+    Limited,
+    /// All samples from 0 to 255, as used by JPEG.
+    Full,
+}
+
+impl std::str::FromStr for ColorRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ColorRange, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "limited" | "tv" => Ok(ColorRange::Limited),
+            "full" | "pc" => Ok(ColorRange::Full),
+            _ => Err(format!("Unknown colour range {}!", value)),
+        }
+    }
+}
+
+/// The width and height of a raw frame, given as `<width>x<height>`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl std::str::FromStr for FrameSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<FrameSize, String> {
+        let size = value
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse::<u16>().ok()?, height.parse::<u16>().ok()?)));
+        match size {
+            Some((width, height)) if width > 0 && height > 0 => Ok(FrameSize { width, height }),
+            _ => Err(format!("Invalid frame size {}!", value)),
+        }
+    }
+}
+
+/// Read a frame of a Y4M (YUV4MPEG2) file into a YCbCr image with 4:2:0 subsampled chroma,
+/// see `read_y4m`.
+///
+/// # Arguments
+/// * `filename`: The path to the file to read.
+/// * `frame`: The index of the frame to read, starting at 0.
+///
+/// # Examples
+/// ```
+/// let image = read_y4m_from_file("video.y4m", 0);
+/// ```
+///
+/// # Panics
+/// * If the file can't be opened.
+/// * If the frame can't be read, see `read_y4m`.
+pub fn read_y4m_from_file(filename: &str, frame: usize) -> Image {
+    let file = File::open(filename).expect("Error reading Y4M file!");
+    match read_y4m(BufReader::new(file), frame) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Read a frame of a Y4M (YUV4MPEG2) file from a buffered reader, e.g. stdin, into a YCbCr image
+/// with 4:2:0 subsampled chroma. The frames before it are skipped without keeping them in memory.
+/// The samples are used as they are, without converting them to RGB and back, so they are expected
+/// to have been converted from RGB with the matrix the JPEG file is meant to be decoded with (BT.601 for JFIF).
+/// Limited range samples are expanded to full range, as indicated by the `XCOLORRANGE` parameter
+/// (limited if it is missing). The chroma siting and interlacing are ignored.
+///
+/// # Arguments
+/// * `reader`: The reader positioned at the start of the file.
+/// * `frame`: The index of the frame to read, starting at 0.
+///
+/// # Examples
+/// ```
+/// let image = read_y4m(std::io::stdin().lock(), 0);
+/// ```
+///
+/// # Errors
+/// * If the reader fails.
+/// * If the data isn't a Y4M file or is missing its width or height.
+/// * If the colour space isn't 8 bit 4:2:0 (`C420`, `C420jpeg`, `C420paldv` or `C420mpeg2`).
+/// * If the file has fewer frames or ends within the frame.
+pub fn read_y4m<R: BufRead>(mut reader: R, frame: usize) -> Result<Image, String> {
+    let header = read_line(&mut reader)?;
+    if !header.as_bytes().starts_with(Y4M_SIGNATURE) {
+        return Err("Not a Y4M file!".to_string());
+    }
+    let mut width: Option<u16> = None;
+    let mut height: Option<u16> = None;
+    let mut range = ColorRange::Limited;
+    for parameter in header.split(' ').skip(1).filter(|parameter| !parameter.is_empty()) {
+        let (tag, value) = parameter.split_at(1);
+        match tag {
+            "W" => width = value.parse().ok(),
+            "H" => height = value.parse().ok(),
+            "C" if !matches!(value, "420" | "420jpeg" | "420paldv" | "420mpeg2") => {
+                return Err(format!("Unsupported Y4M colour space {}!", value));
+            }
+            "X" => match value {
+                "COLORRANGE=FULL" => range = ColorRange::Full,
+                "COLORRANGE=LIMITED" => range = ColorRange::Limited,
+                _ => (),
+            },
+            // supported colour spaces, frame rate, interlacing, pixel aspect ratio and comments
+            _ => (),
+        }
+    }
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err("Y4M header is missing the width or height!".to_string()),
+    };
+
+    let frame_length = frame_length(width, height);
+    for _ in 0..frame {
+        skip_y4m_frame_header(&mut reader)?;
+        skip_bytes(&mut reader, frame_length, "Y4M")?;
+    }
+    skip_y4m_frame_header(&mut reader)?;
+    let frame_data = read_bytes(&mut reader, frame_length, "Y4M")?;
+    Ok(planes_to_image(&frame_data, RawYuvFormat::I420, width, height, range))
+}
+
+/// Read a frame of a raw 8 bit 4:2:0 YUV file into a YCbCr image with 4:2:0 subsampled chroma,
+/// see `read_raw_yuv`.
+///
+/// # Arguments
+/// * `filename`: The path to the file to read.
+/// * `format`: The layout of the planes.
+/// * `size`: The width and height of the frames, which the file doesn't store.
+/// * `range`: The range of the samples.
+/// * `frame`: The index of the frame to read, starting at 0.
+///
+/// # Examples
+/// ```
+/// let size = FrameSize { width: 1920, height: 1080 };
+/// let image = read_raw_yuv_from_file("video.yuv", RawYuvFormat::I420, size, ColorRange::Limited, 0);
+/// ```
+///
+/// # Panics
+/// * If the file can't be opened.
+/// * If the frame can't be read, see `read_raw_yuv`.
+pub fn read_raw_yuv_from_file(filename: &str, format: RawYuvFormat, size: FrameSize, range: ColorRange, frame: usize) -> Image {
+    let file = File::open(filename).expect("Error reading raw YUV file!");
+    match read_raw_yuv(file, format, size, range, frame) {
+        Ok(image) => image,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Read a frame of a raw 8 bit 4:2:0 YUV file, i.e. frames of planes without any header, from a reader,
+/// e.g. stdin, into a YCbCr image with 4:2:0 subsampled chroma. The frames before it are skipped
+/// without keeping them in memory. The samples are used as they are, like in `read_y4m`.
+///
+/// # Arguments
+/// * `reader`: The reader positioned at the start of the file.
+/// * `format`: The layout of the planes.
+/// * `size`: The width and height of the frames.
+/// * `range`: The range of the samples. Limited range samples are expanded to full range.
+/// * `frame`: The index of the frame to read, starting at 0.
+///
+/// # Examples
+/// ```
+/// let size = FrameSize { width: 1920, height: 1080 };
+/// let image = read_raw_yuv(std::io::stdin().lock(), RawYuvFormat::I420, size, ColorRange::Limited, 0);
+/// ```
+///
+/// # Errors
+/// * If the reader fails.
+/// * If the file has fewer frames or ends within the frame.
+pub fn read_raw_yuv<R: Read>(mut reader: R, format: RawYuvFormat, size: FrameSize, range: ColorRange, frame: usize) -> Result<Image, String> {
+    let frame_length = frame_length(size.width, size.height);
+    skip_bytes(&mut reader, frame * frame_length, "Raw YUV")?;
+    let frame_data = read_bytes(&mut reader, frame_length, "Raw YUV")?;
+    Ok(planes_to_image(&frame_data, format, size.width, size.height, range))
+}
+
+/// The amount of bytes of an 8 bit 4:2:0 frame: the Y plane and two chroma planes
+/// with half the width and height (rounded up).
+fn frame_length(width: u16, height: u16) -> usize {
+    let (chroma_width, chroma_height) = (width.div_ceil(2) as usize, height.div_ceil(2) as usize);
+    width as usize * height as usize + 2 * chroma_width * chroma_height
+}
+
+/// Read the next line, returning it without the line feed.
+///
+/// # Errors
+/// * If the reader fails.
+/// * If there is no line feed or the line isn't ASCII.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, String> {
+    let mut line = vec![];
+    reader
+        .read_until(b'\n', &mut line)
+        .map_err(|error| format!("Error reading Y4M file: {}!", error))?;
+    if line.pop() != Some(b'\n') {
+        return Err("Y4M file ends unexpectedly!".to_string());
+    }
+    // tags are split off by their first byte, which has to be a whole character
+    if !line.is_ascii() {
+        return Err("Invalid Y4M header!".to_string());
+    }
+    Ok(line.into_iter().map(char::from).collect())
+}
+
+/// Skip the header of the next frame (`FRAME` and its parameters).
+///
+/// # Errors
+/// * If the reader fails or there is no frame.
+fn skip_y4m_frame_header<R: BufRead>(reader: &mut R) -> Result<(), String> {
+    if !read_line(reader)?.as_bytes().starts_with(Y4M_FRAME_MARKER) {
+        return Err("Y4M file ends unexpectedly!".to_string());
+    }
+    Ok(())
+}
+
+/// Skip `length` bytes of a file of the format (`Y4M` or `Raw YUV`, for the error messages).
+///
+/// # Errors
+/// * If the reader fails or ends before.
+fn skip_bytes<R: Read>(reader: &mut R, length: usize, format: &str) -> Result<(), String> {
+    let skipped = io::copy(&mut reader.take(length as u64), &mut io::sink()).map_err(|error| read_error(error, format))?;
+    if skipped < length as u64 {
+        return Err(format!("{} file ends unexpectedly!", format));
+    }
+    Ok(())
+}
+
+/// Read the next `length` bytes of a file of the format, see `skip_bytes`.
+///
+/// # Errors
+/// * If the reader fails or ends before.
+fn read_bytes<R: Read>(reader: &mut R, length: usize, format: &str) -> Result<Vec<u8>, String> {
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data).map_err(|error| read_error(error, format))?;
+    Ok(data)
+}
+
+/// The message of an error while reading a file of the format, see `skip_bytes`.
+fn read_error(error: io::Error, format: &str) -> String {
+    match error.kind() {
+        ErrorKind::UnexpectedEof => format!("{} file ends unexpectedly!", format),
+        _ => format!("Error reading {} file: {}!", format, error),
+    }
+}
+
+/// Build a YCbCr image from the planes of a frame, expanding limited range samples
+/// and level shifting all of them by -128.
+fn planes_to_image(data: &[u8], format: RawYuvFormat, width: u16, height: u16, range: ColorRange) -> Image {
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let chroma_length = chroma_width * chroma_height;
+    let (luma_plane, chroma_planes) = data.split_at(width * height);
+
+    let luma = luma_plane
+        .chunks(width)
+        .map(|row| row.iter().map(|&value| to_full_range(value, false, range) - 128).collect())
+        .collect();
+    let chroma_sample = |index: usize, plane: usize| match format {
+        RawYuvFormat::I420 => chroma_planes[plane * chroma_length + index],
+        RawYuvFormat::Nv12 => chroma_planes[2 * index + plane],
+    };
+    let chroma_channel = |plane: usize| -> Vec<Vec<i16>> {
+        (0..chroma_height)
+            .map(|y| {
+                (0..chroma_width)
+                    .map(|x| to_full_range(chroma_sample(y * chroma_width + x, plane), true, range) - 128)
+                    .collect()
+            })
+            .collect()
+    };
+    create_ycbcr_420_image(height as u16, width as u16, luma, chroma_channel(0), chroma_channel(1))
+}
+
+/// Expand a limited range sample (Y from 16 to 235, Cb and Cr from 16 to 240) to 0..=255,
+/// leaving full range samples unchanged.
+fn to_full_range(value: u8, is_chroma: bool, range: ColorRange) -> i16 {
+    if range == ColorRange::Full {
+        return value as i16;
+    }
+    let expanded = if is_chroma {
+        (value as f32 - 128.0) * 255.0 / 224.0 + 128.0
+    } else {
+        (value as f32 - 16.0) * 255.0 / 219.0
+    };
+    expanded.round().clamp(0.0, 255.0) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_raw_yuv, read_raw_yuv_from_file, read_y4m, read_y4m_from_file, to_full_range, ColorRange, FrameSize, RawYuvFormat,
+    };
+
+    #[test]
+    fn test_decode_y4m_frames() {
+        // 5x3 full range frames, the second one has every sample increased by 1
+        let first = read_y4m_from_file("test/valid_test_5x3_420.y4m", 0);
+        let second = read_y4m_from_file("test/valid_test_5x3_420.y4m", 1);
+        assert_eq!((5, 3), (first.width(), first.height()));
+        assert_eq!((2, 2, true), (first.cb_downsample_factor(), first.cr_downsample_factor(), first.downsampled_vertically()));
+        assert_eq!(&vec![vec![-128, -118, -108, -98, -88], vec![-78, -68, -58, -48, -38], vec![-28, -18, -8, 2, 12]], first.channel1());
+        assert_eq!(&vec![vec![-28, -18, -8], vec![2, 12, 22]], first.channel2());
+        assert_eq!(&vec![vec![72, 62, 52], vec![42, 32, 22]], first.channel3());
+        assert_eq!(first.channel1()[2][4] + 1, second.channel1()[2][4]);
+        assert_eq!(first.channel3()[1][2] + 1, second.channel3()[1][2]);
+    }
+
+    #[test]
+    fn test_decode_y4m_missing_frame() {
+        let data = std::fs::read("test/valid_test_5x3_420.y4m").unwrap();
+        assert_eq!("Y4M file ends unexpectedly!", read_y4m(data.as_slice(), 2).unwrap_err());
+    }
+
+    #[test]
+    fn test_decode_y4m_unsupported_color_space() {
+        let error = read_y4m(&b"YUV4MPEG2 W2 H2 F25:1 C444\nFRAME\n000000000000"[..], 0).unwrap_err();
+        assert_eq!("Unsupported Y4M colour space 444!", error);
+    }
+
+    #[test]
+    fn test_decode_y4m_non_ascii_header() {
+        let error = read_y4m("YUV4MPEG2 W2 H1 \u{e9}t\u{e9}\nFRAME\n0000".as_bytes(), 0).unwrap_err();
+        assert_eq!("Invalid Y4M header!", error);
+    }
+
+    #[test]
+    fn test_decode_y4m_limited_range() {
+        let image = read_y4m(&b"YUV4MPEG2 W2 H1 F25:1 Ip A1:1\nFRAME\n\x10\xeb\x80\xf0"[..], 0).unwrap();
+        assert_eq!(&vec![vec![-128, 127]], image.channel1());
+        assert_eq!(&vec![vec![0]], image.channel2());
+        assert_eq!(&vec![vec![127]], image.channel3());
+    }
+
+    #[test]
+    fn test_decode_raw_yuv_i420_and_nv12() {
+        let size = FrameSize { width: 5, height: 3 };
+        let i420 = read_raw_yuv_from_file("test/valid_test_5x3.yuv", RawYuvFormat::I420, size, ColorRange::Full, 0);
+        let nv12 = read_raw_yuv_from_file("test/valid_test_5x3.nv12", RawYuvFormat::Nv12, size, ColorRange::Full, 0);
+        let y4m = read_y4m_from_file("test/valid_test_5x3_420.y4m", 0);
+        assert_eq!(y4m, i420);
+        assert_eq!(y4m, nv12);
+    }
+
+    #[test]
+    fn test_decode_raw_yuv_truncated() {
+        let error = read_raw_yuv(&[0; 20][..], RawYuvFormat::I420, FrameSize { width: 4, height: 4 }, ColorRange::Full, 0).unwrap_err();
+        assert_eq!("Raw YUV file ends unexpectedly!", error);
+        let error = read_raw_yuv(&[0; 20][..], RawYuvFormat::I420, FrameSize { width: 2, height: 2 }, ColorRange::Full, 3).unwrap_err();
+        assert_eq!("Raw YUV file ends unexpectedly!", error);
+    }
+
+    #[test]
+    fn test_to_full_range() {
+        assert_eq!(0, to_full_range(16, false, ColorRange::Limited));
+        assert_eq!(255, to_full_range(235, false, ColorRange::Limited));
+        assert_eq!(0, to_full_range(0, false, ColorRange::Limited));
+        assert_eq!(128, to_full_range(128, true, ColorRange::Limited));
+        assert_eq!(255, to_full_range(240, true, ColorRange::Limited));
+        assert_eq!(42, to_full_range(42, false, ColorRange::Full));
+    }
+
+    #[test]
+    fn test_raw_yuv_format_from_filename() {
+        assert_eq!(Some(RawYuvFormat::I420), RawYuvFormat::from_filename("frame.yuv"));
+        assert_eq!(Some(RawYuvFormat::I420), RawYuvFormat::from_filename("frame.I420"));
+        assert_eq!(Some(RawYuvFormat::Nv12), RawYuvFormat::from_filename("frame.nv12"));
+        assert_eq!(None, RawYuvFormat::from_filename("frame.y4m"));
+    }
+
+    #[test]
+    fn test_color_range_from_str() {
+        assert_eq!(Ok(ColorRange::Limited), "limited".parse());
+        assert_eq!(Ok(ColorRange::Full), "Full".parse());
+        assert!("studio".parse::<ColorRange>().is_err());
+    }
+
+    #[test]
+    fn test_frame_size_from_str() {
+        assert_eq!(Ok(FrameSize { width: 1920, height: 1080 }), "1920x1080".parse());
+        assert!("1920".parse::<FrameSize>().is_err());
+        assert!("0x1080".parse::<FrameSize>().is_err());
+    }
+}